@@ -0,0 +1,141 @@
+//! Program sizes, runtime and memory usage from the `--debug` output of the benchmark build of
+//! cwe_checker, which precedes the call graph in the cwe_checker output files.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::error::{Error, Result};
+
+/// Number of blocks in the block to function map, the program size in the figures
+pub const PROGRAM_SIZE_KEY: &str = "block_to_func";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchmarkRecord {
+    pub name: String,
+    /// `<key> size: <n>` as `<key>` and `<X> Blocks: <n>` as `<x>_blocks`, before the first run
+    pub sizes: BTreeMap<String, u64>,
+    pub complexity: Option<u64>,
+    /// From `[BENCH] Start` to the last `[BENCH]` entry
+    pub runtime_minutes: Option<f64>,
+    /// Peak memory from `[MAXMEM]`
+    pub memory_gib: Option<f64>,
+}
+
+impl BenchmarkRecord {
+    pub fn program_size(&self) -> Option<u64> {
+        self.sizes.get(PROGRAM_SIZE_KEY).copied()
+    }
+}
+
+/// None if the log contains no `[BENCH], Run` section, e.g. for a build without benchmarks
+pub fn parse_benchmark_log(name: &str, content: &str) -> Option<BenchmarkRecord> {
+    let (before_run, _) = content.split_once("[BENCH], Run")?;
+
+    let mut sizes = BTreeMap::new();
+    let size_regex = Regex::new(r"(\w+)\s+size:\s+(\d+)").unwrap();
+    for captures in size_regex.captures_iter(before_run) {
+        if let Ok(value) = captures[2].parse() {
+            sizes.insert(captures[1].to_string(), value);
+        }
+    }
+    let blocks_regex = Regex::new(r"(\w+)\sBlocks:\s+(\d+)").unwrap();
+    for captures in blocks_regex.captures_iter(before_run) {
+        if let Ok(value) = captures[2].parse() {
+            sizes.insert(format!("{}_blocks", captures[1].to_lowercase()), value);
+        }
+    }
+
+    // Older builds print BECNH
+    let bench_regex = Regex::new(r"\[(?:BENCH|BECNH)\],?\s+(\w+):\s+(\d+)").unwrap();
+    let complexity_regex = Regex::new(r"^Pre-SSA\|Complexity: (\d+)$").unwrap();
+    let maxmem_regex = Regex::new(r"\[MAXMEM\] (\d+)").unwrap();
+    let mut complexity = None;
+    let mut memory_gib = None;
+    let mut start = None;
+    let mut last_timestamp = None;
+    for line in content.lines() {
+        if let Some(captures) = complexity_regex.captures(line) {
+            complexity = captures[1].parse().ok();
+        }
+        if let Some(captures) = maxmem_regex.captures(line) {
+            memory_gib = captures[1].parse::<u64>().ok().map(|bytes| bytes as f64 / (1u64 << 30) as f64);
+        }
+        if let Some(captures) = bench_regex.captures(line) {
+            let Ok(timestamp) = captures[2].parse::<u64>() else {
+                continue;
+            };
+            if &captures[1] == "Start" {
+                start = Some(timestamp);
+            }
+            last_timestamp = Some(timestamp);
+        }
+    }
+    // Timestamps are in milliseconds
+    let runtime_minutes = start
+        .zip(last_timestamp)
+        .map(|(start, end)| end.saturating_sub(start) as f64 / 1000.0 / 60.0);
+
+    Some(BenchmarkRecord {
+        name: name.to_string(),
+        sizes,
+        complexity,
+        runtime_minutes,
+        memory_gib,
+    })
+}
+
+/// Files in the folder with their file name, e.g. the cwe_checker outputs written with --no-cache
+pub fn output_files(folder: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = fs::read_dir(folder)
+        .map_err(Error::io(folder))?
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path()))
+        .collect::<Vec<(String, PathBuf)>>();
+    files.sort();
+    Ok(files)
+}
+
+/// The outputs that contain benchmark output as records of the given names, sorted by name
+pub fn load_benchmark_logs(outputs: &[(String, PathBuf)]) -> Vec<BenchmarkRecord> {
+    let mut records = outputs
+        .iter()
+        // Skip binaries and other files that are not text
+        .filter_map(|(name, path)| parse_benchmark_log(name, &fs::read_to_string(path).ok()?))
+        .collect::<Vec<BenchmarkRecord>>();
+    records.sort_by(|a, b| a.name.cmp(&b.name));
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_benchmark_log() {
+        let log = "\
+[BENCH], Start: 1000
+block_to_func size: 1234
+CFG Blocks: 77
+Pre-SSA|Complexity: 42
+[BENCH], Run: 1500
+aloc_val size: 5
+[MAXMEM] 2147483648
+[BECNH], Done: 121000
+{\"metadata\":{}}
+";
+        let record = parse_benchmark_log("fp_gcc_O0", log).unwrap();
+        assert_eq!(record.program_size(), Some(1234));
+        assert_eq!(record.sizes.get("cfg_blocks"), Some(&77));
+        assert!(!record.sizes.contains_key("aloc_val"));
+        assert_eq!(record.complexity, Some(42));
+        assert_eq!(record.runtime_minutes, Some(2.0));
+        assert_eq!(record.memory_gib, Some(2.0));
+
+        assert_eq!(parse_benchmark_log("fp", "{\"metadata\":{}}"), None);
+    }
+}
@@ -0,0 +1,383 @@
+//! Builds the test subjects in every compiler, optimisation level and option combination of a
+//! manifest. The outputs are named `<subject>_<compiler>_<opt>[-<option>...]` as expected by the
+//! coordinator and process-callee-results, and the exact flags are recorded in builds.json.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    manifest::InputFile,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildOption {
+    Pie,
+    Static,
+    Lto,
+}
+
+impl BuildOption {
+    fn name(&self) -> &'static str {
+        match self {
+            BuildOption::Pie => "pie",
+            BuildOption::Static => "static",
+            BuildOption::Lto => "lto",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BuildManifest {
+    #[serde(default = "default_compilers")]
+    pub compilers: Vec<String>,
+    /// Without the dash, e.g. `O2`
+    #[serde(default = "default_opt_levels")]
+    pub opt_levels: Vec<String>,
+    /// Option combinations, `[]` is the plain build
+    #[serde(default = "default_variants")]
+    pub variants: Vec<Vec<BuildOption>>,
+    /// Added to every subject
+    #[serde(default)]
+    pub cflags: Vec<String>,
+    #[serde(default)]
+    pub ldflags: Vec<String>,
+    pub subjects: Vec<Subject>,
+}
+
+fn default_compilers() -> Vec<String> {
+    vec!["gcc".to_string(), "clang".to_string()]
+}
+
+fn default_opt_levels() -> Vec<String> {
+    ["O0", "O1", "O2", "O3", "Os"].map(str::to_string).to_vec()
+}
+
+fn default_variants() -> Vec<Vec<BuildOption>> {
+    vec![vec![]]
+}
+
+/// Either C sources compiled in one compiler call or a project with its own build command.
+/// Paths are relative to the manifest
+#[derive(Clone, Debug, Deserialize)]
+pub struct Subject {
+    /// Must not contain `_`
+    pub name: String,
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+    pub project: Option<Project>,
+    #[serde(default)]
+    pub cflags: Vec<String>,
+    /// After the sources, e.g. `-lm`
+    #[serde(default)]
+    pub ldflags: Vec<String>,
+}
+
+/// Built in place with CC, CFLAGS and LDFLAGS in the environment, e.g. `make`
+#[derive(Clone, Debug, Deserialize)]
+pub struct Project {
+    pub directory: PathBuf,
+    pub command: Vec<String>,
+    /// Run before every build, e.g. `["make", "clean"]`. Required, otherwise make would consider
+    /// the objects of the previous variant up to date
+    #[serde(default)]
+    pub clean: Vec<String>,
+    /// The built binary, relative to the directory
+    pub artifact: PathBuf,
+}
+
+/// One line of builds.json
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BuildRecord {
+    pub name: String,
+    pub subject: String,
+    pub compiler: String,
+    pub compiler_version: String,
+    pub opt: String,
+    pub options: Vec<BuildOption>,
+    pub cflags: Vec<String>,
+    pub ldflags: Vec<String>,
+    /// The compiler call, or the build command of a project
+    pub command: Vec<String>,
+    pub binary: Option<InputFile>,
+    pub error: Option<String>,
+}
+
+impl BuildManifest {
+    pub fn load(path: &Path) -> Result<BuildManifest> {
+        let content = fs::read_to_string(path).map_err(Error::io(path))?;
+        let manifest: BuildManifest = serde_json::from_str(&content).map_err(Error::json(path))?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// The output names are split at `_`, so no part may contain one. Compilers are checked by
+    /// their tag, see [`compiler_name`].
+    fn validate(&self) -> Result<()> {
+        let parts = self
+            .subjects
+            .iter()
+            .map(|subject| subject.name.clone())
+            .chain(self.compilers.iter().map(|compiler| compiler_name(compiler)))
+            .chain(self.opt_levels.iter().cloned());
+        for part in parts {
+            if part.is_empty() || part.contains(['_', '/']) {
+                return Err(Error::Config(format!("`{}` can not be part of a binary name", part)));
+            }
+        }
+        for subject in &self.subjects {
+            let valid = match &subject.project {
+                Some(project) => subject.sources.is_empty() && !project.command.is_empty(),
+                None => !subject.sources.is_empty(),
+            };
+            if !valid {
+                return Err(Error::Config(format!("Subject {} needs either sources or a project with a command", subject.name)));
+            }
+            if subject.project.as_ref().is_some_and(|project| project.clean.is_empty()) {
+                return Err(Error::Config(format!("Project of subject {} needs a clean command, e.g. [\"make\", \"clean\"]", subject.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tag of the compiler in binary names, `gcc` for `/usr/bin/gcc` and `x86-64-linux-gnu-gcc` for
+/// `x86_64-linux-gnu-gcc`, as `_` separates the parts of the names
+fn compiler_name(compiler: &str) -> String {
+    Path::new(compiler)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| compiler.to_string())
+        .replace('_', "-")
+}
+
+pub fn binary_name(subject: &str, compiler: &str, opt: &str, options: &[BuildOption]) -> String {
+    let mut name = format!("{}_{}_{}", subject, compiler_name(compiler), opt);
+    for option in options {
+        name.push('-');
+        name.push_str(option.name());
+    }
+    name
+}
+
+/// Subject, compiler and optimisation level of `<subject>_<compiler>_<opt>` names. Options stay
+/// part of the optimisation level, e.g. `O2-pie`.
+pub fn split_binary_name(name: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = name.split('_');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(subject), Some(compiler), Some(opt), None) => Some((subject, compiler, opt)),
+        _ => None,
+    }
+}
+
+/// Compiler and linker flags of an option combination. PIE is always chosen explicitly, as the
+/// default differs between distributions
+pub fn variant_flags(opt: &str, options: &[BuildOption]) -> (Vec<String>, Vec<String>) {
+    let pie = options.contains(&BuildOption::Pie);
+    let static_link = options.contains(&BuildOption::Static);
+    let mut cflags = vec![format!("-{}", opt), if pie { "-fPIE" } else { "-fno-pie" }.to_string()];
+    let mut ldflags = match (pie, static_link) {
+        (true, true) => vec!["-static-pie"],
+        (true, false) => vec!["-pie"],
+        (false, true) => vec!["-no-pie", "-static"],
+        (false, false) => vec!["-no-pie"],
+    }
+    .into_iter()
+    .map(str::to_string)
+    .collect::<Vec<String>>();
+    if options.contains(&BuildOption::Lto) {
+        cflags.push("-flto".to_string());
+        ldflags.push("-flto".to_string());
+    }
+    (cflags, ldflags)
+}
+
+/// First line of `<compiler> --version`, None if the compiler is not installed
+fn compiler_version(compiler: &str) -> Option<String> {
+    let output = Command::new(compiler).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).lines().next().map(str::to_string)
+}
+
+/// Runs the command and returns the end of stderr if it fails
+fn run_command(mut command: Command, program: &str) -> std::result::Result<(), String> {
+    let output = command.output().map_err(|err| format!("could not start {}: {}", program, err))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines = stderr.lines().collect::<Vec<&str>>();
+    Err(format!("{} failed with {}: {}", program, output.status, lines[lines.len().saturating_sub(5)..].join("\n")))
+}
+
+fn command_line(program: &str, args: &[String]) -> Vec<String> {
+    std::iter::once(program.to_string()).chain(args.iter().cloned()).collect()
+}
+
+/// Builds every variant of every subject into the output folder and writes builds.json there.
+/// Failed builds are recorded with their error, compilers that are not installed are skipped.
+pub fn build_subjects(manifest: &BuildManifest, base: &Path, output: &Path) -> Result<Vec<BuildRecord>> {
+    fs::create_dir_all(output).map_err(Error::io(output))?;
+    let output = fs::canonicalize(output).map_err(Error::io(output))?;
+    let mut records = Vec::new();
+    for compiler in &manifest.compilers {
+        let Some(version) = compiler_version(compiler) else {
+            warn!("{} is not installed, skipping its builds", compiler);
+            continue;
+        };
+        for subject in &manifest.subjects {
+            for opt in &manifest.opt_levels {
+                for options in &manifest.variants {
+                    let name = binary_name(&subject.name, compiler, opt, options);
+                    let (mut cflags, mut ldflags) = variant_flags(opt, options);
+                    cflags.extend(manifest.cflags.iter().chain(&subject.cflags).cloned());
+                    ldflags.extend(manifest.ldflags.iter().chain(&subject.ldflags).cloned());
+                    let binary = output.join(&name);
+                    info!("Building {}", name);
+
+                    let (command, result) = match &subject.project {
+                        Some(project) => build_project(project, base, compiler, &cflags, &ldflags, &binary),
+                        None => {
+                            let mut args = cflags.clone();
+                            args.push("-o".to_string());
+                            args.push(binary.display().to_string());
+                            args.extend(subject.sources.iter().map(|source| base.join(source).display().to_string()));
+                            args.extend(ldflags.iter().cloned());
+                            let mut command = Command::new(compiler);
+                            command.args(&args);
+                            (command_line(compiler, &args), run_command(command, compiler))
+                        }
+                    };
+                    let (binary, error) = match result {
+                        Ok(()) => (Some(InputFile::new(&binary)?), None),
+                        Err(err) => {
+                            warn!("Building {} failed: {}", name, err);
+                            (None, Some(err))
+                        }
+                    };
+                    records.push(BuildRecord {
+                        name,
+                        subject: subject.name.clone(),
+                        compiler: compiler.clone(),
+                        compiler_version: version.clone(),
+                        opt: opt.clone(),
+                        options: options.clone(),
+                        cflags,
+                        ldflags,
+                        command,
+                        binary,
+                        error,
+                    });
+                }
+            }
+        }
+    }
+    let path = output.join("builds.json");
+    fs::write(&path, serde_json::to_string_pretty(&records).unwrap()).map_err(Error::io(&path))?;
+    Ok(records)
+}
+
+fn build_project(
+    project: &Project,
+    base: &Path,
+    compiler: &str,
+    cflags: &[String],
+    ldflags: &[String],
+    binary: &Path,
+) -> (Vec<String>, std::result::Result<(), String>) {
+    let directory = base.join(&project.directory);
+    let record = command_line(&project.command[0], &project.command[1..]);
+    let command = |program: &[String]| {
+        let mut command = Command::new(&program[0]);
+        command
+            .args(&program[1..])
+            .current_dir(&directory)
+            .env("CC", compiler)
+            .env("CFLAGS", cflags.join(" "))
+            .env("LDFLAGS", ldflags.join(" "));
+        command
+    };
+    let artifact = directory.join(&project.artifact);
+    let result = (|| {
+        run_command(command(&project.clean), &project.clean[0])?;
+        // A clean command that misses the artifact must not let the previous variant pass as this one
+        let _ignore = fs::remove_file(&artifact);
+        run_command(command(&project.command), &project.command[0])?;
+        fs::copy(&artifact, binary).map_err(|err| format!("could not copy {}: {}", artifact.display(), err))?;
+        Ok(())
+    })();
+    (record, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant_names_and_flags() {
+        let options = [BuildOption::Pie, BuildOption::Lto];
+        assert_eq!(binary_name("fp", "/usr/bin/clang", "O2", &options), "fp_clang_O2-pie-lto");
+        assert_eq!(binary_name("fp", "gcc", "Os", &[]), "fp_gcc_Os");
+        assert_eq!(binary_name("fp", "/usr/bin/x86_64-linux-gnu-gcc", "O0", &[]), "fp_x86-64-linux-gnu-gcc_O0");
+        assert_eq!(split_binary_name("fp_clang_O2-pie-lto"), Some(("fp", "clang", "O2-pie-lto")));
+        assert_eq!(split_binary_name("fp_clang"), None);
+        assert_eq!(split_binary_name("fp_1_clang_O2"), None);
+        assert_eq!(
+            variant_flags("O2", &options),
+            (vec!["-O2".to_string(), "-fPIE".to_string(), "-flto".to_string()], vec!["-pie".to_string(), "-flto".to_string()])
+        );
+        assert_eq!(variant_flags("O0", &[BuildOption::Static]).1, ["-no-pie", "-static"]);
+
+        let mut manifest: BuildManifest = serde_json::from_str(r#"{"subjects": [{"name": "fp_1", "sources": ["fp.c"]}]}"#).unwrap();
+        assert_eq!(manifest.opt_levels.len(), 5);
+        assert!(manifest.validate().is_err());
+        manifest.subjects[0].name = "fp".to_string();
+        manifest.compilers = vec!["x86_64-linux-gnu-gcc".to_string()];
+        assert!(manifest.validate().is_ok());
+
+        let project = r#"{"subjects": [{"name": "fp", "project": {"directory": "fp", "command": ["make"], "artifact": "fp"}}]}"#;
+        let mut manifest: BuildManifest = serde_json::from_str(project).unwrap();
+        assert!(manifest.validate().is_err());
+        manifest.subjects[0].project.as_mut().unwrap().clean = vec!["make".to_string(), "clean".to_string()];
+        assert!(manifest.validate().is_ok());
+    }
+
+    #[test]
+    fn test_build_subjects() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("fp.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::write(dir.path().join("broken.c"), "int main(void) { return }\n").unwrap();
+        let manifest: BuildManifest = serde_json::from_str(
+            r#"{
+                "compilers": ["cc", "not-a-compiler"],
+                "opt_levels": ["O0"],
+                "variants": [[], ["pie"]],
+                "subjects": [{"name": "fp", "sources": ["fp.c"]}, {"name": "broken", "sources": ["broken.c"]}]
+            }"#,
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+        let output = dir.path().join("bins");
+
+        // The missing compiler is skipped, the broken subject is recorded with its error
+        let records = build_subjects(&manifest, dir.path(), &output).unwrap();
+        let names = records.iter().map(|record| record.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, ["fp_cc_O0", "fp_cc_O0-pie", "broken_cc_O0", "broken_cc_O0-pie"]);
+        assert!(records[..2].iter().all(|record| record.binary.is_some() && record.error.is_none()));
+        assert!(records[2..].iter().all(|record| record.binary.is_none() && record.error.is_some()));
+        assert_eq!(records[1].ldflags, ["-pie"]);
+        assert!(output.join("fp_cc_O0-pie").is_file());
+        assert!(!output.join("broken_cc_O0").exists());
+
+        let stored: Vec<BuildRecord> = serde_json::from_str(&fs::read_to_string(output.join("builds.json")).unwrap()).unwrap();
+        assert_eq!(stored.len(), 4);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+pub fn sha256_of_file(path: &Path) -> Result<String> {
+    let data = fs::read(path).map_err(Error::io(path))?;
+    Ok(to_hex(&Sha256::digest(&data)))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheMode {
+    Use,
+    /// Neither read nor write the cache
+    Disabled,
+    /// Run the tools again and replace the cached artifacts
+    Refresh,
+}
+
+/// Everything an artifact depends on
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub binary_sha256: String,
+    pub tool: String,
+    pub tool_version: String,
+    pub args: Vec<String>,
+    /// File name of the artifact within the cache entry
+    pub artifact: String,
+    /// File name of the binary, not part of the hash. Empty in entries of older versions
+    #[serde(default)]
+    pub binary_name: String,
+}
+
+impl CacheKey {
+    pub fn new(binary: &Path, tool: &str, tool_version: Option<String>, args: &[&str], artifact: &str) -> Result<CacheKey> {
+        Ok(CacheKey {
+            binary_sha256: sha256_of_file(binary)?,
+            tool: tool.to_string(),
+            tool_version: tool_version.unwrap_or_else(|| "unknown".to_string()),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            artifact: artifact.to_string(),
+            binary_name: binary.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        })
+    }
+
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [&self.binary_sha256, &self.tool, &self.tool_version, &self.artifact].into_iter().chain(&self.args) {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        to_hex(&hasher.finalize())
+    }
+}
+
+/// Written after the artifact is complete, entries without it are incomplete
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: CacheKey,
+    created_at: DateTime<Local>,
+    last_used_at: DateTime<Local>,
+}
+
+const ENTRY_FILE: &str = "entry.json";
+
+/// last_used_at is only rewritten once it is older, so reads do not write on every hit
+fn last_used_resolution() -> Duration {
+    Duration::hours(1)
+}
+
+/// Artifacts of cwe_checker and valgrind runs in `<root>/<key hash>/`
+#[derive(Clone)]
+pub struct ArtifactCache {
+    root: PathBuf,
+    mode: CacheMode,
+}
+
+impl ArtifactCache {
+    pub fn new(root: PathBuf, mode: CacheMode) -> ArtifactCache {
+        ArtifactCache { root, mode }
+    }
+
+    fn entry_dir(&self, key: &CacheKey) -> PathBuf {
+        self.root.join(key.hash())
+    }
+
+    fn read_entry(dir: &Path) -> Option<CacheEntry> {
+        serde_json::from_str(&fs::read_to_string(dir.join(ENTRY_FILE)).ok()?).ok()
+    }
+
+    fn write_entry(dir: &Path, entry: &CacheEntry) -> Result<()> {
+        let path = dir.join(ENTRY_FILE);
+        fs::write(&path, serde_json::to_string_pretty(entry).unwrap()).map_err(Error::io(path))
+    }
+
+    /// The cached artifact if there is a complete entry for the key
+    pub fn get(&self, key: &CacheKey) -> Option<PathBuf> {
+        if self.mode != CacheMode::Use {
+            return None;
+        }
+        let dir = self.entry_dir(key);
+        let mut entry = Self::read_entry(&dir)?;
+        let artifact = dir.join(&key.artifact);
+        if !artifact.exists() {
+            return None;
+        }
+        let now = Local::now();
+        if now - entry.last_used_at > last_used_resolution() {
+            entry.last_used_at = now;
+            if let Err(err) = Self::write_entry(&dir, &entry) {
+                warn!("Could not update the cache entry: {}", err);
+            }
+        }
+        info!("Using cached {} artifact {}", key.tool, artifact.display());
+        Some(artifact)
+    }
+
+    /// Where the tool should write the artifact, None if the cache is disabled
+    pub fn put_path(&self, key: &CacheKey) -> Result<Option<PathBuf>> {
+        if self.mode == CacheMode::Disabled {
+            return Ok(None);
+        }
+        let dir = self.entry_dir(key);
+        // Drop a previous entry, it is incomplete until commit
+        let _ignore = fs::remove_file(dir.join(ENTRY_FILE));
+        fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+        Ok(Some(dir.join(&key.artifact)))
+    }
+
+    /// Marks the artifact written to put_path as complete
+    pub fn commit(&self, key: &CacheKey) -> Result<()> {
+        if self.mode == CacheMode::Disabled {
+            return Ok(());
+        }
+        let now = Local::now();
+        Self::write_entry(
+            &self.entry_dir(key),
+            &CacheEntry {
+                key: key.clone(),
+                created_at: now,
+                last_used_at: now,
+            },
+        )?;
+        debug!("Cached {} artifact under {}", key.tool, key.hash());
+        Ok(())
+    }
+
+    /// Keys and artifacts of the complete entries of the tool, in any mode
+    pub fn artifacts(&self, tool: &str) -> Vec<(CacheKey, PathBuf)> {
+        let Ok(dirs) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        let mut artifacts = dirs
+            .flatten()
+            .filter_map(|dir| {
+                let entry = Self::read_entry(&dir.path())?;
+                let artifact = dir.path().join(&entry.key.artifact);
+                (entry.key.tool == tool && artifact.exists()).then_some((entry.key, artifact))
+            })
+            .collect::<Vec<(CacheKey, PathBuf)>>();
+        artifacts.sort_by(|a, b| a.1.cmp(&b.1));
+        artifacts
+    }
+
+    /// Latest modification of the entry directory or a file in it
+    fn last_modified(dir: &Path) -> Option<DateTime<Local>> {
+        let files = fs::read_dir(dir).ok()?.flatten().filter_map(|file| file.metadata().ok());
+        std::iter::once(fs::metadata(dir).ok()?)
+            .chain(files)
+            .filter_map(|metadata| metadata.modified().ok())
+            .max()
+            .map(DateTime::from)
+    }
+
+    /// Removes entries not used within max_age, returns the number of removed entries.
+    /// Incomplete entries are removed once they were not written to within max_age, other
+    /// processes sharing the cache may still be running the tool
+    pub fn gc(&self, max_age: Duration) -> Result<usize> {
+        let Ok(dirs) = fs::read_dir(&self.root) else {
+            return Ok(0);
+        };
+        let oldest = Local::now() - max_age;
+        let mut removed = 0;
+        for dir in dirs.flatten() {
+            let path = dir.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let keep = match Self::read_entry(&path) {
+                Some(entry) => entry.last_used_at >= oldest && path.join(&entry.key.artifact).exists(),
+                None => Self::last_modified(&path).is_some_and(|modified| modified >= oldest),
+            };
+            if !keep {
+                info!("Removing cache entry {}", path.display());
+                fs::remove_dir_all(&path).map_err(Error::io(&path))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("binary");
+        fs::write(&binary, "binary").unwrap();
+        let root = dir.path().join("cache");
+        let cache = ArtifactCache::new(root.clone(), CacheMode::Use);
+        let key = CacheKey::new(&binary, "valgrind", Some("3.24".to_string()), &["--tool=callgrind"], "callgrind.out").unwrap();
+
+        assert_eq!(cache.get(&key), None);
+        let artifact = cache.put_path(&key).unwrap().unwrap();
+        fs::write(&artifact, "calls").unwrap();
+        // Not complete before the commit
+        assert_eq!(cache.get(&key), None);
+        cache.commit(&key).unwrap();
+        assert_eq!(cache.get(&key), Some(artifact.clone()));
+
+        // A recent entry is read without rewriting it
+        let entry_file = cache.entry_dir(&key).join(ENTRY_FILE);
+        let entry = fs::read_to_string(&entry_file).unwrap();
+        cache.get(&key).unwrap();
+        assert_eq!(fs::read_to_string(&entry_file).unwrap(), entry);
+
+        let refresh = ArtifactCache::new(root.clone(), CacheMode::Refresh);
+        assert_eq!(refresh.get(&key), None);
+        assert_eq!(refresh.put_path(&key).unwrap(), Some(artifact.clone()));
+        assert_eq!(cache.get(&key), None);
+        refresh.commit(&key).unwrap();
+        assert_eq!(cache.get(&key), Some(artifact));
+        assert_eq!(ArtifactCache::new(root.clone(), CacheMode::Disabled).put_path(&key).unwrap(), None);
+
+        // An incomplete entry untouched and one unused for ten days are removed, a running one is kept
+        let running = CacheKey { tool_version: "3.26".to_string(), ..key.clone() };
+        fs::write(cache.put_path(&running).unwrap().unwrap(), "calls").unwrap();
+        let incomplete = CacheKey { tool_version: "3.25".to_string(), ..key.clone() };
+        let ten_days_ago = Local::now() - Duration::days(10);
+        let incomplete_artifact = cache.put_path(&incomplete).unwrap().unwrap();
+        fs::write(&incomplete_artifact, "calls").unwrap();
+        for path in [&incomplete_artifact, &cache.entry_dir(&incomplete)] {
+            fs::File::open(path).unwrap().set_modified(ten_days_ago.into()).unwrap();
+        }
+        let old = CacheKey { tool_version: "3.23".to_string(), ..key.clone() };
+        fs::write(cache.put_path(&old).unwrap().unwrap(), "calls").unwrap();
+        ArtifactCache::write_entry(&cache.entry_dir(&old), &CacheEntry { key: old.clone(), created_at: ten_days_ago, last_used_at: ten_days_ago }).unwrap();
+        assert_eq!(cache.artifacts("valgrind").len(), 2);
+        assert_eq!(cache.artifacts("valgrind")[0].0.binary_name, "binary");
+        assert_eq!(cache.gc(Duration::days(5)).unwrap(), 2);
+        assert!(cache.get(&key).is_some());
+        assert!(!cache.entry_dir(&incomplete).exists() && !cache.entry_dir(&old).exists());
+        assert!(cache.entry_dir(&running).exists());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    cwe_checker::CweCheckerResult,
+    elf::{ElfInfo, SymbolSource},
+    valgrind::{RealCall, ValgrindResult},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ObservedCallKind {
+    /// Callsite and target within the binary
+    Internal,
+    /// The jump of a PLT stub into the imported function, an indirection of the dynamic linker
+    PltStub { import: String },
+    /// Call into another object, with the public name of the target
+    CrossObject { symbol: String },
+    /// Call into another object without a usable target name, e.g. from a ptrace trace
+    Unknown,
+}
+
+/// Parts of glibc's IFUNC implementation names naming the CPU variant, e.g. `__memcpy_avx_unaligned_erms`
+const IFUNC_VARIANTS: [&str; 14] = [
+    "sse2", "sse3", "ssse3", "sse4", "avx", "avx2", "avx512", "evex", "evex512", "erms", "unaligned", "aligned", "rtm", "generic",
+];
+
+/// Maps IFUNC implementations and internal aliases back to the public symbol, e.g.
+/// `__memcpy_avx_unaligned_erms` and `memcpy@@GLIBC_2.14` to `memcpy`, `_IO_puts` to `puts`.
+/// Variants are only stripped from internal names with a leading `_`, so `read_aligned` is kept.
+/// `__libc_*` names are kept, many of them have no public alias, e.g. `__libc_start_main`
+pub fn public_symbol_name(name: &str) -> String {
+    let versionless = name.split('@').next().unwrap_or(name);
+    let mut name = versionless.trim_start_matches('_');
+    let is_internal = name.len() != versionless.len();
+    if let Some(stripped) = name.strip_prefix("GI_") {
+        if stripped.trim_start_matches('_').starts_with("libc_") {
+            return stripped.to_string();
+        }
+        name = stripped.trim_start_matches('_');
+    }
+    if name.starts_with("libc_") {
+        return versionless.to_string();
+    }
+    if let Some(stripped) = name.strip_prefix("IO_") {
+        name = stripped.trim_start_matches('_');
+    }
+    let mut parts = name.split('_');
+    let first = parts.next().unwrap_or_default();
+    let rest = parts.take_while(|part| !is_internal || !IFUNC_VARIANTS.contains(part));
+    let public = std::iter::once(first).chain(rest).collect::<Vec<&str>>().join("_");
+    if public.is_empty() {
+        versionless.to_string()
+    } else {
+        public
+    }
+}
+
+pub fn classify(call: &RealCall, callsite: u64, cwe_checker: &CweCheckerResult, real: &ValgrindResult, elf: &ElfInfo) -> ObservedCallKind {
+    if let Some((symbol, _)) = elf.from_cwe_address(callsite, &cwe_checker.metadata).and_then(|callsite| elf.lookup(callsite)) {
+        if symbol.source == SymbolSource::Plt {
+            return ObservedCallKind::PltStub {
+                import: symbol.name.trim_end_matches("@plt").to_string(),
+            };
+        }
+    }
+    if !call.does_jump_object_file {
+        return ObservedCallKind::Internal;
+    }
+    // Without callgrind names the target number is returned
+    let target_name = real.get_target_function_of_call(call);
+    if real.valgrind_name_cache.name_cache.is_empty() || target_name.parse::<u64>().is_ok() {
+        return ObservedCallKind::Unknown;
+    }
+    ObservedCallKind::CrossObject {
+        symbol: public_symbol_name(&target_name),
+    }
+}
+
+/// Names a static target of cwe_checker, PLT stubs are named after their import
+fn static_target_name(target: u64, cwe_checker: &CweCheckerResult, elf: &ElfInfo) -> Option<String> {
+    if let Some(function) = cwe_checker.metadata.functions.iter().find(|func| func.address == target) {
+        return Some(function.name.trim_end_matches("@plt").to_string());
+    }
+    let (symbol, offset) = elf.lookup(elf.from_cwe_address(target, &cwe_checker.metadata)?)?;
+    if offset != 0 {
+        return None;
+    }
+    Some(symbol.name.trim_end_matches("@plt").to_string())
+}
+
+/// Checks a call into another object against the extern symbols the callsite may call
+pub fn has_extern_target(callsite: u64, symbol: &str, cwe_checker: &CweCheckerResult, elf: &ElfInfo) -> bool {
+    let Some(callsite) = cwe_checker.get_call_site(callsite) else {
+        return false;
+    };
+    callsite
+        .targets
+        .iter()
+        .filter_map(|target| static_target_name(*target, cwe_checker, elf))
+        .any(|name| public_symbol_name(&name) == symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Function, Metadata},
+        elf::{ElfSection, ElfSymbol},
+        valgrind::ValgrindNameCache,
+    };
+
+    #[test]
+    fn test_public_symbol_name() {
+        assert_eq!(public_symbol_name("__memcpy_avx_unaligned_erms"), "memcpy");
+        assert_eq!(public_symbol_name("__strlen_avx2"), "strlen");
+        assert_eq!(public_symbol_name("memcpy@@GLIBC_2.14"), "memcpy");
+        assert_eq!(public_symbol_name("__GI_memcpy"), "memcpy");
+        // glibc's own functions keep their name instead of looking like functions of the binary
+        assert_eq!(public_symbol_name("__libc_start_main"), "__libc_start_main");
+        assert_eq!(public_symbol_name("__libc_csu_init"), "__libc_csu_init");
+        assert_eq!(public_symbol_name("__libc_malloc"), "__libc_malloc");
+        assert_eq!(public_symbol_name("__GI___libc_free"), "__libc_free");
+        assert_eq!(public_symbol_name("_IO_puts"), "puts");
+        assert_eq!(public_symbol_name("pthread_create"), "pthread_create");
+        assert_eq!(public_symbol_name("__generic_handler_sse2"), "generic_handler");
+        assert_eq!(public_symbol_name("generic_handler"), "generic_handler");
+        assert_eq!(public_symbol_name("read_aligned"), "read_aligned");
+        assert_eq!(public_symbol_name("__"), "__");
+    }
+
+    #[test]
+    fn test_classify() {
+        let symbol = |name: &str, address: u64, size: u64, source: SymbolSource| ElfSymbol {
+            name: name.to_string(),
+            address,
+            size,
+            source,
+            is_exported: false,
+            is_ifunc: false,
+        };
+        let section = |name: &str, address: u64, size: u64| ElfSection {
+            name: name.to_string(),
+            address,
+            size,
+        };
+        let elf = ElfInfo {
+            is_pie: false,
+            symbols: vec![symbol("main", 0x1000, 0x100, SymbolSource::Symtab), symbol("puts@plt", 0x2000, 0x10, SymbolSource::Plt)],
+            sections: vec![section(".text", 0x1000, 0x100), section(".plt", 0x2000, 0x10)],
+            plt_entries: vec![],
+            got_imports: HashMap::new(),
+            data_pointers: HashMap::new(),
+        };
+        // cwe_checker calls the PLT stub of puts from 0x1010
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1010, 0x1020],
+                functions: vec![Function {
+                    name: "main".to_string(),
+                    address: 0x1000,
+                }],
+            },
+            calls: vec![Call {
+                from_instr: 0x1010,
+                to_instr: Some(0x2000),
+                is_indirect: true,
+            }],
+        });
+        let call = |from_instr: u64, to_instr: u64, does_jump_object_file: bool| RealCall {
+            from_instr,
+            to_instr,
+            in_fn: 0,
+            target_fn: 1,
+            does_jump_object_file,
+            call_count: 1,
+            inclusive_cost: 0,
+        };
+        let mut real = ValgrindResult {
+            valgrind_name_cache: ValgrindNameCache {
+                name_cache: HashMap::from([(0, "main".to_string()), (1, "_IO_puts".to_string())]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let plt_jump = call(0x2004, 0x7f0000001000, true);
+        assert_eq!(classify(&plt_jump, 0x2004, &cwe_checker, &real, &elf), ObservedCallKind::PltStub { import: "puts".to_string() });
+        let into_libc = call(0x1010, 0x7f0000001000, true);
+        assert_eq!(classify(&into_libc, 0x1010, &cwe_checker, &real, &elf), ObservedCallKind::CrossObject { symbol: "puts".to_string() });
+        assert_eq!(classify(&call(0x1020, 0x1050, false), 0x1020, &cwe_checker, &real, &elf), ObservedCallKind::Internal);
+
+        // The PLT stub stands for the extern symbol, other names and unknown callsites do not match
+        assert!(has_extern_target(0x1010, "puts", &cwe_checker, &elf));
+        assert!(!has_extern_target(0x1010, "free", &cwe_checker, &elf));
+        assert!(!has_extern_target(0x1020, "puts", &cwe_checker, &elf));
+
+        real.valgrind_name_cache = ValgrindNameCache::new();
+        assert_eq!(classify(&into_libc, 0x1010, &cwe_checker, &real, &elf), ObservedCallKind::Unknown);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    cwe_checker::CweCheckerResult,
+    soundness_test::offset_based_on_main,
+    valgrind::ValgrindResult,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallSiteChange {
+    Unchanged,
+    /// The old result had no targets for the callsite, the new one has
+    NewlyResolved,
+    /// The old result had targets for the callsite, the new one has none
+    NewlyUnresolved,
+    TargetsChanged,
+}
+
+#[derive(Clone, Debug)]
+pub struct CallSiteDiff {
+    pub callsite: u64,
+    pub added_targets: Vec<u64>,
+    pub removed_targets: Vec<u64>,
+    pub change: CallSiteChange,
+}
+
+/// An observed edge whose soundness differs between the old and the new result
+#[derive(Clone, Debug)]
+pub struct SoundnessChange {
+    pub callsite: u64,
+    pub target: u64,
+    /// true if only the new result contains the edge, false if only the old one does
+    pub gained: bool,
+}
+
+pub struct CallGraphDiff {
+    pub callsites: Vec<CallSiteDiff>,
+    pub soundness_changes: Vec<SoundnessChange>,
+}
+
+fn targets_of(result: &CweCheckerResult, callsite: u64) -> HashSet<u64> {
+    result
+        .get_call_site(callsite)
+        .map(|callsite| callsite.targets)
+        .unwrap_or_default()
+}
+
+pub fn diff_call_graphs(old: &CweCheckerResult, new: &CweCheckerResult) -> Vec<CallSiteDiff> {
+    let indirect_call_sites = old
+        .metadata
+        .indirect_call_sites
+        .iter()
+        .chain(new.metadata.indirect_call_sites.iter())
+        .cloned()
+        .collect::<BTreeSet<u64>>();
+
+    let mut diffs = vec![];
+    for callsite in indirect_call_sites {
+        let old_targets = targets_of(old, callsite);
+        let new_targets = targets_of(new, callsite);
+
+        let mut added_targets = new_targets.difference(&old_targets).cloned().collect::<Vec<u64>>();
+        let mut removed_targets = old_targets.difference(&new_targets).cloned().collect::<Vec<u64>>();
+        added_targets.sort();
+        removed_targets.sort();
+
+        let change = if old_targets.is_empty() && !new_targets.is_empty() {
+            CallSiteChange::NewlyResolved
+        } else if !old_targets.is_empty() && new_targets.is_empty() {
+            CallSiteChange::NewlyUnresolved
+        } else if added_targets.is_empty() && removed_targets.is_empty() {
+            CallSiteChange::Unchanged
+        } else {
+            CallSiteChange::TargetsChanged
+        };
+
+        diffs.push(CallSiteDiff {
+            callsite,
+            added_targets,
+            removed_targets,
+            change,
+        });
+    }
+    diffs
+}
+
+/// Checks every observed indirect call against both results and keeps the edges only one of them covers
+pub fn diff_soundness(old: &CweCheckerResult, new: &CweCheckerResult, real: &ValgrindResult) -> Vec<SoundnessChange> {
+    // Both results are for the same binary, so the offset is computed once
+    let offset = offset_based_on_main(new, real);
+
+    let mut seen = HashSet::new();
+    let mut changes = vec![];
+    for call in &real.calls {
+        if call.does_jump_object_file {
+            continue;
+        }
+        let callsite = ((call.from_instr as i64) - offset) as u64;
+        let target = ((call.to_instr as i64) - offset) as u64;
+        if !old.metadata.indirect_call_sites.contains(&callsite) && !new.metadata.indirect_call_sites.contains(&callsite) {
+            continue;
+        }
+        if !seen.insert((callsite, target)) {
+            continue;
+        }
+
+        let in_old = targets_of(old, callsite).contains(&target);
+        let in_new = targets_of(new, callsite).contains(&target);
+        if in_old != in_new {
+            changes.push(SoundnessChange {
+                callsite,
+                target,
+                gained: in_new,
+            });
+        }
+    }
+    changes.sort_by_key(|change| (change.callsite, change.target));
+    changes
+}
+
+pub fn diff(old: &CweCheckerResult, new: &CweCheckerResult, real: Option<&ValgrindResult>) -> CallGraphDiff {
+    CallGraphDiff {
+        callsites: diff_call_graphs(old, new),
+        soundness_changes: real.map(|real| diff_soundness(old, new, real)).unwrap_or_default(),
+    }
+}
+
+fn format_targets(targets: &[u64]) -> String {
+    targets
+        .iter()
+        .map(|target| format!("{:#x}", target))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+pub fn render_diff(diff: &CallGraphDiff, with_soundness: bool) -> String {
+    let mut out = String::new();
+    let count = |change: CallSiteChange| diff.callsites.iter().filter(|callsite| callsite.change == change).count();
+
+    for callsite in &diff.callsites {
+        match callsite.change {
+            CallSiteChange::Unchanged => continue,
+            CallSiteChange::NewlyResolved => writeln!(out, "{:#x}: newly resolved", callsite.callsite).unwrap(),
+            CallSiteChange::NewlyUnresolved => writeln!(out, "{:#x}: newly unresolved", callsite.callsite).unwrap(),
+            CallSiteChange::TargetsChanged => writeln!(out, "{:#x}: targets changed", callsite.callsite).unwrap(),
+        }
+        if !callsite.added_targets.is_empty() {
+            writeln!(out, "\t+ {}", format_targets(&callsite.added_targets)).unwrap();
+        }
+        if !callsite.removed_targets.is_empty() {
+            writeln!(out, "\t- {}", format_targets(&callsite.removed_targets)).unwrap();
+        }
+    }
+
+    writeln!(
+        out,
+        "Callsites: {}, unchanged: {}, changed: {}, newly resolved: {}, newly unresolved: {}",
+        diff.callsites.len(),
+        count(CallSiteChange::Unchanged),
+        count(CallSiteChange::TargetsChanged),
+        count(CallSiteChange::NewlyResolved),
+        count(CallSiteChange::NewlyUnresolved)
+    )
+    .unwrap();
+
+    if !with_soundness {
+        return out;
+    }
+    for change in &diff.soundness_changes {
+        if change.gained {
+            writeln!(out, "[+] SOUND NOW: {:#x} -> {:#x}", change.callsite, change.target).unwrap();
+        } else {
+            writeln!(out, "[!] UNSOUND NOW: {:#x} -> {:#x}", change.callsite, change.target).unwrap();
+        }
+    }
+    let gained = diff.soundness_changes.iter().filter(|change| change.gained).count();
+    writeln!(
+        out,
+        "Observed edges gained: {}, lost: {}",
+        gained,
+        diff.soundness_changes.len() - gained
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Metadata},
+        valgrind::RealCall,
+    };
+
+    fn call_graph(calls: &[(u64, u64)]) -> CweCheckerResult {
+        CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x10, 0x20, 0x30],
+                functions: vec![],
+            },
+            calls: calls
+                .iter()
+                .map(|(from_instr, to_instr)| Call {
+                    from_instr: *from_instr,
+                    to_instr: Some(*to_instr),
+                    is_indirect: true,
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = call_graph(&[(0x10, 0x100), (0x30, 0x100), (0x30, 0x200)]);
+        let new = call_graph(&[(0x20, 0x100), (0x30, 0x100), (0x30, 0x300)]);
+
+        let callsites = diff_call_graphs(&old, &new);
+        let changes = callsites.iter().map(|callsite| (callsite.callsite, callsite.change.clone())).collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [(0x10, CallSiteChange::NewlyUnresolved), (0x20, CallSiteChange::NewlyResolved), (0x30, CallSiteChange::TargetsChanged)]
+        );
+        assert_eq!(callsites[2].added_targets, [0x300]);
+        assert_eq!(callsites[2].removed_targets, [0x200]);
+        assert!(diff_call_graphs(&old, &old).iter().all(|callsite| callsite.change == CallSiteChange::Unchanged));
+
+        let real_call = |from_instr: u64, to_instr: u64| RealCall {
+            from_instr,
+            to_instr,
+            in_fn: 0,
+            target_fn: 1,
+            does_jump_object_file: false,
+            call_count: 1,
+            inclusive_cost: 0,
+        };
+        let real = ValgrindResult {
+            // The second call to 0x200 is reported once, 0x40 is no indirect callsite
+            calls: vec![real_call(0x10, 0x100), real_call(0x30, 0x200), real_call(0x30, 0x200), real_call(0x30, 0x100), real_call(0x20, 0x100), real_call(0x40, 0x100)],
+            ..Default::default()
+        };
+        let changes = diff_soundness(&old, &new, &real)
+            .iter()
+            .map(|change| (change.callsite, change.target, change.gained))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [(0x10, 0x100, false), (0x20, 0x100, true), (0x30, 0x200, false)]);
+    }
+}
@@ -1,9 +1,14 @@
 use std::{
-    collections::{HashMap, HashSet}, fs::{self, File}, io::{BufRead, BufReader, Write}, path::{Path, PathBuf}, process::{Command, Stdio}
+    collections::{HashMap, HashSet}, fs::{self, File}, io::Write, path::{Path, PathBuf}, process::Command
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    cache::{ArtifactCache, CacheKey},
+    error::{Error, Result},
+};
+
 // Copy from json_export.rs in cwe_checker
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -52,7 +57,7 @@ pub struct CweCheckerResult {
 }
 
 impl CweCheckerResult {
-    fn from_export_call_graph(export_call_graph: ExportCallGraph) -> Self {
+    pub fn from_export_call_graph(export_call_graph: ExportCallGraph) -> Self {
         let mut call_hash_map_by_call_site = HashMap::new();
         for call in &export_call_graph.calls {
             let callsite = call_hash_map_by_call_site
@@ -74,84 +79,113 @@ impl CweCheckerResult {
     pub fn get_call_site(&self, addr: u64) -> Option<CallSite> {
         self.call_hash_map_by_call_site.get(&addr).cloned()
     }
+
+    pub fn call_sites(&self) -> impl Iterator<Item = &CallSite> {
+        self.call_hash_map_by_call_site.values()
+    }
+
+    /// Average indirect call targets, over the indirect callsites with at least one target. None
+    /// if no callsite has a target
+    pub fn aict(&self) -> Option<f32> {
+        let target_counts = self
+            .metadata
+            .indirect_call_sites
+            .iter()
+            .filter_map(|callsite| self.call_hash_map_by_call_site.get(callsite))
+            .map(|callsite| callsite.targets.len())
+            .filter(|count| *count > 0)
+            .collect::<Vec<usize>>();
+        if target_counts.is_empty() {
+            return None;
+        }
+        Some(target_counts.iter().sum::<usize>() as f32 / target_counts.len() as f32)
+    }
+
+    /// Lowest function start to the highest function start or callsite, an approximation of the code of the binary
+    pub fn address_range(&self) -> Option<(u64, u64)> {
+        let start = self.metadata.functions.iter().map(|func| func.address).min()?;
+        let end = self
+            .metadata
+            .functions
+            .iter()
+            .map(|func| func.address)
+            .chain(self.call_sites().map(|callsite| callsite.callsite_loc))
+            .max()?;
+        Some((start, end))
+    }
+
+    /// The function starting at addr
+    pub fn get_function_at(&self, addr: u64) -> Option<&Function> {
+        self.metadata.functions.iter().find(|func| func.address == addr)
+    }
+
+    /// The function with the highest start address not above addr
+    pub fn get_function_containing(&self, addr: u64) -> Option<&Function> {
+        self.metadata
+            .functions
+            .iter()
+            .filter(|func| func.address <= addr)
+            .max_by_key(|func| func.address)
+    }
 }
 
-pub fn complete_analysis(binary: &PathBuf) -> CweCheckerResult {
-    let output_folder = Path::new("cwe_output");
-    let file_name_str = binary.file_name().unwrap().to_str().unwrap();
-    fs::create_dir_all(output_folder).expect("Could not create output folder");
-    let output_file = output_folder.join(Path::new(file_name_str));
-    run_cwe_checker(binary, &output_file);
-    get_analysis_results(&output_file).unwrap()
+pub const CWE_CHECKER_ARGS: [&str; 2] = ["--debug", "i-call-rec-vsa"];
+
+pub fn complete_analysis(binary: &Path, cache: &ArtifactCache) -> Result<CweCheckerResult> {
+    cached_analysis(binary, cwe_checker_version(), cache, |binary, output_file| run_cwe_checker(binary, &output_file.to_path_buf()))
 }
 
-pub fn get_analysis_results(report: &PathBuf) -> Option<CweCheckerResult> {
-    let content = fs::read_to_string(report).unwrap();
-    // Skip debug log. This is take the second last elemtn
-    let Some(content) = content.split('\n').rev().find(|line| line.contains("{\"metadata\":")) else {
-        return None;
+/// Uses the cached call graph of the binary if there is one, otherwise run_tool writes it to the given file
+pub(crate) fn cached_analysis(
+    binary: &Path,
+    tool_version: Option<String>,
+    cache: &ArtifactCache,
+    run_tool: impl FnOnce(&Path, &Path) -> Result<()>,
+) -> Result<CweCheckerResult> {
+    let key = CacheKey::new(binary, "cwe_checker", tool_version, &CWE_CHECKER_ARGS, "call_graph.json")?;
+    if let Some(cached) = cache.get(&key) {
+        return get_analysis_results(&cached);
+    }
+    let output_file = match cache.put_path(&key)? {
+        Some(output_file) => output_file,
+        None => {
+            let output_folder = Path::new("cwe_output");
+            fs::create_dir_all(output_folder).map_err(Error::io(output_folder))?;
+            output_folder.join(binary.file_name().unwrap_or_default())
+        }
     };
-    let callgraph: ExportCallGraph = serde_json::from_str(&content).expect("JSON is bad");
-    Some(CweCheckerResult::from_export_call_graph(callgraph))
+    run_tool(binary, &output_file)?;
+    let result = get_analysis_results(&output_file)?;
+    cache.commit(&key)?;
+    Ok(result)
 }
 
-pub fn run_cwe_checker(binary: &PathBuf, output_file: &PathBuf) {
-    let output = Command::new("cwe-checker")
-        .args(["--debug", "i-call-rec-vsa", binary.to_str().unwrap()])
-        .output()
-        .expect("Failed exeucting cwe_checker");
-
-    //println!("stdout: {}", String::from_utf8(output.stdout.clone()).unwrap());
-    //println!("stderr: {}", String::from_utf8(output.stderr.clone()).unwrap());
-
-    let json = output.stdout;
-    let mut file = File::create(output_file).expect("Could not create output file");
-    file.write_all(&json).expect("Could not write json to file");
+pub fn get_analysis_results(report: &PathBuf) -> Result<CweCheckerResult> {
+    let content = fs::read_to_string(report).map_err(Error::io(report))?;
+    // Skip debug log. This is take the second last elemtn
+    let content = content
+        .split('\n')
+        .rev()
+        .find(|line| line.contains("{\"metadata\":"))
+        .ok_or_else(|| Error::NoCallGraph(report.clone()))?;
+    let callgraph: ExportCallGraph = serde_json::from_str(content).map_err(Error::json(report))?;
+    Ok(CweCheckerResult::from_export_call_graph(callgraph))
 }
 
-fn start_analysis(should_run_cwe_checker: bool, binary: PathBuf) {
-    let output_file = PathBuf::from("output_cwe_checker.json");
-    if should_run_cwe_checker {
-        run_cwe_checker(&binary, &output_file);
-    }
+/// Version of the installed cwe_checker, None if it is not installed
+pub fn cwe_checker_version() -> Option<String> {
+    let output = Command::new("cwe-checker").arg("--version").output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|version| !version.is_empty())
 }
 
-pub fn setup_hetzner_server(binary: PathBuf) {
-    println!("You want to run the binary remote?");
-    println!("Setup a $Cloud Service with a common distro (a lot of cores and ram) and run the following command");
-    println!("curl -sSf -L https://install.lix.systems/lix | sh -s -- install --enable-flakes --no-confirm");
-    println!(". /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh");
-    println!("curl https://getcroc.schollz.com | bash");
-
-    let mut child = Command::new("croc")
-        .arg("--yes")
-        .arg("send")
-        .arg(binary.clone())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect("Failed to start croc process");
-
-    if let Some(stdout) = &mut child.stderr {
-        let reader = BufReader::new(stdout);
-        let mut next_line_croc = false;
-        for line in reader.lines() {
-            let line = line.expect("Failed to read line");
-            if next_line_croc {
-                print!("{}", line.trim()); // This will print croc code and status updates
-                println!(" --yes");
-                next_line_croc = false;
-
-                let file_name_str = binary.file_name().unwrap().to_str().unwrap();
-                println!("nix run github:felix-ulonska/cwe_checker/feat/split_stack_mem --extra-experimental-features flakes --option tarball-ttl 0 -- {} > {}_call_graph.json", file_name_str, file_name_str);
-            }
-            if line.contains("For Linux") {
-                next_line_croc = true;
-            }
-        }
-    }
+pub fn run_cwe_checker(binary: &Path, output_file: &PathBuf) -> Result<()> {
+    let output = Command::new("cwe-checker")
+        .args(CWE_CHECKER_ARGS)
+        .arg(binary)
+        .output()
+        .map_err(|err| Error::tool("cwe_checker", err))?;
 
-    println!("Received on server!");
-    println!("Afterwards, you can use croc to send the file back");
-    println!("DO NOT FORGET to stop the server; otherwise the bill will be heavy");
+    let json = output.stdout;
+    let mut file = File::create(output_file).map_err(Error::io(output_file))?;
+    file.write_all(&json).map_err(Error::io(output_file))
 }
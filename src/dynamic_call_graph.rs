@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::valgrind::ValgrindResult;
+
+/// Function level call graph as observed by callgrind, nodes are the fn= numbers
+pub struct DynamicCallGraph {
+    pub callees: HashMap<u64, HashSet<u64>>,
+    pub callers: HashMap<u64, HashSet<u64>>,
+    /// Summed inclusive cost of all calls from caller to callee
+    pub edge_costs: HashMap<(u64, u64), u64>,
+}
+
+impl DynamicCallGraph {
+    pub fn from_valgrind(real: &ValgrindResult) -> DynamicCallGraph {
+        let mut callees: HashMap<u64, HashSet<u64>> = HashMap::new();
+        let mut callers: HashMap<u64, HashSet<u64>> = HashMap::new();
+        let mut edge_costs: HashMap<(u64, u64), u64> = HashMap::new();
+        for call in real.calls.iter().chain(real.jumps.iter()) {
+            let (from, to) = (call.in_fn as u64, call.target_fn as u64);
+            callees.entry(from).or_default().insert(to);
+            callers.entry(to).or_default().insert(from);
+            *edge_costs.entry((from, to)).or_default() += call.inclusive_cost;
+        }
+        DynamicCallGraph { callees, callers, edge_costs }
+    }
+
+    /// Functions that were called but never called anything themselves are not included
+    pub fn functions(&self) -> HashSet<u64> {
+        self.callees.keys().chain(self.callers.keys()).cloned().collect()
+    }
+
+    /// Functions without any observed caller, e.g. `(below main)`
+    pub fn entry_functions(&self) -> Vec<u64> {
+        let mut entries = self
+            .callees
+            .keys()
+            .filter(|fn_id| !self.callers.contains_key(fn_id))
+            .cloned()
+            .collect::<Vec<u64>>();
+        entries.sort();
+        entries
+    }
+
+    /// Shortest observed call chain from any of the roots to target, including both ends
+    pub fn shortest_chain(&self, roots: &[u64], target: u64) -> Option<Vec<u64>> {
+        let mut predecessor: HashMap<u64, Option<u64>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for root in roots {
+            predecessor.insert(*root, None);
+            queue.push_back(*root);
+        }
+
+        while let Some(current) = queue.pop_front() {
+            if current == target {
+                let mut chain = vec![current];
+                while let Some(Some(prev)) = predecessor.get(chain.last().unwrap()) {
+                    chain.push(*prev);
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+            for next in self.callees.get(&current).into_iter().flatten() {
+                if !predecessor.contains_key(next) {
+                    predecessor.insert(*next, Some(current));
+                    queue.push_back(*next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Reconstructs the most likely caller chain of function, ending with function itself.
+    ///
+    /// Callgrind only records caller/callee pairs, not full stacks. At every step the caller
+    /// which spent the most inclusive cost in calls to the current function is taken.
+    pub fn caller_chain(&self, function: u64) -> Vec<u64> {
+        let mut chain = vec![function];
+        let mut visited = HashSet::from([function]);
+        let mut current = function;
+        while let Some(caller) = self
+            .callers
+            .get(&current)
+            .into_iter()
+            .flatten()
+            .filter(|caller| !visited.contains(caller))
+            .max_by_key(|caller| (self.edge_costs.get(&(**caller, current)).cloned().unwrap_or(0), std::cmp::Reverse(**caller)))
+        {
+            chain.push(*caller);
+            visited.insert(*caller);
+            current = *caller;
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::valgrind::RealCall;
+
+    #[test]
+    fn test_chains() {
+        let call = |in_fn: i64, target_fn: i64, inclusive_cost: u64| RealCall {
+            from_instr: 0,
+            to_instr: 0,
+            in_fn,
+            target_fn,
+            does_jump_object_file: false,
+            call_count: 1,
+            inclusive_cost,
+        };
+        // 3 is recursive and 5 and 6 call each other without any other caller
+        let real = ValgrindResult {
+            calls: vec![call(0, 1, 100), call(0, 2, 10), call(1, 3, 5), call(2, 3, 50), call(3, 3, 1000), call(3, 4, 20), call(5, 6, 1), call(6, 5, 1)],
+            jumps: vec![call(1, 4, 0)],
+            ..Default::default()
+        };
+        let graph = DynamicCallGraph::from_valgrind(&real);
+        assert_eq!(graph.entry_functions(), [0]);
+
+        assert_eq!(graph.caller_chain(4), [0, 2, 3, 4]);
+        assert_eq!(graph.caller_chain(6), [5, 6]);
+        assert_eq!(graph.caller_chain(0), [0]);
+
+        assert_eq!(graph.shortest_chain(&[0], 4), Some(vec![0, 1, 4]));
+        assert_eq!(graph.shortest_chain(&[0], 0), Some(vec![0]));
+        assert_eq!(graph.shortest_chain(&[0], 6), None);
+    }
+}
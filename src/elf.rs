@@ -0,0 +1,429 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use gimli::{BaseAddresses, CieOrFde, EhFrame, RunTimeEndian, UnwindSection};
+use log::warn;
+use object::{
+    elf,
+    read::elf::SectionHeader as _,
+    Architecture, Object, ObjectKind, ObjectSection, ObjectSymbol, ObjectSymbolTable, RelocationFlags, RelocationTarget, SymbolFlags,
+    SymbolKind,
+};
+
+use crate::{
+    cwe_checker::Metadata,
+    error::{Error, Result},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolSource {
+    Symtab,
+    Dynsym,
+    /// Stub in .plt, .plt.sec or .plt.got, named after the imported symbol
+    Plt,
+    /// Function start from an .eh_frame FDE without any symbol
+    EhFrame,
+}
+
+#[derive(Clone, Debug)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    pub source: SymbolSource,
+    /// Defined global symbol in .dynsym
+    pub is_exported: bool,
+    /// STT_GNU_IFUNC, the address is the resolver, not the implementation
+    pub is_ifunc: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ElfSection {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct PltEntry {
+    pub address: u64,
+    pub size: u64,
+    pub got_slot: u64,
+    pub import: String,
+}
+
+/// Symbols and sections of an ELF file, all addresses are virtual addresses of the file
+#[derive(Debug)]
+pub struct ElfInfo {
+    pub is_pie: bool,
+    /// Sorted by address
+    pub symbols: Vec<ElfSymbol>,
+    pub sections: Vec<ElfSection>,
+    pub plt_entries: Vec<PltEntry>,
+    /// GOT slot to the name of the imported symbol
+    pub got_imports: HashMap<u64, String>,
+    /// Function starts stored as pointers in .data, .rodata or .data.rel.ro, with the section
+    pub data_pointers: HashMap<u64, String>,
+}
+
+const STT_GNU_IFUNC: u8 = 10;
+
+fn function_symbols<'data, T: ObjectSymbol<'data>>(
+    symbols: impl Iterator<Item = T>,
+    source: SymbolSource,
+) -> Vec<ElfSymbol> {
+    symbols
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && !symbol.is_undefined() && symbol.address() != 0)
+        .filter_map(|symbol| {
+            let is_ifunc = matches!(symbol.flags(), SymbolFlags::Elf { st_info, .. } if st_info & 0xf == STT_GNU_IFUNC);
+            Some(ElfSymbol {
+                name: symbol.name().ok()?.to_string(),
+                address: symbol.address(),
+                size: symbol.size(),
+                source,
+                is_exported: source == SymbolSource::Dynsym && symbol.is_global(),
+                is_ifunc,
+            })
+        })
+        .collect()
+}
+
+/// Relocation types and PLT stubs are only decoded for x86-64, other architectures get no PLT
+/// entries, GOT imports or RELATIVE relocations
+fn is_x86_64(file: &object::File) -> bool {
+    file.architecture() == Architecture::X86_64
+}
+
+/// Resolves the GOT slots of JUMP_SLOT and GLOB_DAT relocations to the imported symbol names
+fn got_imports(file: &object::File) -> HashMap<u64, String> {
+    let mut imports = HashMap::new();
+    if !is_x86_64(file) {
+        return imports;
+    }
+    let (Some(relocations), Some(dynamic_symbols)) = (file.dynamic_relocations(), file.dynamic_symbol_table()) else {
+        return imports;
+    };
+    for (offset, relocation) in relocations {
+        let RelocationFlags::Elf { r_type } = relocation.flags() else {
+            continue;
+        };
+        if r_type != elf::R_X86_64_JUMP_SLOT && r_type != elf::R_X86_64_GLOB_DAT {
+            continue;
+        }
+        let RelocationTarget::Symbol(index) = relocation.target() else {
+            continue;
+        };
+        if let Some(name) = dynamic_symbols.symbol_by_index(index).ok().and_then(|symbol| symbol.name().ok().map(str::to_string)) {
+            imports.insert(offset, name);
+        }
+    }
+    imports
+}
+
+/// sh_entsize of the section, 0 if it is not set
+fn entry_size(file: &object::File, name: &str) -> u64 {
+    match file {
+        object::File::Elf64(elf) => elf
+            .section_by_name(name)
+            .map(|section| section.elf_section_header().sh_entsize(elf.endian()))
+            .unwrap_or(0),
+        object::File::Elf32(elf) => elf
+            .section_by_name(name)
+            .map(|section| section.elf_section_header().sh_entsize(elf.endian()).into())
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Finds the `jmp *disp(%rip)` of every x86-64 PLT stub and maps it to the import of its GOT slot
+fn plt_entries(file: &object::File, got_imports: &HashMap<u64, String>) -> Vec<PltEntry> {
+    let mut entries = vec![];
+    if !is_x86_64(file) {
+        return entries;
+    }
+    for section in file.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+        if !name.starts_with(".plt") {
+            continue;
+        }
+        let Ok(data) = section.data() else {
+            continue;
+        };
+        // .plt.got stubs are 16 bytes with IBT (-fcf-protection), like the ones of .plt.sec
+        let entry_size = match (entry_size(file, name), name) {
+            (0, ".plt.got") => 8,
+            (0, _) => 16,
+            (size, _) => size as usize,
+        };
+        for index in 0..data.len().saturating_sub(5) {
+            if data[index] != 0xff || data[index + 1] != 0x25 {
+                continue;
+            }
+            let displacement = i32::from_le_bytes(data[index + 2..index + 6].try_into().unwrap());
+            let next_instruction = section.address() + index as u64 + 6;
+            let got_slot = (next_instruction as i64 + displacement as i64) as u64;
+            let Some(import) = got_imports.get(&got_slot) else {
+                continue;
+            };
+            entries.push(PltEntry {
+                address: section.address() + (index / entry_size * entry_size) as u64,
+                size: entry_size as u64,
+                got_slot,
+                import: import.clone(),
+            });
+        }
+    }
+    entries
+}
+
+/// Sections of function pointer tables and initialised global pointers
+fn is_pointer_section(name: &str) -> bool {
+    [".data", ".rodata", ".data.rel.ro"]
+        .iter()
+        .any(|prefix| name == *prefix || name.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('.')))
+}
+
+/// Scans the data sections for aligned pointer-sized values that are function starts. In PIE
+/// binaries the values are the addends of RELATIVE relocations instead of the section contents
+fn data_pointers(file: &object::File, symbols: &[ElfSymbol]) -> HashMap<u64, String> {
+    let pointer_size = if file.is_64() { 8 } else { 4 };
+    let read_pointer = |chunk: &[u8]| -> u64 {
+        let mut bytes = [0; 8];
+        if file.is_little_endian() {
+            bytes[..pointer_size].copy_from_slice(chunk);
+            u64::from_le_bytes(bytes)
+        } else {
+            bytes[8 - pointer_size..].copy_from_slice(chunk);
+            u64::from_be_bytes(bytes)
+        }
+    };
+    let functions = symbols.iter().map(|symbol| symbol.address).collect::<HashSet<u64>>();
+    let sections = file
+        .sections()
+        .filter(|section| section.address() != 0 && section.name().is_ok_and(is_pointer_section))
+        .collect::<Vec<_>>();
+    let mut pointers = HashMap::new();
+    for section in &sections {
+        let (Ok(name), Ok(data)) = (section.name(), section.data()) else {
+            continue;
+        };
+        for chunk in data.chunks_exact(pointer_size) {
+            let value = read_pointer(chunk);
+            if functions.contains(&value) {
+                pointers.insert(value, name.to_string());
+            }
+        }
+    }
+    let relocations = file.dynamic_relocations().filter(|_| is_x86_64(file));
+    for (offset, relocation) in relocations.into_iter().flatten() {
+        if relocation.flags() != (RelocationFlags::Elf { r_type: elf::R_X86_64_RELATIVE }) {
+            continue;
+        }
+        let value = relocation.addend() as u64;
+        if !functions.contains(&value) {
+            continue;
+        }
+        let section = sections
+            .iter()
+            .find(|section| section.address() <= offset && offset < section.address() + section.size());
+        if let Some(name) = section.and_then(|section| section.name().ok()) {
+            pointers.insert(value, name.to_string());
+        }
+    }
+    pointers
+}
+
+/// Function starts from the FDEs of .eh_frame, also available in stripped binaries
+fn eh_frame_functions(file: &object::File) -> Vec<(u64, u64)> {
+    let Some(section) = file.section_by_name(".eh_frame") else {
+        return vec![];
+    };
+    let Ok(data) = section.data() else {
+        return vec![];
+    };
+    let endian = if file.is_little_endian() { RunTimeEndian::Little } else { RunTimeEndian::Big };
+    let eh_frame = EhFrame::new(data, endian);
+    let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+    if let Some(text) = file.section_by_name(".text") {
+        bases = bases.set_text(text.address());
+    }
+
+    let mut functions = vec![];
+    let mut entries = eh_frame.entries(&bases);
+    while let Ok(Some(entry)) = entries.next() {
+        let CieOrFde::Fde(partial) = entry else {
+            continue;
+        };
+        if let Ok(fde) = partial.parse(|section, bases, offset| section.cie_from_offset(bases, offset)) {
+            functions.push((fde.initial_address(), fde.len()));
+        }
+    }
+    functions
+}
+
+impl ElfInfo {
+    pub fn load(path: &Path) -> Result<ElfInfo> {
+        let data = fs::read(path).map_err(Error::io(path))?;
+        let file = object::File::parse(&*data).map_err(|source| Error::Elf { path: path.to_path_buf(), source })?;
+        if !is_x86_64(&file) {
+            warn!("{} is no x86-64 binary, calls through the PLT are not recognised", path.display());
+        }
+
+        let mut symbols = function_symbols(file.symbols(), SymbolSource::Symtab);
+        symbols.extend(function_symbols(file.dynamic_symbols(), SymbolSource::Dynsym));
+
+        let got_imports = got_imports(&file);
+        let plt_entries = plt_entries(&file, &got_imports);
+        symbols.extend(plt_entries.iter().map(|entry| ElfSymbol {
+            name: format!("{}@plt", entry.import),
+            address: entry.address,
+            size: entry.size,
+            source: SymbolSource::Plt,
+            is_exported: false,
+            is_ifunc: false,
+        }));
+
+        for (address, size) in eh_frame_functions(&file) {
+            if symbols.iter().any(|symbol| symbol.address == address) {
+                continue;
+            }
+            symbols.push(ElfSymbol {
+                name: format!("sub_{:x}", address),
+                address,
+                size,
+                source: SymbolSource::EhFrame,
+                is_exported: false,
+                is_ifunc: false,
+            });
+        }
+        // Prefer the .symtab name if a function is in several tables
+        symbols.sort_by_key(|symbol| (symbol.address, symbol.source as u8));
+        symbols.dedup_by(|duplicate, kept| {
+            if duplicate.address != kept.address {
+                return false;
+            }
+            kept.is_exported |= duplicate.is_exported;
+            kept.is_ifunc |= duplicate.is_ifunc;
+            true
+        });
+
+        let sections = file
+            .sections()
+            .filter(|section| section.address() != 0)
+            .map(|section| ElfSection {
+                name: section.name().unwrap_or("").to_string(),
+                address: section.address(),
+                size: section.size(),
+            })
+            .collect();
+
+        let data_pointers = data_pointers(&file, &symbols);
+
+        Ok(ElfInfo {
+            is_pie: file.kind() == ObjectKind::Dynamic,
+            symbols,
+            sections,
+            plt_entries,
+            got_imports,
+            data_pointers,
+        })
+    }
+
+    /// The function containing addr and the offset into it
+    pub fn lookup(&self, addr: u64) -> Option<(&ElfSymbol, u64)> {
+        let index = self.symbols.partition_point(|symbol| symbol.address <= addr).checked_sub(1)?;
+        let symbol = &self.symbols[index];
+        let offset = addr - symbol.address;
+        // Symbols without a size extend to the next symbol within their section
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+        if symbol.size == 0 && self.section_of(addr).map(|section| section.address) != self.section_of(symbol.address).map(|section| section.address) {
+            return None;
+        }
+        Some((symbol, offset))
+    }
+
+    pub fn section_of(&self, addr: u64) -> Option<&ElfSection> {
+        self.sections
+            .iter()
+            .find(|section| section.address <= addr && addr < section.address + section.size)
+    }
+
+    pub fn exported_functions(&self) -> impl Iterator<Item = &ElfSymbol> {
+        self.symbols.iter().filter(|symbol| symbol.is_exported)
+    }
+
+    /// cwe_checker addresses of PIE binaries are relative to address_base_offset, None below it
+    pub fn from_cwe_address(&self, addr: u64, metadata: &Metadata) -> Option<u64> {
+        if self.is_pie {
+            addr.checked_sub(metadata.address_base_offset)
+        } else {
+            Some(addr)
+        }
+    }
+
+    /// Runtime addresses of PIE binaries are relative to the load base of the binary, None below it
+    pub fn from_runtime_address(&self, addr: u64, load_base: u64) -> Option<u64> {
+        if self.is_pie {
+            addr.checked_sub(load_base)
+        } else {
+            Some(addr)
+        }
+    }
+
+    /// `name+0x12 (.text)` or just the address if it is not within any known function
+    pub fn format_address(&self, addr: u64) -> String {
+        let section = self.section_of(addr).map(|section| format!(" ({})", section.name)).unwrap_or_default();
+        match self.lookup(addr) {
+            Some((symbol, 0)) => format!("{}{}", symbol.name, section),
+            Some((symbol, offset)) => format!("{}+{:#x}{}", symbol.name, offset, section),
+            None => format!("{:#x}{}", addr, section),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_own_binary() {
+        let elf = ElfInfo::load(&std::env::current_exe().unwrap()).unwrap();
+        let main = elf.symbols.iter().find(|symbol| symbol.name == "main").expect("main is in .symtab");
+        let (symbol, offset) = elf.lookup(main.address + 1).unwrap();
+        assert_eq!((symbol.name.as_str(), offset), ("main", 1));
+        assert_eq!(elf.section_of(main.address).unwrap().name, ".text");
+
+        // Calls into libc, e.g. memcpy, go through the PLT
+        let entry = elf.plt_entries.first().expect("the test binary imports from libc");
+        assert_eq!(elf.got_imports[&entry.got_slot], entry.import);
+        let (symbol, offset) = elf.lookup(entry.address + entry.size - 1).unwrap();
+        assert_eq!((symbol.name.clone(), symbol.source, offset), (format!("{}@plt", entry.import), SymbolSource::Plt, entry.size - 1));
+
+        let metadata = Metadata {
+            address_base_offset: 0x100000,
+            indirect_call_sites: vec![],
+            functions: vec![],
+        };
+        assert!(elf.is_pie);
+        assert_eq!(elf.from_cwe_address(0x100010, &metadata), Some(0x10));
+        assert_eq!(elf.from_cwe_address(0x10, &metadata), None);
+        assert_eq!(elf.from_runtime_address(0x10, 0x555555554000), None);
+    }
+
+    #[test]
+    fn test_eh_frame_functions() {
+        let data = fs::read(std::env::current_exe().unwrap()).unwrap();
+        let file = object::File::parse(&*data).unwrap();
+        let main = file.symbols().find(|symbol| symbol.name() == Ok("main")).unwrap();
+        let functions = eh_frame_functions(&file);
+        let (_, size) = functions.iter().find(|(address, _)| *address == main.address()).expect("main has an FDE");
+        assert!(*size > 0);
+    }
+}
@@ -0,0 +1,81 @@
+use std::{fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io { path: PathBuf, source: io::Error },
+    Json { path: PathBuf, source: serde_json::Error },
+    /// Malformed trace or call graph
+    Parse { path: PathBuf, message: String },
+    Elf { path: PathBuf, source: object::Error },
+    Dwarf { path: PathBuf, source: gimli::Error },
+    /// The cwe_checker output contains no call graph, e.g. because cwe_checker failed
+    NoCallGraph(PathBuf),
+    /// An external tool could not be started or failed
+    Tool { tool: String, message: String },
+    Ptrace(nix::errno::Errno),
+    Database { path: PathBuf, source: rusqlite::Error },
+    /// A figure could not be drawn or written
+    Plot { path: PathBuf, message: String },
+    /// Missing or conflicting inputs
+    Config(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// For `map_err`, e.g. `fs::read(path).map_err(Error::io(path))`
+    pub fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Error {
+        let path = path.into();
+        move |source| Error::Io { path, source }
+    }
+
+    pub fn json(path: impl Into<PathBuf>) -> impl FnOnce(serde_json::Error) -> Error {
+        let path = path.into();
+        move |source| Error::Json { path, source }
+    }
+
+    pub fn tool(tool: &str, message: impl ToString) -> Error {
+        Error::Tool {
+            tool: tool.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Json { path, source } => write!(f, "{}: invalid JSON: {}", path.display(), source),
+            Error::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::Elf { path, source } => write!(f, "{}: could not parse ELF file: {}", path.display(), source),
+            Error::Dwarf { path, source } => write!(f, "{}: could not read DWARF: {}", path.display(), source),
+            Error::NoCallGraph(path) => write!(f, "{} contains no call graph", path.display()),
+            Error::Tool { tool, message } => write!(f, "{}: {}", tool, message),
+            Error::Ptrace(errno) => write!(f, "ptrace failed: {}", errno),
+            Error::Database { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Plot { path, message } => write!(f, "{}: could not draw figure: {}", path.display(), message),
+            Error::Config(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Elf { source, .. } => Some(source),
+            Error::Dwarf { source, .. } => Some(source),
+            Error::Ptrace(errno) => Some(errno),
+            Error::Database { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<nix::errno::Errno> for Error {
+    fn from(errno: nix::errno::Errno) -> Error {
+        Error::Ptrace(errno)
+    }
+}
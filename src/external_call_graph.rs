@@ -0,0 +1,390 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    cwe_checker::{Call, CweCheckerResult, ExportCallGraph, Metadata},
+    elf::ElfInfo,
+    error::{Error, Result},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExternalFormat {
+    /// `networkx.node_link_data(cfg.kb.callgraph)` of angr's CFGFast as JSON
+    Angr,
+    /// CSV with callsite and target address per row, e.g. from a Ghidra script
+    Ghidra,
+    /// Output of radare2's `agCj`
+    Radare2,
+    /// Callgraph dump of BAP, one `caller -> callee` edge per line
+    Bap,
+}
+
+impl ExternalFormat {
+    /// Function level graphs give an indirect callsite every callee of its function, including
+    /// the direct ones, so their soundness is an upper bound compared to callsite level graphs
+    pub fn is_function_granular(&self) -> bool {
+        !matches!(self, ExternalFormat::Ghidra)
+    }
+
+    /// Address the tool loads a PIE binary at by default. None for other binaries, which every
+    /// tool analyses at their static addresses
+    pub fn image_base(&self, elf: &ElfInfo) -> Option<u64> {
+        if !elf.is_pie {
+            return None;
+        }
+        Some(match self {
+            ExternalFormat::Angr => 0x400000,
+            // Same as cwe_checker, which uses Ghidra
+            ExternalFormat::Ghidra => 0x100000,
+            ExternalFormat::Radare2 | ExternalFormat::Bap => 0,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum FunctionRef {
+    Address(u64),
+    Name(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ImportedEdges {
+    /// Edges from a callsite to the target address
+    Callsite(Vec<(u64, u64)>),
+    /// Edges between functions, without the callsite
+    Function(Vec<(FunctionRef, FunctionRef)>),
+}
+
+#[derive(Deserialize)]
+struct NodeLinkEdge {
+    source: u64,
+    target: u64,
+}
+
+#[derive(Deserialize)]
+struct NodeLinkGraph {
+    #[serde(alias = "edges")]
+    links: Vec<NodeLinkEdge>,
+}
+
+#[derive(Deserialize)]
+struct Radare2Function {
+    name: String,
+    #[serde(default)]
+    imports: Vec<String>,
+}
+
+fn parse_hex(input: &str) -> Option<u64> {
+    u64::from_str_radix(input.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn parse_angr(content: &str) -> serde_json::Result<ImportedEdges> {
+    let graph: NodeLinkGraph = serde_json::from_str(content)?;
+    Ok(ImportedEdges::Function(
+        graph
+            .links
+            .iter()
+            .map(|edge| (FunctionRef::Address(edge.source), FunctionRef::Address(edge.target)))
+            .collect(),
+    ))
+}
+
+fn parse_ghidra(content: &str) -> ImportedEdges {
+    ImportedEdges::Callsite(
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split(',');
+                Some((parse_hex(columns.next()?)?, parse_hex(columns.next()?)?))
+            })
+            .collect(),
+    )
+}
+
+fn parse_radare2(content: &str) -> serde_json::Result<ImportedEdges> {
+    let functions: Vec<Radare2Function> = serde_json::from_str(content)?;
+    Ok(ImportedEdges::Function(
+        functions
+            .iter()
+            .flat_map(|function| {
+                function
+                    .imports
+                    .iter()
+                    .map(|callee| (FunctionRef::Name(function.name.clone()), FunctionRef::Name(callee.clone())))
+            })
+            .collect(),
+    ))
+}
+
+fn parse_bap(content: &str) -> ImportedEdges {
+    let clean = |name: &str| {
+        let name = name.split('[').next().unwrap_or(name);
+        name.trim().trim_end_matches(';').trim().trim_matches('"').to_string()
+    };
+    ImportedEdges::Function(
+        content
+            .lines()
+            .filter_map(|line| {
+                let (caller, callee) = line.split_once("->")?;
+                Some((FunctionRef::Name(clean(caller)), FunctionRef::Name(clean(callee))))
+            })
+            .collect(),
+    )
+}
+
+/// Tools prefix or generate names differently, e.g. `sym.imp.puts`, `@main` or `sub_401126`.
+/// Names are looked up in cwe_checker's functions, addresses are rebased into its coordinates
+fn resolve_function(function: &FunctionRef, functions_by_name: &HashMap<&str, u64>, rebase: impl Fn(u64) -> Option<u64>) -> Option<u64> {
+    let name = match function {
+        FunctionRef::Address(address) => return rebase(*address),
+        FunctionRef::Name(name) => name,
+    };
+    let name = name.trim_start_matches('@');
+    let name = ["sym.imp.", "sym.", "dbg.", "imp."]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    if let Some(address) = functions_by_name.get(name) {
+        return Some(*address);
+    }
+    ["fcn.", "sub_", "FUN_", "0x"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .and_then(parse_hex)
+        .and_then(rebase)
+}
+
+/// Loads the call graph of another tool into the same structure as cwe_checker's result.
+///
+/// The functions and indirect callsites are taken from the reference, so all tools are evaluated
+/// on the same callsites. Function level graphs are expanded: every indirect callsite of a function
+/// gets all callees of that function as targets, see [`ExternalFormat::is_function_granular`].
+///
+/// image_base is the address the tool loaded the binary at, see [`ExternalFormat::image_base`].
+/// The addresses of the tool are moved from there to address_base_offset. None keeps them as they
+/// are, for binaries analysed at their static addresses.
+pub fn load_external_call_graph(
+    format: ExternalFormat,
+    path: &Path,
+    reference: &CweCheckerResult,
+    image_base: Option<u64>,
+) -> Result<CweCheckerResult> {
+    let content = fs::read_to_string(path).map_err(Error::io(path))?;
+    let edges = match format {
+        ExternalFormat::Angr => parse_angr(&content).map_err(Error::json(path))?,
+        ExternalFormat::Ghidra => parse_ghidra(&content),
+        ExternalFormat::Radare2 => parse_radare2(&content).map_err(Error::json(path))?,
+        ExternalFormat::Bap => parse_bap(&content),
+    };
+    let indirect_call_sites = &reference.metadata.indirect_call_sites;
+    let address_base_offset = reference.metadata.address_base_offset;
+    // Addresses below the image base are dropped
+    let rebase = |address: u64| match image_base {
+        Some(image_base) => address.checked_sub(image_base).map(|offset| offset + address_base_offset),
+        None => Some(address),
+    };
+
+    let calls = match edges {
+        ImportedEdges::Callsite(edges) => edges
+            .into_iter()
+            .filter_map(|(callsite, target)| Some((rebase(callsite)?, rebase(target)?)))
+            .map(|(callsite, target)| Call {
+                from_instr: callsite,
+                to_instr: Some(target),
+                is_indirect: indirect_call_sites.contains(&callsite),
+            })
+            .collect::<Vec<Call>>(),
+        ImportedEdges::Function(edges) => {
+            let functions_by_name = reference
+                .metadata
+                .functions
+                .iter()
+                .map(|func| (func.name.as_str(), func.address))
+                .collect::<HashMap<&str, u64>>();
+            let mut callees: HashMap<u64, Vec<u64>> = HashMap::new();
+            for (caller, callee) in &edges {
+                let (Some(caller), Some(callee)) = (
+                    resolve_function(caller, &functions_by_name, rebase),
+                    resolve_function(callee, &functions_by_name, rebase),
+                ) else {
+                    continue;
+                };
+                callees.entry(caller).or_default().push(callee);
+            }
+
+            let mut calls = vec![];
+            for callsite in indirect_call_sites {
+                let Some(caller) = reference.get_function_containing(*callsite) else {
+                    continue;
+                };
+                for callee in callees.get(&caller.address).into_iter().flatten() {
+                    calls.push(Call {
+                        from_instr: *callsite,
+                        to_instr: Some(*callee),
+                        is_indirect: true,
+                    });
+                }
+            }
+            calls
+        }
+    };
+
+    Ok(CweCheckerResult::from_export_call_graph(ExportCallGraph {
+        metadata: Metadata {
+            address_base_offset,
+            indirect_call_sites: indirect_call_sites.clone(),
+            functions: reference.metadata.functions.clone(),
+        },
+        calls,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::cwe_checker::Function;
+
+    fn name(name: &str) -> FunctionRef {
+        FunctionRef::Name(name.to_string())
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let angr = r#"{"directed": true, "nodes": [{"id": 4198694}, {"id": 4198656}], "links": [{"source": 4198694, "target": 4198656}]}"#;
+        assert_eq!(
+            parse_angr(angr).unwrap(),
+            ImportedEdges::Function(vec![(FunctionRef::Address(0x401126), FunctionRef::Address(0x401100))])
+        );
+
+        let ghidra = "callsite,target\n0x401130,0x401100\n401134, 401110\n";
+        assert_eq!(parse_ghidra(ghidra), ImportedEdges::Callsite(vec![(0x401130, 0x401100), (0x401134, 0x401110)]));
+
+        let radare2 = r#"[{"name": "main", "size": 42, "imports": ["sym.handler", "sym.imp.puts"]}, {"name": "sym.handler"}]"#;
+        assert_eq!(
+            parse_radare2(radare2).unwrap(),
+            ImportedEdges::Function(vec![(name("main"), name("sym.handler")), (name("main"), name("sym.imp.puts"))])
+        );
+
+        let bap = "digraph {\n  \"@main\" -> \"@handler\";\n  \"sub_401100\"[shape=box] -> \"@puts\";\n}\n";
+        assert_eq!(
+            parse_bap(bap),
+            ImportedEdges::Function(vec![(name("@main"), name("@handler")), (name("sub_401100"), name("@puts"))])
+        );
+    }
+
+    #[test]
+    fn test_resolve_function() {
+        let functions_by_name = HashMap::from([("main", 0x401126), ("handler", 0x401100)]);
+        assert_eq!(resolve_function(&FunctionRef::Address(0x10), &functions_by_name, Some), Some(0x10));
+        assert_eq!(resolve_function(&name("@main"), &functions_by_name, Some), Some(0x401126));
+        assert_eq!(resolve_function(&name("sym.handler"), &functions_by_name, Some), Some(0x401100));
+        assert_eq!(resolve_function(&name("fcn.00401140"), &functions_by_name, Some), Some(0x401140));
+        assert_eq!(resolve_function(&name("FUN_00401150"), &functions_by_name, Some), Some(0x401150));
+        assert_eq!(resolve_function(&name("sym.imp.puts"), &functions_by_name, Some), None);
+
+        // Without any resolved callsite there is no average
+        let empty = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x401130],
+                functions: vec![Function {
+                    name: "main".to_string(),
+                    address: 0x401126,
+                }],
+            },
+            calls: vec![],
+        });
+        assert_eq!(empty.aict(), None);
+    }
+
+    #[test]
+    fn test_load_external_call_graph() {
+        let reference = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x401130, 0x401210],
+                functions: [("handler", 0x401100), ("main", 0x401126), ("other", 0x401200)]
+                    .iter()
+                    .map(|(name, address)| Function {
+                        name: name.to_string(),
+                        address: *address,
+                    })
+                    .collect(),
+            },
+            calls: vec![],
+        });
+        let dir = tempfile::tempdir().unwrap();
+
+        let ghidra = dir.path().join("ghidra.csv");
+        fs::write(&ghidra, "callsite,target\n0x401130,0x401100\n0x401140,0x401200\n").unwrap();
+        let result = load_external_call_graph(ExternalFormat::Ghidra, &ghidra, &reference, None).unwrap();
+        assert_eq!(result.get_call_site(0x401130).unwrap().targets, HashSet::from([0x401100]));
+        assert_eq!(result.metadata.indirect_call_sites, reference.metadata.indirect_call_sites);
+        assert!(!ExternalFormat::Ghidra.is_function_granular());
+
+        // Both callees of main become targets of its callsite, unknown names are dropped
+        let bap = dir.path().join("bap.dot");
+        fs::write(&bap, "digraph {\n  \"@main\" -> \"@handler\";\n  \"@main\" -> \"@other\";\n  \"@main\" -> \"@puts\";\n}\n").unwrap();
+        let result = load_external_call_graph(ExternalFormat::Bap, &bap, &reference, None).unwrap();
+        assert_eq!(result.get_call_site(0x401130).unwrap().targets, HashSet::from([0x401100, 0x401200]));
+        assert!(result.get_call_site(0x401210).is_none());
+        assert!(ExternalFormat::Bap.is_function_granular());
+
+        let err = load_external_call_graph(ExternalFormat::Angr, &bap, &reference, None).unwrap_err();
+        assert!(matches!(err, Error::Json { .. }));
+    }
+
+    #[test]
+    fn test_rebase_pie() {
+        // cwe_checker analyses PIE binaries at 0x100000, angr at 0x400000 and radare2 at 0
+        let reference = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0x100000,
+                indirect_call_sites: vec![0x101130],
+                functions: [("handler", 0x101100), ("main", 0x101126)]
+                    .iter()
+                    .map(|(name, address)| Function {
+                        name: name.to_string(),
+                        address: *address,
+                    })
+                    .collect(),
+            },
+            calls: vec![],
+        });
+        let mut elf = ElfInfo {
+            is_pie: true,
+            symbols: vec![],
+            sections: vec![],
+            plt_entries: vec![],
+            got_imports: HashMap::new(),
+            data_pointers: HashMap::new(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        let angr = dir.path().join("angr.json");
+        fs::write(&angr, r#"{"links": [{"source": 4198694, "target": 4198656}, {"source": 4198694, "target": 16}]}"#).unwrap();
+        let image_base = ExternalFormat::Angr.image_base(&elf);
+        assert_eq!(image_base, Some(0x400000));
+        let result = load_external_call_graph(ExternalFormat::Angr, &angr, &reference, image_base).unwrap();
+        assert_eq!(result.get_call_site(0x101130).unwrap().targets, HashSet::from([0x101100]));
+
+        let radare2 = dir.path().join("radare2.json");
+        fs::write(&radare2, r#"[{"name": "main", "imports": ["fcn.00001200", "sym.handler"]}]"#).unwrap();
+        let image_base = ExternalFormat::Radare2.image_base(&elf);
+        let result = load_external_call_graph(ExternalFormat::Radare2, &radare2, &reference, image_base).unwrap();
+        assert_eq!(result.get_call_site(0x101130).unwrap().targets, HashSet::from([0x101100, 0x101200]));
+
+        let ghidra = dir.path().join("ghidra.csv");
+        fs::write(&ghidra, "0x101130,0x101100\n").unwrap();
+        let image_base = ExternalFormat::Ghidra.image_base(&elf);
+        let result = load_external_call_graph(ExternalFormat::Ghidra, &ghidra, &reference, image_base).unwrap();
+        assert_eq!(result.get_call_site(0x101130).unwrap().targets, HashSet::from([0x101100]));
+
+        elf.is_pie = false;
+        assert_eq!(ExternalFormat::Angr.image_base(&elf), None);
+    }
+}
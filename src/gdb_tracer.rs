@@ -0,0 +1,217 @@
+use std::{collections::HashMap, fs, path::Path, process::Stdio, sync::LazyLock, time::Duration};
+
+use log::{info, warn};
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use regex::Regex;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    time::timeout,
+};
+
+use crate::{
+    cwe_checker::Metadata,
+    error::{Error, Result},
+    process_maps::{binary_mappings, is_position_independent, load_bias},
+    valgrind::{RealCall, ValgrindResult},
+};
+
+static PID_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"pid="(\d+)""#).unwrap());
+static FRAME_ADDRESS_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"frame=\{addr="0x([0-9a-fA-F]+)""#).unwrap());
+
+/// Minimal driver for `gdb --interpreter=mi`
+struct GdbMi {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    token: u64,
+    /// Set from the =thread-group-started record
+    pid: Option<u32>,
+}
+
+impl GdbMi {
+    fn spawn(binary: &Path) -> Result<GdbMi> {
+        let mut child = Command::new("gdb")
+            .args(["--interpreter=mi", "--nx", "--quiet"])
+            .arg(binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| Error::tool("gdb", err))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        Ok(GdbMi {
+            child,
+            stdin,
+            stdout,
+            token: 0,
+            pid: None,
+        })
+    }
+
+    async fn next_line(&mut self) -> Result<String> {
+        let line = self
+            .stdout
+            .next_line()
+            .await
+            .map_err(|err| Error::tool("gdb", err))?
+            .ok_or_else(|| Error::tool("gdb", "exited unexpectedly"))?;
+        if let Some(pid) = thread_group_pid(&line) {
+            self.pid = Some(pid);
+        }
+        Ok(line)
+    }
+
+    /// Sends a command and returns its result record, e.g. `^done,...`
+    async fn command(&mut self, command: &str) -> Result<String> {
+        self.token += 1;
+        let token = self.token.to_string();
+        self.stdin
+            .write_all(format!("{}{}\n", token, command).as_bytes())
+            .await
+            .map_err(|err| Error::tool("gdb", err))?;
+        loop {
+            let line = self.next_line().await?;
+            if let Some(result) = result_record(&line, &token) {
+                return result.map_err(|result| Error::tool("gdb", format!("command {} failed: {}", command, result)));
+            }
+        }
+    }
+
+    /// Waits for the next `*stopped` record
+    async fn stopped(&mut self) -> Result<String> {
+        loop {
+            let line = self.next_line().await?;
+            if line.starts_with("*stopped") {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+/// Pid of the binary from `=thread-group-started,id="i1",pid="1234"`
+fn thread_group_pid(line: &str) -> Option<u32> {
+    if !line.starts_with("=thread-group-started") {
+        return None;
+    }
+    PID_REGEX.captures(line)?[1].parse().ok()
+}
+
+/// The result record of the command with the token, `^error` records as Err
+fn result_record(line: &str, token: &str) -> Option<std::result::Result<String, String>> {
+    let result = line.strip_prefix(token)?;
+    if !result.starts_with('^') {
+        return None;
+    }
+    if result.starts_with("^error") {
+        return Some(Err(result.to_string()));
+    }
+    Some(Ok(result.to_string()))
+}
+
+fn frame_address(stopped: &str) -> Option<u64> {
+    let captures = FRAME_ADDRESS_REGEX.captures(stopped)?;
+    u64::from_str_radix(&captures[1], 16).ok()
+}
+
+async fn trace(gdb: &mut GdbMi, bias: u64, edges: &mut HashMap<(u64, u64), u64>) -> Result<()> {
+    loop {
+        gdb.command("-exec-continue").await?;
+        let stopped = gdb.stopped().await?;
+        if stopped.contains("reason=\"exited") {
+            return Ok(());
+        }
+        if !stopped.contains("reason=\"breakpoint-hit\"") {
+            // e.g. signal-received, continuing delivers the signal
+            continue;
+        }
+        let Some(callsite) = frame_address(&stopped) else {
+            continue;
+        };
+        gdb.command("-exec-step-instruction").await?;
+        let stopped = gdb.stopped().await?;
+        if let Some(target) = frame_address(&stopped) {
+            *edges.entry((callsite - bias, target)).or_default() += 1;
+        }
+    }
+}
+
+/// Runs the binary in gdb with a breakpoint on each indirect callsite, steps over every hit
+/// call and records where it went. gdb is killed after the timeout.
+pub async fn run_gdb_tracer(binary: &Path, metadata: &Metadata, time_limit: Duration) -> Result<ValgrindResult> {
+    let mut gdb = GdbMi::spawn(binary)?;
+    gdb.command("-gdb-set disable-randomization on").await?;
+    gdb.command("-gdb-set inferior-tty /dev/null").await?;
+    gdb.command("-interpreter-exec console \"starti\"").await?;
+    gdb.stopped().await?;
+
+    let pid = gdb.pid.ok_or_else(|| Error::tool("gdb", "did not report the pid of the binary"))?;
+    let maps_path = format!("/proc/{}/maps", pid);
+    let maps = fs::read_to_string(&maps_path).map_err(Error::io(maps_path))?;
+    let mappings = binary_mappings(&maps, binary);
+    let bias = load_bias(&mappings, is_position_independent(binary)?, metadata)
+        .ok_or_else(|| Error::tool("gdb", "the binary is not mapped"))?;
+    info!("Using load bias: {:#x}", bias);
+
+    for callsite in &metadata.indirect_call_sites {
+        gdb.command(&format!("-break-insert *{:#x}", callsite + bias)).await?;
+    }
+
+    let mut edges = HashMap::new();
+    match timeout(time_limit, trace(&mut gdb, bias, &mut edges)).await {
+        Ok(result) => result?,
+        Err(_) => {
+            warn!("gdb reached the timeout, using the calls seen so far");
+            // The binary still has the breakpoints inserted, it would not survive gdb anyway
+            if let Err(err) = kill(Pid::from_raw(pid as i32), Signal::SIGKILL) {
+                warn!("Failed to kill the traced binary: {}", err);
+            }
+        }
+    }
+    let _ignore = gdb.child.kill().await;
+
+    let in_binary = |addr: u64| mappings.iter().any(|(start, end)| *start <= addr && addr < *end);
+    let calls = edges
+        .into_iter()
+        .map(|((callsite, target), count)| RealCall {
+            from_instr: callsite,
+            to_instr: if in_binary(target) { target - bias } else { target },
+            in_fn: 0,
+            target_fn: 0,
+            does_jump_object_file: !in_binary(target),
+            call_count: count,
+            inclusive_cost: 0,
+        })
+        .collect();
+
+    Ok(ValgrindResult {
+        calls,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mi_records() {
+        assert_eq!(thread_group_pid(r#"=thread-group-started,id="i1",pid="4242""#), Some(4242));
+        assert_eq!(thread_group_pid(r#"=thread-group-added,id="i1""#), None);
+
+        assert_eq!(result_record("3^done", "3"), Some(Ok("^done".to_string())));
+        assert_eq!(result_record(r#"3^error,msg="No symbol table""#, "3"), Some(Err(r#"^error,msg="No symbol table""#.to_string())));
+        // Asynchronous records of the command and other tokens are skipped
+        assert_eq!(result_record("3*running,thread-id=\"all\"", "3"), None);
+        assert_eq!(result_record("13^done", "1"), None);
+
+        let stopped = r#"*stopped,reason="breakpoint-hit",disp="keep",bkptno="1",frame={addr="0x0000555555555189",func="dispatch",args=[]},thread-id="1""#;
+        assert_eq!(frame_address(stopped), Some(0x555555555189));
+        assert_eq!(frame_address(r#"*stopped,reason="exited-normally""#), None);
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    cwe_checker::{CweCheckerResult, Function},
+    soundness_test::offset_based_on_main,
+    valgrind::ValgrindResult,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Dot,
+    Graphml,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    PredictedAndObserved,
+    PredictedOnly,
+    /// Observed at runtime but missing in the static call graph, i.e. unsound
+    ObservedOnly,
+}
+
+impl EdgeKind {
+    fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::PredictedAndObserved => "predicted_and_observed",
+            EdgeKind::PredictedOnly => "predicted_only",
+            EdgeKind::ObservedOnly => "observed_only",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            EdgeKind::PredictedAndObserved => "darkgreen",
+            EdgeKind::PredictedOnly => "gray",
+            EdgeKind::ObservedOnly => "red",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub id: String,
+    pub name: String,
+    pub address: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+    pub indirect: bool,
+}
+
+pub struct ExportedGraph {
+    pub nodes: BTreeMap<String, Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Default)]
+struct EdgeState {
+    predicted: bool,
+    observed: bool,
+    indirect: bool,
+}
+
+fn function_node(func: &Function) -> Node {
+    Node {
+        id: format!("f_{:x}", func.address),
+        name: func.name.clone(),
+        address: Some(func.address),
+    }
+}
+
+/// The function starting at the target, other addresses are not known to be in the binary
+fn target_node(cwe_checker: &CweCheckerResult, addr: u64) -> Option<Node> {
+    cwe_checker.get_function_at(addr).map(function_node)
+}
+
+/// The function containing the callsite, if it is in the code of the binary at all
+fn caller_node(cwe_checker: &CweCheckerResult, addr: u64) -> Option<Node> {
+    let (start, end) = cwe_checker.address_range()?;
+    if addr < start || addr > end {
+        return None;
+    }
+    cwe_checker.get_function_containing(addr).map(function_node)
+}
+
+fn dynamic_node(name: String, fn_id: i64) -> Node {
+    Node {
+        id: format!("v_{}", fn_id),
+        name,
+        address: None,
+    }
+}
+
+/// Builds the function level call graph of both the static and the observed calls
+pub fn build_graph(cwe_checker: &CweCheckerResult, real: Option<&ValgrindResult>) -> ExportedGraph {
+    let mut nodes = BTreeMap::new();
+    let mut edges: HashMap<(String, String), EdgeState> = HashMap::new();
+
+    for func in &cwe_checker.metadata.functions {
+        let node = function_node(func);
+        nodes.insert(node.id.clone(), node);
+    }
+
+    for callsite in cwe_checker.call_sites() {
+        let Some(from) = caller_node(cwe_checker, callsite.callsite_loc) else {
+            continue;
+        };
+        let indirect = cwe_checker.metadata.indirect_call_sites.contains(&callsite.callsite_loc);
+        for target in &callsite.targets {
+            let Some(to) = target_node(cwe_checker, *target) else {
+                continue;
+            };
+            let edge = edges.entry((from.id.clone(), to.id.clone())).or_default();
+            edge.predicted = true;
+            edge.indirect |= indirect;
+        }
+    }
+
+    if let Some(real) = real {
+        let offset = offset_based_on_main(cwe_checker, real);
+        for call in &real.calls {
+            if call.does_jump_object_file {
+                continue;
+            }
+            let callsite = ((call.from_instr as i64) - offset) as u64;
+            let target = ((call.to_instr as i64) - offset) as u64;
+            let from = caller_node(cwe_checker, callsite).unwrap_or_else(|| dynamic_node(real.get_function_of_call(call), call.in_fn));
+            let to = target_node(cwe_checker, target).unwrap_or_else(|| dynamic_node(real.get_target_function_of_call(call), call.target_fn));
+
+            let edge = edges.entry((from.id.clone(), to.id.clone())).or_default();
+            edge.observed = true;
+            edge.indirect |= cwe_checker.metadata.indirect_call_sites.contains(&callsite);
+            nodes.entry(from.id.clone()).or_insert(from);
+            nodes.entry(to.id.clone()).or_insert(to);
+        }
+    }
+
+    let mut edges = edges
+        .into_iter()
+        .map(|((from, to), state)| Edge {
+            from,
+            to,
+            kind: match (state.predicted, state.observed) {
+                (true, true) => EdgeKind::PredictedAndObserved,
+                (true, false) => EdgeKind::PredictedOnly,
+                _ => EdgeKind::ObservedOnly,
+            },
+            indirect: state.indirect,
+        })
+        .collect::<Vec<Edge>>();
+    edges.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+
+    ExportedGraph { nodes, edges }
+}
+
+impl ExportedGraph {
+    /// Keeps only the nodes within depth edges (in either direction) of the function with the given name
+    pub fn restrict_to_neighbourhood(&self, function: &str, depth: usize) -> ExportedGraph {
+        let mut adjacent: HashMap<&str, Vec<&str>> = HashMap::new();
+        for edge in &self.edges {
+            adjacent.entry(&edge.from).or_default().push(&edge.to);
+            adjacent.entry(&edge.to).or_default().push(&edge.from);
+        }
+
+        let mut reached = HashSet::new();
+        let mut queue = self
+            .nodes
+            .values()
+            .filter(|node| node.name == function)
+            .map(|node| (node.id.as_str(), 0))
+            .collect::<VecDeque<(&str, usize)>>();
+        while let Some((id, distance)) = queue.pop_front() {
+            if !reached.insert(id) || distance == depth {
+                continue;
+            }
+            for next in adjacent.get(id).into_iter().flatten() {
+                queue.push_back((next, distance + 1));
+            }
+        }
+
+        ExportedGraph {
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|(id, _)| reached.contains(id.as_str()))
+                .map(|(id, node)| (id.clone(), node.clone()))
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|edge| reached.contains(edge.from.as_str()) && reached.contains(edge.to.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Dot => self.to_dot(),
+            ExportFormat::Graphml => self.to_graphml(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph callgraph {{").unwrap();
+        writeln!(out, "\tnode [shape=box];").unwrap();
+        for node in self.nodes.values() {
+            let label = match node.address {
+                Some(address) => format!("{}\\n{:#x}", escape_dot(&node.name), address),
+                None => escape_dot(&node.name),
+            };
+            writeln!(out, "\t\"{}\" [label=\"{}\"];", node.id, label).unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "\t\"{}\" -> \"{}\" [color={}, kind={}, style={}];",
+                edge.from,
+                edge.to,
+                edge.kind.color(),
+                edge.kind.name(),
+                if edge.indirect { "dashed" } else { "solid" }
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+        writeln!(out, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">").unwrap();
+        writeln!(out, "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>").unwrap();
+        writeln!(out, "  <key id=\"address\" for=\"node\" attr.name=\"address\" attr.type=\"string\"/>").unwrap();
+        writeln!(out, "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>").unwrap();
+        writeln!(out, "  <key id=\"color\" for=\"edge\" attr.name=\"color\" attr.type=\"string\"/>").unwrap();
+        writeln!(out, "  <key id=\"indirect\" for=\"edge\" attr.name=\"indirect\" attr.type=\"boolean\"/>").unwrap();
+        writeln!(out, "  <graph id=\"callgraph\" edgedefault=\"directed\">").unwrap();
+        for node in self.nodes.values() {
+            writeln!(out, "    <node id=\"{}\">", node.id).unwrap();
+            writeln!(out, "      <data key=\"name\">{}</data>", escape_xml(&node.name)).unwrap();
+            if let Some(address) = node.address {
+                writeln!(out, "      <data key=\"address\">{:#x}</data>", address).unwrap();
+            }
+            writeln!(out, "    </node>").unwrap();
+        }
+        for edge in &self.edges {
+            writeln!(out, "    <edge source=\"{}\" target=\"{}\">", edge.from, edge.to).unwrap();
+            writeln!(out, "      <data key=\"kind\">{}</data>", edge.kind.name()).unwrap();
+            writeln!(out, "      <data key=\"color\">{}</data>", edge.kind.color()).unwrap();
+            writeln!(out, "      <data key=\"indirect\">{}</data>", edge.indirect).unwrap();
+            writeln!(out, "    </edge>").unwrap();
+        }
+        writeln!(out, "  </graph>").unwrap();
+        writeln!(out, "</graphml>").unwrap();
+        out
+    }
+}
+
+fn escape_dot(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Metadata},
+        valgrind::{RealCall, ValgrindNameCache},
+    };
+
+    #[test]
+    fn test_build_graph() {
+        let function = |name: &str, address: u64| Function {
+            name: name.to_string(),
+            address,
+        };
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1010],
+                functions: vec![function("main", 0x1000), function("handler", 0x1100)],
+            },
+            calls: vec![Call {
+                from_instr: 0x1010,
+                to_instr: Some(0x1100),
+                is_indirect: true,
+            }],
+        });
+        let real_call = |to_instr: u64, target_fn: i64| RealCall {
+            from_instr: 0x1010,
+            to_instr,
+            in_fn: 1,
+            target_fn,
+            does_jump_object_file: false,
+            call_count: 1,
+            inclusive_cost: 0,
+        };
+        let real = ValgrindResult {
+            calls: vec![real_call(0x1100, 2), real_call(0x7000, 3)],
+            valgrind_name_cache: ValgrindNameCache {
+                name_cache: HashMap::from([(1, "main".to_string()), (2, "handler".to_string()), (3, "free".to_string())]),
+                ..Default::default()
+            },
+            base_address_mapping: HashMap::from([(1, 0x1000)]),
+            ..Default::default()
+        };
+
+        let graph = build_graph(&cwe_checker, Some(&real));
+        // The address after the last function is not attributed to it
+        assert_eq!(graph.nodes["v_3"].name, "free");
+        let edges = graph.edges.iter().map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.kind)).collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [("f_1000", "f_1100", EdgeKind::PredictedAndObserved), ("f_1000", "v_3", EdgeKind::ObservedOnly)]
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        let graph = ExportedGraph {
+            nodes: BTreeMap::from([(
+                "f_10".to_string(),
+                Node {
+                    id: "f_10".to_string(),
+                    name: "operator<<\"a\\b\"&'".to_string(),
+                    address: Some(0x10),
+                },
+            )]),
+            edges: vec![],
+        };
+        assert!(graph.to_dot().contains("[label=\"operator<<\\\"a\\\\b\\\"&'\\n0x10\"]"));
+        assert!(graph.to_graphml().contains("<data key=\"name\">operator&lt;&lt;&quot;a\\b&quot;&amp;&apos;</data>"));
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+};
+
+use crate::{
+    cwe_checker::CweCheckerResult,
+    elf::ElfInfo,
+    root_cause::root_cause_counts,
+    soundness_test::SoundnessReport,
+    text_report::symbolize,
+};
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+struct ObservedTarget {
+    name: String,
+    object: String,
+    sound: bool,
+}
+
+#[derive(Default)]
+struct CallsiteRow {
+    function: String,
+    static_targets: Vec<String>,
+    observed: Vec<ObservedTarget>,
+}
+
+impl CallsiteRow {
+    fn status(&self) -> &'static str {
+        if self.observed.is_empty() {
+            "not executed"
+        } else if self.observed.iter().all(|target| target.sound) {
+            "sound"
+        } else {
+            "unsound"
+        }
+    }
+}
+
+fn callsite_rows(report: &SoundnessReport, cwe_checker: &CweCheckerResult, elf: Option<&ElfInfo>, binary_name: &str) -> BTreeMap<u64, CallsiteRow> {
+    let function_of = |addr: u64| {
+        cwe_checker
+            .get_function_containing(addr)
+            .map(|function| function.name.clone())
+            .unwrap_or_else(|| "<unknown>".to_string())
+    };
+    let static_targets_of = |addr: u64| {
+        let mut targets = cwe_checker
+            .get_call_site(addr)
+            .map(|callsite| callsite.targets.into_iter().collect::<Vec<u64>>())
+            .unwrap_or_default();
+        targets.sort();
+        targets.into_iter().map(|target| symbolize(target, cwe_checker, elf)).collect()
+    };
+
+    let mut rows: BTreeMap<u64, CallsiteRow> = BTreeMap::new();
+    let callsites = cwe_checker
+        .metadata
+        .indirect_call_sites
+        .iter()
+        .chain(report.checked.iter().map(|call| &call.callsite));
+    for callsite in callsites {
+        rows.entry(*callsite).or_insert_with(|| CallsiteRow {
+            function: function_of(*callsite),
+            static_targets: static_targets_of(*callsite),
+            observed: vec![],
+        });
+    }
+    for call in &report.checked {
+        let object = match (&call.target_object, &call.target_symbol) {
+            (Some(object), _) => object.clone(),
+            (None, Some(_)) => "extern".to_string(),
+            (None, None) => binary_name.to_string(),
+        };
+        rows.get_mut(&call.callsite).unwrap().observed.push(ObservedTarget {
+            name: call.target_symbol.clone().unwrap_or_else(|| symbolize(call.target, cwe_checker, elf)),
+            object,
+            sound: call.sound,
+        });
+    }
+    rows
+}
+
+/// Collapsed list, the summary shows the number of entries
+fn target_list(targets: &[String]) -> String {
+    if targets.is_empty() {
+        return "0".to_string();
+    }
+    let items = targets.iter().map(|target| format!("<li>{}</li>", target)).collect::<String>();
+    format!("<details><summary>{}</summary><ul>{}</ul></details>", targets.len(), items)
+}
+
+fn options(values: &BTreeSet<&str>) -> String {
+    values
+        .iter()
+        .map(|value| format!("<option>{}</option>", escape(value)))
+        .collect()
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; vertical-align: top; }
+th { cursor: pointer; background: #eee; }
+tr.unsound { background: #fdd; }
+tr.sound { background: #dfd; }
+span.unsound { color: #b00; font-weight: bold; }
+dl { display: grid; grid-template-columns: max-content auto; gap: 0.2em 1em; }
+dd { margin: 0; font-family: monospace; }
+ul { margin: 0; padding-left: 1.2em; }
+";
+
+const SCRIPT: &str = "
+function sortTable(column) {
+  const table = document.getElementById('callsites');
+  const rows = Array.from(table.tBodies[0].rows);
+  const ascending = table.dataset.column != column || table.dataset.order != 'asc';
+  const key = row => row.cells[column].dataset.sort || row.cells[column].textContent;
+  rows.sort((a, b) => {
+    const x = key(a), y = key(b);
+    const order = isNaN(x) || isNaN(y) ? x.localeCompare(y) : x - y;
+    return ascending ? order : -order;
+  });
+  rows.forEach(row => table.tBodies[0].appendChild(row));
+  table.dataset.column = column;
+  table.dataset.order = ascending ? 'asc' : 'desc';
+}
+function applyFilters() {
+  const unsoundOnly = document.getElementById('unsound-only').checked;
+  const func = document.getElementById('function-filter').value;
+  const object = document.getElementById('object-filter').value;
+  for (const row of document.getElementById('callsites').tBodies[0].rows) {
+    row.hidden = (unsoundOnly && row.dataset.status != 'unsound')
+      || (func && row.dataset.function != func)
+      || (object && !row.dataset.objects.split('|').includes(object));
+  }
+}
+";
+
+pub fn render_html_report(report: &SoundnessReport, cwe_checker: &CweCheckerResult, elf: Option<&ElfInfo>) -> String {
+    let binary_name = report
+        .manifest
+        .as_ref()
+        .and_then(|manifest| manifest.binary.as_ref())
+        .and_then(|binary| Path::new(&binary.path).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "binary".to_string());
+    let rows = callsite_rows(report, cwe_checker, elf, &binary_name);
+    let functions = rows.values().map(|row| row.function.as_str()).collect::<BTreeSet<&str>>();
+    let objects = rows
+        .values()
+        .flat_map(|row| row.observed.iter().map(|target| target.object.as_str()))
+        .collect::<BTreeSet<&str>>();
+
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>Soundness report {}</title>", escape(&binary_name)).unwrap();
+    writeln!(out, "<style>{}</style>\n<script>{}</script>\n</head>\n<body>", STYLE, SCRIPT).unwrap();
+    writeln!(out, "<h1>Soundness report {}</h1>", escape(&binary_name)).unwrap();
+
+    let mut summary = report.manifest.as_ref().map(|manifest| manifest.summary()).unwrap_or_default();
+    let sound_percentage = report.sound_percentage().map(|percentage| format!(" ({:.1}%)", percentage)).unwrap_or_default();
+    summary.extend([
+        ("Checked calls", report.checked_calls.to_string()),
+        ("Sound calls", format!("{}{}", report.sound_calls, sound_percentage)),
+        ("Unsound edges", report.unsound_edges.len().to_string()),
+        ("AICT", report.aict.map(|aict| format!("{:.2}", aict)).unwrap_or_else(|| "-".to_string())),
+    ]);
+    let causes = root_cause_counts(report);
+    if !causes.is_empty() {
+        let causes = causes.iter().map(|(cause, count)| format!("{}: {}", cause, count)).collect::<Vec<String>>();
+        summary.push(("Root causes", causes.join(", ")));
+    }
+    writeln!(out, "<dl>").unwrap();
+    for (name, value) in summary {
+        writeln!(out, "<dt>{}</dt><dd>{}</dd>", name, escape(&value)).unwrap();
+    }
+    writeln!(out, "</dl>").unwrap();
+
+    writeln!(
+        out,
+        "<p><label><input type=\"checkbox\" id=\"unsound-only\" onchange=\"applyFilters()\"> Unsound only</label>
+<label>Function <select id=\"function-filter\" onchange=\"applyFilters()\"><option value=\"\">all</option>{}</select></label>
+<label>Object <select id=\"object-filter\" onchange=\"applyFilters()\"><option value=\"\">all</option>{}</select></label></p>",
+        options(&functions),
+        options(&objects)
+    )
+    .unwrap();
+
+    writeln!(out, "<table id=\"callsites\">\n<thead><tr>").unwrap();
+    for (column, name) in ["Callsite", "Function", "Status", "Static targets", "Observed targets"].iter().enumerate() {
+        writeln!(out, "<th onclick=\"sortTable({})\">{}</th>", column, name).unwrap();
+    }
+    writeln!(out, "</tr></thead>\n<tbody>").unwrap();
+    for (callsite, row) in &rows {
+        let observed = row
+            .observed
+            .iter()
+            .map(|target| {
+                let name = format!("{} <small>{}</small>", escape(&target.name), escape(&target.object));
+                if target.sound {
+                    name
+                } else {
+                    format!("<span class=\"unsound\">{}</span>", name)
+                }
+            })
+            .collect::<Vec<String>>();
+        let row_objects = row.observed.iter().map(|target| target.object.as_str()).collect::<BTreeSet<&str>>();
+        let static_targets = row.static_targets.iter().map(|target| escape(target)).collect::<Vec<String>>();
+        writeln!(
+            out,
+            "<tr class=\"{status}\" data-status=\"{status}\" data-function=\"{function}\" data-objects=\"{objects}\">\
+<td data-sort=\"{callsite}\">{location}</td><td>{function}</td><td>{status}</td>\
+<td data-sort=\"{static_count}\">{static_targets}</td><td data-sort=\"{observed_count}\">{observed}</td></tr>",
+            status = row.status(),
+            function = escape(&row.function),
+            objects = escape(&row_objects.into_iter().collect::<Vec<&str>>().join("|")),
+            callsite = callsite,
+            location = escape(&symbolize(*callsite, cwe_checker, elf)),
+            static_count = row.static_targets.len(),
+            static_targets = target_list(&static_targets),
+            observed_count = row.observed.len(),
+            observed = target_list(&observed),
+        )
+        .unwrap();
+    }
+    writeln!(out, "</tbody>\n</table>").unwrap();
+
+    if !report.unsound_edges.is_empty() {
+        writeln!(out, "<h2>Unsound edges</h2>\n<ul>").unwrap();
+        for edge in &report.unsound_edges {
+            let target = edge.target_symbol.clone().unwrap_or_else(|| symbolize(edge.target, cwe_checker, elf));
+            let mut details = vec![];
+            if let Some(source) = &edge.callsite_source {
+                details.push(format!("callsite at {}", source));
+            }
+            if let Some(source) = &edge.target_source {
+                details.push(format!("target at {}", source));
+            }
+            if !edge.call_stack.is_empty() {
+                details.push(format!("reached via {}", edge.call_stack.join(" -> ")));
+            }
+            writeln!(
+                out,
+                "<li>{} -&gt; {}{}<br><small>{}</small></li>",
+                escape(&symbolize(edge.callsite, cwe_checker, elf)),
+                escape(&target),
+                match edge.root_cause {
+                    Some(cause) => format!(" ({})", cause),
+                    None if edge.callsite_missing => " (callsite missing)".to_string(),
+                    None => String::new(),
+                },
+                escape(&details.join(", "))
+            )
+            .unwrap();
+        }
+        writeln!(out, "</ul>").unwrap();
+    }
+    writeln!(out, "</body>\n</html>").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Function, Metadata},
+        soundness_test::{CheckedCall, UnsoundEdge},
+    };
+
+    #[test]
+    fn test_escaping() {
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1010],
+                functions: vec![
+                    Function { name: "run<int>".to_string(), address: 0x1000 },
+                    Function { name: "operator<<".to_string(), address: 0x1100 },
+                ],
+            },
+            calls: vec![Call { from_instr: 0x1010, to_instr: Some(0x1100), is_indirect: true }],
+        });
+        let report = SoundnessReport {
+            checked_calls: 2,
+            sound_calls: 1,
+            aict: cwe_checker.aict(),
+            checked: vec![
+                CheckedCall { callsite: 0x1010, target: 0x1100, target_symbol: None, target_object: None, sound: true },
+                CheckedCall { callsite: 0x1010, target: 0x2000, target_symbol: Some("a&b".to_string()), target_object: None, sound: false },
+            ],
+            unsound_edges: vec![UnsoundEdge {
+                callsite: 0x1010,
+                target: 0x2000,
+                target_symbol: Some("a&b".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let html = render_html_report(&report, &cwe_checker, None);
+        assert!(html.contains("<option>run&lt;int&gt;</option>"));
+        assert!(html.contains("<td>run&lt;int&gt;</td><td>unsound</td>"));
+        assert!(html.contains("<li>operator&lt;&lt; (0x1100)</li>"));
+        assert!(html.contains("<span class=\"unsound\">a&amp;b <small>extern</small></span>"));
+        assert!(html.contains("<li>run&lt;int&gt;+0x10 (0x1010) -&gt; a&amp;b<br>"));
+        assert!(!html.contains("run<int>") && !html.contains("operator<<") && !html.contains("a&b"));
+    }
+}
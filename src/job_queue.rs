@@ -0,0 +1,605 @@
+//! Distributes soundness tests of many binaries over workers on other machines.
+//!
+//! Workers connect to the coordinator over TCP and exchange one JSON message per line. A job is
+//! followed by the raw bytes of the binary, so the workers need no shared file system. Workers
+//! authenticate with a shared token in every request. Jobs of failed or disconnected workers, and
+//! jobs whose lease expired, are handed out again until max_attempts is reached.
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsStr,
+    fs::{self, OpenOptions},
+    future::Future,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Notify,
+    task::JoinSet,
+    time::{interval, timeout},
+};
+
+use crate::{
+    build_subjects::split_binary_name,
+    error::{Error, Result},
+    soundness_test::SoundnessReport,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    /// File name of the binary, e.g. `ls_gcc_O2`
+    pub name: String,
+    /// Compiler and optimisation level, e.g. `gcc_O2`
+    pub variant: String,
+    /// Path on the coordinator
+    pub binary: PathBuf,
+    /// Starts at 1
+    pub attempt: u32,
+}
+
+impl Job {
+    /// `<binary>_<compiler>_<opt>` names get the variant `<compiler>_<opt>`
+    pub fn new(id: u64, binary: PathBuf) -> Job {
+        let name = binary.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let variant = split_binary_name(&name)
+            .map(|(_, compiler, opt)| format!("{}_{}", compiler, opt))
+            .unwrap_or_default();
+        Job {
+            id,
+            name,
+            variant,
+            binary,
+            attempt: 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobOutcome {
+    Success { report: Box<SoundnessReport> },
+    Failure { error: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorkerMessage {
+    Request { worker: String, token: String },
+    Result { job: u64, outcome: JobOutcome },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CoordinatorMessage {
+    /// Followed by `size` bytes of the binary
+    Job { job: Job, size: u64 },
+    /// All remaining jobs are running, one of them may be retried
+    Wait { seconds: u64 },
+    Done,
+    /// The connection is closed afterwards
+    Rejected { reason: String },
+}
+
+async fn send<W: AsyncWrite + Unpin, T: Serialize>(writer: &mut W, message: &T) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).unwrap();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+/// None if the connection was closed
+async fn receive<R: AsyncRead + Unpin, T: for<'de> Deserialize<'de>>(reader: &mut BufReader<R>) -> std::io::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<Job>,
+    /// Running jobs with the end of their lease
+    running: HashMap<u64, (Job, Instant)>,
+    reports: Vec<PathBuf>,
+    failed: usize,
+}
+
+struct Coordinator {
+    state: Mutex<QueueState>,
+    finished: Notify,
+    results: PathBuf,
+    max_attempts: u32,
+    /// Time a worker has for one job before it is handed out again
+    lease: Duration,
+    token: String,
+}
+
+impl Coordinator {
+    /// The next pending job, otherwise the message telling the worker to wait or stop
+    fn next_job(&self) -> std::result::Result<Job, CoordinatorMessage> {
+        let mut state = self.state.lock().unwrap();
+        match state.pending.pop_front() {
+            Some(job) => {
+                state.running.insert(job.id, (job.clone(), Instant::now() + self.lease));
+                Ok(job)
+            }
+            None if state.running.is_empty() => Err(CoordinatorMessage::Done),
+            None => Err(CoordinatorMessage::Wait { seconds: 5 }),
+        }
+    }
+
+    /// Requeues the job or gives up on it after max_attempts
+    fn fail(&self, job_id: u64, error: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some((mut job, _)) = state.running.remove(&job_id) else {
+            return Ok(());
+        };
+        warn!("{} failed in attempt {}: {}", job.name, job.attempt, error);
+        if job.attempt < self.max_attempts {
+            job.attempt += 1;
+            state.pending.push_back(job);
+            return Ok(());
+        }
+        state.failed += 1;
+        self.record(&job, "failed", None, error)?;
+        self.check_finished(&state);
+        Ok(())
+    }
+
+    fn succeed(&self, job_id: u64, report: &SoundnessReport) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let Some((job, _)) = state.running.remove(&job_id) else {
+            return Ok(());
+        };
+        info!("{} finished in attempt {}", job.name, job.attempt);
+        let report_path = self.results.join(format!("{}.json", job.name));
+        fs::write(&report_path, serde_json::to_string_pretty(report).unwrap()).map_err(Error::io(&report_path))?;
+        state.reports.push(report_path);
+        self.record(&job, "succeeded", Some(report), "")?;
+        self.check_finished(&state);
+        Ok(())
+    }
+
+    /// Fails the running jobs whose lease expired, a late result of their worker is ignored
+    fn expire_leases(&self) -> Result<()> {
+        let now = Instant::now();
+        let expired = {
+            let state = self.state.lock().unwrap();
+            state
+                .running
+                .iter()
+                .filter(|(_, (_, deadline))| *deadline <= now)
+                .map(|(job_id, _)| *job_id)
+                .collect::<Vec<u64>>()
+        };
+        for job_id in expired {
+            self.fail(job_id, &format!("lease of {} seconds expired", self.lease.as_secs()))?;
+        }
+        Ok(())
+    }
+
+    /// One line per finished job in `<results>/jobs.csv`
+    fn record(&self, job: &Job, status: &str, report: Option<&SoundnessReport>, error: &str) -> Result<()> {
+        let path = self.results.join("jobs.csv");
+        let csv_error = |err: csv::Error| Error::io(&path)(err.into());
+        let is_new = !path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(&path).map_err(Error::io(&path))?;
+        let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(file);
+        if is_new {
+            writer
+                .write_record(["name", "variant", "attempts", "status", "checked", "sound", "sound_percentage", "error"])
+                .map_err(csv_error)?;
+        }
+        let count = |count: fn(&SoundnessReport) -> i64| report.map(|report| count(report).to_string()).unwrap_or_default();
+        let sound_percentage = report.and_then(SoundnessReport::sound_percentage).map(|percentage| percentage.to_string());
+        writer
+            .write_record([
+                job.name.as_str(),
+                &job.variant,
+                &job.attempt.to_string(),
+                status,
+                &count(|report| report.checked_calls),
+                &count(|report| report.sound_calls),
+                &sound_percentage.unwrap_or_default(),
+                error,
+            ])
+            .map_err(csv_error)?;
+        writer.flush().map_err(Error::io(&path))
+    }
+
+    fn check_finished(&self, state: &QueueState) {
+        if state.pending.is_empty() && state.running.is_empty() {
+            self.finished.notify_one();
+        }
+    }
+
+    async fn serve(&self, stream: TcpStream) -> Result<()> {
+        let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        // Job of this worker, requeued if the connection breaks
+        let mut current = None;
+        let result = loop {
+            let message = match receive::<_, WorkerMessage>(&mut reader).await {
+                Ok(Some(message)) => message,
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            };
+            match message {
+                WorkerMessage::Request { worker, token } => {
+                    if token != self.token {
+                        warn!("Rejecting {} ({}), wrong token", worker, peer);
+                        let reason = "wrong token".to_string();
+                        let _ = send(&mut writer, &CoordinatorMessage::Rejected { reason }).await;
+                        break Ok(());
+                    }
+                    // The worker gave up on its previous job without reporting it
+                    if let Some(previous) = current.take() {
+                        self.fail(previous, &format!("worker {} ({}) requested another job", worker, peer))?;
+                    }
+                    let job = match self.next_job() {
+                        Ok(job) => job,
+                        Err(message) => {
+                            if let Err(err) = send(&mut writer, &message).await {
+                                break Err(err);
+                            }
+                            continue;
+                        }
+                    };
+                    info!("Sending {} to {} ({})", job.name, worker, peer);
+                    current = Some(job.id);
+                    let binary = match fs::read(&job.binary) {
+                        Ok(binary) => binary,
+                        Err(err) => {
+                            current = None;
+                            self.fail(job.id, &format!("could not read {}: {}", job.binary.display(), err))?;
+                            let _ = send(&mut writer, &CoordinatorMessage::Wait { seconds: 0 }).await;
+                            continue;
+                        }
+                    };
+                    let message = CoordinatorMessage::Job { job, size: binary.len() as u64 };
+                    if let Err(err) = async {
+                        send(&mut writer, &message).await?;
+                        writer.write_all(&binary).await
+                    }
+                    .await
+                    {
+                        break Err(err);
+                    }
+                }
+                WorkerMessage::Result { job, outcome } => {
+                    // Only the job handed to this worker, which also requires a valid token
+                    if current != Some(job) {
+                        warn!("Ignoring result of job {} from {}, it was not sent there", job, peer);
+                        continue;
+                    }
+                    current = None;
+                    match outcome {
+                        JobOutcome::Success { report } => self.succeed(job, &report)?,
+                        JobOutcome::Failure { error } => self.fail(job, &error)?,
+                    }
+                }
+            }
+        };
+        if let Some(job) = current {
+            self.fail(job, &format!("worker {} disconnected", peer))?;
+        }
+        result.map_err(|err| Error::tool("job queue", format!("connection to {} failed: {}", peer, err)))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Reports written by this run
+    pub reports: Vec<PathBuf>,
+}
+
+/// Hands out the jobs to workers connecting to address and returns when every job succeeded or
+/// failed max_attempts times. Jobs not reported within lease are handed out again. Reports are
+/// written to `<results>/<binary name>.json`.
+pub async fn run_coordinator(
+    address: &str,
+    token: &str,
+    jobs: Vec<Job>,
+    results: &Path,
+    max_attempts: u32,
+    lease: Duration,
+) -> Result<QueueSummary> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|err| Error::tool("job queue", format!("could not listen on {}: {}", address, err)))?;
+    info!("Waiting for workers on {}, {} jobs", address, jobs.len());
+    coordinate(listener, token, jobs, results, max_attempts, lease).await
+}
+
+async fn coordinate(
+    listener: TcpListener,
+    token: &str,
+    jobs: Vec<Job>,
+    results: &Path,
+    max_attempts: u32,
+    lease: Duration,
+) -> Result<QueueSummary> {
+    fs::create_dir_all(results).map_err(Error::io(results))?;
+
+    let coordinator = Arc::new(Coordinator {
+        state: Mutex::new(QueueState {
+            pending: jobs.into(),
+            ..Default::default()
+        }),
+        finished: Notify::new(),
+        results: results.to_path_buf(),
+        max_attempts,
+        lease,
+        token: token.to_string(),
+    });
+    coordinator.check_finished(&coordinator.state.lock().unwrap());
+
+    let mut connections = JoinSet::new();
+    let mut lease_check = interval((lease / 10).clamp(Duration::from_millis(10), Duration::from_secs(30)));
+    loop {
+        tokio::select! {
+            _ = coordinator.finished.notified() => break,
+            _ = lease_check.tick() => coordinator.expire_leases()?,
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else {
+                    continue;
+                };
+                let coordinator = coordinator.clone();
+                connections.spawn(async move {
+                    if let Err(err) = coordinator.serve(stream).await {
+                        warn!("{}", err);
+                    }
+                });
+            }
+        }
+    }
+    // Waiting workers ask again within a few seconds and are told to stop
+    let _ignore = timeout(Duration::from_secs(10), async { while connections.join_next().await.is_some() {} }).await;
+
+    let state = coordinator.state.lock().unwrap();
+    Ok(QueueSummary {
+        succeeded: state.reports.len(),
+        failed: state.failed,
+        reports: state.reports.clone(),
+    })
+}
+
+/// Takes jobs from the coordinator until there are none left. The binary of each job is stored in
+/// work_dir and passed to analyze with its job. Returns the number of processed jobs.
+pub async fn run_worker<F, Fut>(coordinator: &str, name: &str, token: &str, work_dir: &Path, mut analyze: F) -> Result<usize>
+where
+    F: FnMut(Job, PathBuf) -> Fut,
+    Fut: Future<Output = Result<SoundnessReport>>,
+{
+    let connection_error = |err: std::io::Error| Error::tool("job queue", format!("connection to {} failed: {}", coordinator, err));
+    let stream = TcpStream::connect(coordinator).await.map_err(connection_error)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    fs::create_dir_all(work_dir).map_err(Error::io(work_dir))?;
+
+    let mut processed = 0;
+    loop {
+        let request = WorkerMessage::Request { worker: name.to_string(), token: token.to_string() };
+        send(&mut writer, &request).await.map_err(connection_error)?;
+        let Some(message) = receive::<_, CoordinatorMessage>(&mut reader).await.map_err(connection_error)? else {
+            return Err(Error::tool("job queue", "the coordinator closed the connection"));
+        };
+        let (job, size) = match message {
+            CoordinatorMessage::Job { job, size } => (job, size),
+            CoordinatorMessage::Wait { seconds } => {
+                tokio::time::sleep(Duration::from_secs(seconds)).await;
+                continue;
+            }
+            CoordinatorMessage::Done => return Ok(processed),
+            CoordinatorMessage::Rejected { reason } => {
+                return Err(Error::tool("job queue", format!("{} rejected {}: {}", coordinator, name, reason)));
+            }
+        };
+
+        let mut binary = vec![0; size as usize];
+        reader.read_exact(&mut binary).await.map_err(connection_error)?;
+        // Keep the name, valgrind and the reports refer to it. Anything else than a plain file
+        // name could point outside of work_dir.
+        if Path::new(&job.name).file_name() != Some(OsStr::new(&job.name)) {
+            warn!("Refusing job {} with the invalid name {:?}", job.id, job.name);
+            let outcome = JobOutcome::Failure { error: format!("invalid binary name {:?}", job.name) };
+            send(&mut writer, &WorkerMessage::Result { job: job.id, outcome }).await.map_err(connection_error)?;
+            continue;
+        }
+        let binary_path = work_dir.join(&job.name);
+        fs::write(&binary_path, binary).map_err(Error::io(&binary_path))?;
+        fs::set_permissions(&binary_path, fs::Permissions::from_mode(0o755)).map_err(Error::io(&binary_path))?;
+
+        info!("Analysing {} (attempt {})", job.name, job.attempt);
+        let job_id = job.id;
+        let outcome = match analyze(job, binary_path.clone()).await {
+            Ok(report) => JobOutcome::Success { report: Box::new(report) },
+            Err(err) => JobOutcome::Failure { error: err.to_string() },
+        };
+        let _ignore = fs::remove_file(&binary_path);
+        send(&mut writer, &WorkerMessage::Result { job: job_id, outcome }).await.map_err(connection_error)?;
+        processed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_variant() {
+        assert_eq!(Job::new(0, PathBuf::from("bins/ls_gcc_O2")).variant, "gcc_O2");
+        assert_eq!(Job::new(1, PathBuf::from("bins/ls")).variant, "");
+    }
+
+    fn report() -> SoundnessReport {
+        SoundnessReport {
+            checked_calls: 1,
+            sound_calls: 1,
+            ..Default::default()
+        }
+    }
+
+    type Connection = (BufReader<tokio::net::tcp::OwnedReadHalf>, tokio::net::tcp::OwnedWriteHalf);
+
+    /// Connects with the token and requests one job
+    async fn take_job(address: &str, token: &str) -> (Connection, CoordinatorMessage) {
+        let (reader, writer) = TcpStream::connect(address).await.unwrap().into_split();
+        let mut connection = (BufReader::new(reader), writer);
+        let message = request(&mut connection, token).await;
+        (connection, message)
+    }
+
+    /// Requests another job on the connection, skipping the bytes of a previous one
+    async fn request(connection: &mut Connection, token: &str) -> CoordinatorMessage {
+        let (reader, writer) = connection;
+        send(writer, &WorkerMessage::Request { worker: "test".to_string(), token: token.to_string() }).await.unwrap();
+        let message = receive(reader).await.unwrap().unwrap();
+        if let CoordinatorMessage::Job { size, .. } = &message {
+            reader.read_exact(&mut vec![0; *size as usize]).await.unwrap();
+        }
+        message
+    }
+
+    fn write_jobs(dir: &Path, names: &[&str]) -> Vec<Job> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| {
+                let binary = dir.join(name);
+                fs::write(&binary, name).unwrap();
+                Job::new(id as u64, binary)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = write_jobs(dir.path(), &["a_gcc_O0", "b_gcc_O0", "c_gcc_O0"]);
+        let results = dir.path().join("results");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = tokio::spawn(async move { coordinate(listener, "secret", jobs, &results, 3, Duration::from_secs(60)).await });
+
+        let (_, message) = take_job(&address, "wrong").await;
+        assert!(matches!(message, CoordinatorMessage::Rejected { .. }));
+        // The worker disconnects after receiving the job, it is handed out again
+        let (connection, message) = take_job(&address, "secret").await;
+        assert!(matches!(message, CoordinatorMessage::Job { job: Job { id: 0, attempt: 1, .. }, .. }));
+        drop(connection);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // Requesting another job without reporting b hands b out again
+        let (mut connection, message) = take_job(&address, "secret").await;
+        assert!(matches!(message, CoordinatorMessage::Job { job: Job { id: 1, attempt: 1, .. }, .. }));
+        let message = request(&mut connection, "secret").await;
+        assert!(matches!(message, CoordinatorMessage::Job { job: Job { id: 2, attempt: 1, .. }, .. }));
+        drop(connection);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // b succeeds in its second attempt, c always fails
+        let mut attempts = vec![];
+        let processed = run_worker(&address, "worker", "secret", &dir.path().join("work"), |job, binary| {
+            let content = fs::read_to_string(binary).unwrap();
+            attempts.push((content.clone(), job.attempt));
+            async move {
+                match (content.as_str(), job.attempt) {
+                    ("a_gcc_O0", _) | ("b_gcc_O0", 2) => Ok(report()),
+                    _ => Err(Error::Config("analysis failed, \"twice\"\nor more".to_string())),
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(processed, 4);
+        attempts.sort();
+        let expected = [("a_gcc_O0", 2), ("b_gcc_O0", 2), ("c_gcc_O0", 2), ("c_gcc_O0", 3)];
+        assert_eq!(attempts, expected.map(|(name, attempt)| (name.to_string(), attempt)));
+
+        let summary = coordinator.await.unwrap().unwrap();
+        assert_eq!((summary.succeeded, summary.failed), (2, 1));
+        let results = dir.path().join("results");
+        assert_eq!(summary.reports, [results.join("a_gcc_O0.json"), results.join("b_gcc_O0.json")]);
+        let jobs_csv = fs::read_to_string(results.join("jobs.csv")).unwrap();
+        assert!(jobs_csv.starts_with("name,variant,attempts,status,checked,sound,sound_percentage,error\n"));
+        assert!(jobs_csv.contains("a_gcc_O0,gcc_O0,2,succeeded,1,1,100,\n"));
+        let rows = csv::Reader::from_reader(jobs_csv.as_bytes())
+            .records()
+            .map(|record| record.unwrap().iter().map(str::to_string).collect::<Vec<String>>())
+            .collect::<Vec<_>>();
+        let failed = rows.iter().find(|row| row[0] == "c_gcc_O0").unwrap();
+        assert_eq!(failed[2..], ["3", "failed", "", "", "", "analysis failed, \"twice\"\nor more"]);
+    }
+
+    #[tokio::test]
+    async fn test_lease() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = write_jobs(dir.path(), &["a_gcc_O0"]);
+        let results = dir.path().join("results");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = tokio::spawn(async move { coordinate(listener, "secret", jobs, &results, 2, Duration::from_millis(100)).await });
+
+        // A hung worker keeps the connection open without reporting
+        let (mut hung, message) = take_job(&address, "secret").await;
+        assert!(matches!(message, CoordinatorMessage::Job { job: Job { attempt: 1, .. }, .. }));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut attempts = vec![];
+        let processed = run_worker(&address, "worker", "secret", &dir.path().join("work"), |job, _| {
+            attempts.push(job.attempt);
+            async { Ok(report()) }
+        })
+        .await
+        .unwrap();
+        assert_eq!((processed, attempts), (1, vec![2]));
+        // The late result of the hung worker is ignored
+        let (_, writer) = &mut hung;
+        send(writer, &WorkerMessage::Result { job: 0, outcome: JobOutcome::Failure { error: "late".to_string() } }).await.unwrap();
+        drop(hung);
+
+        let summary = coordinator.await.unwrap().unwrap();
+        assert_eq!((summary.succeeded, summary.failed), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_job_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let work_dir = dir.path().join("work");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        // Answers like a coordinator handing out a job named `../escape`
+        let coordinator = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            let _: WorkerMessage = receive(&mut reader).await.unwrap().unwrap();
+            let mut job = Job::new(0, PathBuf::from("escape"));
+            job.name = "../escape".to_string();
+            send(&mut writer, &CoordinatorMessage::Job { job, size: 4 }).await.unwrap();
+            writer.write_all(b"\x7fELF").await.unwrap();
+            let outcome: WorkerMessage = receive(&mut reader).await.unwrap().unwrap();
+            let _: WorkerMessage = receive(&mut reader).await.unwrap().unwrap();
+            send(&mut writer, &CoordinatorMessage::Done).await.unwrap();
+            outcome
+        });
+
+        let processed = run_worker(&address, "worker", "secret", &work_dir, |_, _| async { Ok(report()) }).await.unwrap();
+        assert_eq!(processed, 0);
+        assert!(!dir.path().join("escape").exists());
+        let outcome = coordinator.await.unwrap();
+        assert!(matches!(outcome, WorkerMessage::Result { job: 0, outcome: JobOutcome::Failure { .. } }));
+    }
+}
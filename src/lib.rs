@@ -0,0 +1,44 @@
+//! Checks the indirect call graph of a static analysis, e.g. cwe_checker, against the calls
+//! observed while running the binary.
+//!
+//! The loaders return the observed calls as [`ValgrindResult`], independent of the tracer.
+//! [`soundness`] compares them with a [`CweCheckerResult`] and returns a [`SoundnessReport`].
+//! [`run::run_soundness`] does all steps of the CLI, from the binary to the stored report.
+pub mod error;
+pub mod valgrind_parser;
+pub mod valgrind;
+pub mod cwe_checker;
+pub mod load_from_callee_csv;
+pub mod call_graph_diff;
+pub mod graph_export;
+pub mod dynamic_call_graph;
+pub mod reachability;
+pub mod process_maps;
+#[cfg(target_arch = "x86_64")]
+pub mod ptrace_tracer;
+pub mod qemu_trace;
+pub mod gdb_tracer;
+pub mod external_call_graph;
+pub mod elf;
+pub mod call_classification;
+pub mod source_lines;
+pub mod text_report;
+pub mod html_report;
+pub mod regression_check;
+pub mod cache;
+pub mod manifest;
+pub mod remote;
+pub mod job_queue;
+pub mod results_db;
+pub mod benchmark_log;
+pub mod plots;
+pub mod build_subjects;
+pub mod micro_benchmarks;
+pub mod root_cause;
+pub mod soundness_test;
+pub mod run;
+
+pub use cwe_checker::CweCheckerResult;
+pub use error::{Error, Result};
+pub use soundness_test::{soundness, SoundnessOptions, SoundnessReport};
+pub use valgrind::ValgrindResult;
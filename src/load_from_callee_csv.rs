@@ -2,54 +2,42 @@ use std::{collections::HashMap, path::PathBuf};
 
 use nom::{bytes::tag, number::complete::hex_u32, sequence::preceded, IResult, Parser};
 
-use crate::valgrind::{RealCall, ValgrindNameCache, ValgrindResult};
+use crate::{
+    error::{Error, Result},
+    valgrind::{RealCall, ValgrindResult},
+};
 
-#[derive(Debug, serde::Deserialize)]
-struct Record {
-    from_instr: i64,
-    to_instr: i64,
-    binary_name: String,
-}
-
-//#[derive(Clone, Debug, PartialEq, Eq)]
-//pub struct RealCall {
-//    pub from_instr: u64,
-//    pub to_instr: u64,
-//    pub in_fn: i64,
-//    pub target_fn: i64,
-//    pub does_jump_object_file: bool,
-//}
-//
-//impl Display for RealCall {
-//    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//        write!(f, "{:#x} -> {:#x} @ {}", self.from_instr, self.to_instr, self.in_fn)
-//    }
-//}
 fn parse_hex(input: &str) -> IResult<&str, u64> {
     preceded(tag("0x"), hex_u32)
         .parse(input)
-        .and_then(|result| Ok((result.0, result.1 as u64)))
+        .map(|result| (result.0, result.1 as u64))
 }
 
-pub fn load_callee_from_csv(path: &PathBuf, binary_name: &str) -> ValgrindResult {
+/// Observed calls of every binary in the CSV, by the object name in the third column
+pub fn load_callee_csv(path: &PathBuf) -> Result<HashMap<String, ValgrindResult>> {
+    let parse_error = |message: String| Error::Parse { path: path.clone(), message };
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false)
         .from_path(path)
-        .unwrap();
+        .map_err(|err| parse_error(err.to_string()))?;
 
-    let mut calls = vec![];
+    let mut calls: HashMap<String, Vec<RealCall>> = HashMap::new();
 
     for result in rdr.records() {
-        let record = result.unwrap();
-        let (_, from_instr): (&str, u64) =
-            parse_hex(record.get(0).unwrap()).expect("Failed parsing hex");
-        let (_, to_instr): (&str, u64) =
-            parse_hex(record.get(1).unwrap()).expect("Failed parsing hex");
-        let src_obj = record.get(2).unwrap();
-
-        if binary_name != src_obj {
-            continue;
-        }
+        let record = result.map_err(|err| parse_error(err.to_string()))?;
+        let column = |index: usize| {
+            record
+                .get(index)
+                .ok_or_else(|| parse_error(format!("expected 3 columns: {:?}", record)))
+        };
+        let hex_column = |index: usize| {
+            parse_hex(column(index)?)
+                .map(|(_, value)| value)
+                .map_err(|err| parse_error(format!("not a hex address: {}", err)))
+        };
+        let from_instr = hex_column(0)?;
+        let to_instr = hex_column(1)?;
+        let src_obj = column(2)?;
 
         let new_call = RealCall {
             from_instr: (from_instr + 0x400000),
@@ -57,13 +45,16 @@ pub fn load_callee_from_csv(path: &PathBuf, binary_name: &str) -> ValgrindResult
             does_jump_object_file: false,
             in_fn: 0,
             target_fn: 0,
+            call_count: 1,
+            inclusive_cost: 0,
         };
-        calls.push(new_call);
+        calls.entry(src_obj.to_string()).or_default().push(new_call);
     }
 
-    ValgrindResult {
-        calls,
-        valgrind_name_cache: ValgrindNameCache::new(),
-        base_address_mapping: HashMap::new()
-    }
+    Ok(calls.into_iter().map(|(binary_name, calls)| (binary_name, ValgrindResult { calls, ..Default::default() })).collect())
+}
+
+/// Observed calls of one binary, none if the CSV does not contain it
+pub fn load_callee_from_csv(path: &PathBuf, binary_name: &str) -> Result<ValgrindResult> {
+    Ok(load_callee_csv(path)?.remove(binary_name).unwrap_or_default())
 }
@@ -1,32 +1,131 @@
-pub mod valgrind_parser;
-pub mod valgrind;
-pub mod cwe_checker;
-pub mod load_from_callee_csv;
-mod soudness_test;
-use std::{ fs, path::{Path, PathBuf}};
-use chrono::Local;
-use cwe_checker::{complete_analysis, get_analysis_results, setup_hetzner_server};
-use load_from_callee_csv::load_callee_from_csv;
-use soudness_test::soundness;
-use valgrind::{analyze_valgrind, run_valgrind};
-
-use clap::{arg, Parser, Subcommand};
-
-/// Search for a pattern in a file and display the lines that contain it.
+use std::{fs, path::{Path, PathBuf}};
+use soundness_testing_valgrind::{
+    benchmark_log::{load_benchmark_logs, output_files},
+    build_subjects::{build_subjects, split_binary_name, BuildManifest},
+    cache::{ArtifactCache, CacheMode},
+    call_graph_diff::{diff, render_diff},
+    cwe_checker::get_analysis_results,
+    elf::ElfInfo,
+    error::Error,
+    external_call_graph::{load_external_call_graph, ExternalFormat},
+    graph_export::{build_graph, ExportFormat},
+    html_report::render_html_report,
+    job_queue::{run_coordinator, run_worker, Job},
+    load_from_callee_csv::load_callee_csv,
+    micro_benchmarks::{load_expected_edges, verify_expected_edges, write_benchmarks, EdgeStatus, Pattern},
+    plots::render_figures,
+    reachability::{reachability, render_reachability},
+    regression_check::{check_against_baseline, render_regressions, Thresholds},
+    remote::RemoteHost,
+    results_db::{QueryView, ResultsDb},
+    run::{load_call_graph, load_real_calls, parse_hex_arg, run_soundness, GroundTruthArgs, Run, Tracer},
+    soundness_test::{soundness, SoundnessOptions, SoundnessReport},
+    text_report::render_text_report,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use log::{error, info, warn, LevelFilter};
+
+/// Checks the indirect call graph of cwe_checker and other static analyses against the calls
+/// observed while running the binary
 #[derive(Parser)]
 struct Cli {
     /// Subcommands for different tasks
     #[command(subcommand)]
     command: Commands,
+
+    /// More diagnostics on stderr, -v for progress, -vv for every checked call
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors on stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Always run cwe_checker and valgrind, without reading or writing the cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Run cwe_checker and valgrind again and replace the cached results
+    #[arg(long, global = true, conflicts_with = "no_cache")]
+    refresh: bool,
+
+    /// Folder of the cached cwe_checker and valgrind results
+    #[arg(long, global = true, default_value = "cache")]
+    cache_dir: PathBuf,
+
+    /// Run cwe_checker on this host over ssh, as `[user@]host`. The host needs nix with flakes
+    #[arg(long, global = true)]
+    remote_host: Option<String>,
+
+    /// ssh program and options for --remote-host, split at whitespace
+    #[arg(long, global = true, default_value = "ssh")]
+    ssh_command: String,
+
+    /// scp program and options for --remote-host, split at whitespace
+    #[arg(long, global = true, default_value = "scp")]
+    scp_command: String,
+
+    /// SQLite database the soundness results are added to
+    #[arg(long, global = true)]
+    results_db: Option<PathBuf>,
+}
+
+impl Cli {
+    fn log_level(&self) -> LevelFilter {
+        if self.quiet {
+            return LevelFilter::Error;
+        }
+        match self.verbose {
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    fn cache(&self) -> ArtifactCache {
+        let mode = if self.no_cache {
+            CacheMode::Disabled
+        } else if self.refresh {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Use
+        };
+        ArtifactCache::new(self.cache_dir.clone(), mode)
+    }
+
+    fn remote(&self) -> Result<Option<RemoteHost>, Error> {
+        let split = |flag: &str, command: &str| {
+            let program = command.split_whitespace().map(str::to_string).collect::<Vec<String>>();
+            if program.is_empty() {
+                return Err(Error::Config(format!("{} must name a program", flag)));
+            }
+            Ok(program)
+        };
+        let Some(destination) = &self.remote_host else {
+            return Ok(None);
+        };
+        let mut host = RemoteHost::new(destination);
+        host.ssh = split("--ssh-command", &self.ssh_command)?;
+        host.scp = split("--scp-command", &self.scp_command)?;
+        Ok(Some(host))
+    }
+}
+
+/// `<format>=<path>`, e.g. `angr=callgraph.json`
+fn parse_graph_arg(arg: &str) -> Result<(ExternalFormat, PathBuf), String> {
+    let (format, path) = arg.split_once('=').ok_or("Expected <format>=<path>")?;
+    Ok((ExternalFormat::from_str(format, true)?, PathBuf::from(path)))
 }
 
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Subcommand to run the CWE checker
-    ShowHetznerHelp {
+    /// Run cwe_checker, on --remote-host if set, and store the call graph in the cache
+    CweChecker {
         /// Path to the binary to check
-        binary_path: String,
-        
+        binary_path: PathBuf,
     },
 
     /// Subcommand to use callee 
@@ -38,13 +137,165 @@ enum Commands {
         #[arg(long)]
         cwe_checker_result: Option<PathBuf>,
 
+        #[command(flatten)]
+        ground_truth: GroundTruthArgs,
+
+        /// Also check direct calls and tail-call jumps against the call graph
+        #[arg(long)]
+        all_calls: bool,
+
+        /// Write the report including the unsound edges as JSON
+        #[arg(long)]
+        json: Option<PathBuf>,
+
+        /// Write a self-contained HTML report
+        #[arg(long)]
+        html: Option<PathBuf>,
+    },
+
+    /// Run the soundness test and compare it against a baseline written with --json.
+    /// Exits with 2 on new unsound edges, 3 if the soundness dropped, 4 if the AICT increased
+    Check {
+        /// Path to the binary to check
+        #[arg(long)]
+        binary_path: Option<PathBuf>,
+
+        #[arg(long)]
+        cwe_checker_result: Option<PathBuf>,
+
+        #[command(flatten)]
+        ground_truth: GroundTruthArgs,
+
+        /// Report of an earlier soundness-test --json run
+        #[arg(long)]
+        baseline: PathBuf,
+
+        /// Allowed drop of the sound percentage in percentage points
+        #[arg(long, default_value_t = 0.0)]
+        max_soundness_drop: f32,
+
+        /// Allowed increase of the AICT
+        #[arg(long, default_value_t = 0.0)]
+        max_aict_increase: f32,
+    },
+
+    /// Hand out the soundness tests of all binaries in a folder to workers
+    Coordinator {
+        /// Binaries named `<binary>_<compiler>_<opt>`
+        #[arg(long)]
+        binaries: PathBuf,
+
+        /// Use e.g. `0.0.0.0:7878` for workers on other machines
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+
+        /// Shared secret the workers have to send
+        #[arg(long)]
+        token: String,
+
+        /// Reports as `<binary name>.json` and one line per job in jobs.csv
+        #[arg(long, default_value = "results")]
+        results: PathBuf,
+
+        /// Attempts per job before it is given up
+        #[arg(long, default_value_t = 3)]
+        max_attempts: u32,
+
+        /// Minutes a worker has for one job before it is handed out again
+        #[arg(long, default_value_t = 120)]
+        lease_minutes: u64,
+    },
+
+    /// Run soundness tests for a coordinator until it has no jobs left
+    Worker {
+        /// Address of the coordinator, e.g. `10.0.0.2:7878`
+        #[arg(long)]
+        coordinator: String,
+
+        /// Shown in the logs of the coordinator, the host name by default
         #[arg(long)]
-        valgrind_output: Option<PathBuf>,
+        name: Option<String>,
 
+        /// --token of the coordinator
         #[arg(long)]
-        callee_csv: Option<PathBuf>,
+        token: String,
+
+        /// Where the binaries of the jobs are stored
+        #[arg(long, default_value = "worker")]
+        work_dir: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = Tracer::Valgrind)]
+        tracer: Tracer,
+
+        /// Also check direct calls and tail-call jumps against the call graph
         #[arg(long)]
-        callee_bin_name: Option<String>
+        all_calls: bool,
+    },
+
+    /// Show a view of the results database given with --results-db as CSV
+    Query {
+        #[arg(value_enum)]
+        view: QueryView,
+
+        /// Maximum number of rows
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Build the test subjects of a JSON manifest with every compiler, optimisation level and option
+    /// combination, named `<subject>_<compiler>_<opt>[-<option>...]`. The flags are written to builds.json
+    BuildSubjects {
+        manifest: PathBuf,
+
+        #[arg(long, default_value = "subjects")]
+        output: PathBuf,
+    },
+
+    /// Write C programs with known indirect call targets, their expected edges and a build-subjects manifest
+    GenerateBenchmarks {
+        #[arg(long, default_value = "micro_benchmarks")]
+        output: PathBuf,
+
+        /// Patterns to generate, all by default
+        #[arg(long, value_enum)]
+        pattern: Vec<Pattern>,
+
+        /// Targets per indirect call
+        #[arg(long, default_value_t = 4)]
+        targets: usize,
+
+        /// Functions the pointer is passed through for the heap, global and stack patterns
+        #[arg(long, default_value_t = 3)]
+        depth: usize,
+    },
+
+    /// Check the call graphs of built micro-benchmarks against their expected edges, as CSV
+    VerifyBenchmarks {
+        /// Folder with the `<pattern>.expected.json` files
+        #[arg(long)]
+        expected: PathBuf,
+
+        /// Binaries named `<pattern>_<compiler>_<opt>`
+        binaries: Vec<PathBuf>,
+    },
+
+    /// Draw the figures of the evaluation as SVG: cwe_checker runtime and memory usage against the
+    /// program size, the AICT distribution and, with --results-db, the soundness per optimisation level.
+    /// The benchmarks are also stored in the --results-db
+    Plot {
+        /// Folder of cwe_checker outputs with the --debug log of the benchmark build, e.g. `cwe_output`
+        /// of runs with --no-cache. The cwe_checker results in --cache-dir by default
+        #[arg(long)]
+        cwe_checker_outputs: Option<PathBuf>,
+
+        #[arg(long, default_value = "output")]
+        output: PathBuf,
+    },
+
+    /// Manage the cache of cwe_checker and valgrind results
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
     },
 
     ProcessCalleeResults {
@@ -53,69 +304,427 @@ enum Commands {
 
         #[arg(long)]
         callee_csv: PathBuf,
-    }
+    },
+
+    /// Compare two cwe_checker results of the same binary
+    Diff {
+        /// The result used as reference
+        #[arg(long)]
+        old: PathBuf,
+
+        #[arg(long)]
+        new: PathBuf,
+
+        /// Binary to trace, if the soundness changes should be reported
+        #[arg(long)]
+        binary_path: Option<PathBuf>,
+
+        #[command(flatten)]
+        ground_truth: GroundTruthArgs,
+    },
+
+    /// Export the static and the observed call graph for Graphviz or Gephi
+    Export {
+        #[arg(long)]
+        cwe_checker_result: PathBuf,
+
+        /// Binary to trace, without any ground truth only the static graph is exported
+        #[arg(long)]
+        binary_path: Option<PathBuf>,
+
+        #[command(flatten)]
+        ground_truth: GroundTruthArgs,
+
+        #[arg(long, value_enum, default_value_t = ExportFormat::Dot)]
+        format: ExportFormat,
+
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Only export the neighbourhood of this function
+        #[arg(long)]
+        function: Option<String>,
+
+        /// Number of edges to follow from --function
+        #[arg(long, default_value_t = 2)]
+        depth: usize,
+    },
+
+    /// Check if every executed function is reachable in the static call graph
+    Reachability {
+        /// Path to the binary to check
+        #[arg(long)]
+        binary_path: Option<PathBuf>,
+
+        #[arg(long)]
+        cwe_checker_result: Option<PathBuf>,
+
+        #[command(flatten)]
+        ground_truth: GroundTruthArgs,
+
+        /// Additional root functions, e.g. exported symbols. main and the entry point are always used
+        #[arg(long)]
+        root: Vec<String>,
+    },
+
+    /// Look up the function and section of addresses in a binary
+    Symbolize {
+        #[arg(long)]
+        binary_path: PathBuf,
+
+        /// Load base of the binary, if the addresses are runtime addresses of a PIE binary
+        #[arg(long, value_parser = parse_hex_arg)]
+        load_base: Option<u64>,
+
+        /// Hex addresses
+        #[arg(value_parser = parse_hex_arg)]
+        addresses: Vec<u64>,
+    },
+
+    /// Rank call graphs of several static analyses against the same trace. Function level graphs
+    /// are marked, their soundness is inflated by giving a callsite all callees of its function
+    RankAnalyses {
+        /// Path to the binary to check, also needed to rebase the graphs of PIE binaries
+        #[arg(long)]
+        binary_path: Option<PathBuf>,
+
+        /// The cwe_checker result, its functions and indirect callsites are used for all graphs
+        #[arg(long)]
+        cwe_checker_result: Option<PathBuf>,
+
+        /// Call graph of another tool as <format>=<path>, formats: angr, ghidra, radare2, bap
+        #[arg(long, value_parser = parse_graph_arg)]
+        graph: Vec<(ExternalFormat, PathBuf)>,
+
+        #[command(flatten)]
+        ground_truth: GroundTruthArgs,
+    },
 }
 
-#[tokio::main]
-async fn main() {
-    let args = Cli::parse();
-    match args.command {
-        Commands::ShowHetznerHelp { binary_path } => {
-            let bin_to_analyis = PathBuf::from(binary_path);
-            setup_hetzner_server(bin_to_analyis);
-        },
-        Commands::SoundnessTest { binary_path, cwe_checker_result, callee_csv, callee_bin_name, valgrind_output } => {
-            let cwe_checker_results = match cwe_checker_result {
-                Some(path) => get_analysis_results(&path).unwrap(),
-                None => complete_analysis(&binary_path.clone().expect("If cwe_checker_result is not set, the bianry needs to be set")),
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove entries not used and incomplete entries not written for a while
+    Gc {
+        #[arg(long, default_value_t = 30)]
+        max_age_days: i64,
+    },
+}
+
+fn write_file(path: &Path, content: impl AsRef<[u8]>) -> Result<(), Error> {
+    fs::write(path, content).map_err(Error::io(path))
+}
+
+async fn run_command(command: Commands, run: Run) -> Result<(), Error> {
+    match command {
+        Commands::CweChecker { binary_path } => {
+            let cwe_checker_results = load_call_graph(&Some(binary_path), None, &run)?;
+            println!(
+                "Functions: {}, indirect callsites: {}, AICT: {}",
+                cwe_checker_results.metadata.functions.len(),
+                cwe_checker_results.metadata.indirect_call_sites.len(),
+                cwe_checker_results.aict().map(|aict| format!("{:.2}", aict)).unwrap_or_else(|| "-".to_string())
+            );
+        },
+        Commands::SoundnessTest { binary_path, cwe_checker_result, ground_truth, all_calls, json, html } => {
+            let (report, cwe_checker_results, elf) = run_soundness(&run, binary_path, cwe_checker_result, ground_truth, all_calls).await?;
+            print!("{}", render_text_report(&report, &cwe_checker_results, elf.as_ref()));
+            if let Some(json) = json {
+                write_file(&json, serde_json::to_string_pretty(&report).unwrap())?;
+            }
+            if let Some(html) = html {
+                write_file(&html, render_html_report(&report, &cwe_checker_results, elf.as_ref()))?;
+            }
+        },
+        Commands::Check { binary_path, cwe_checker_result, ground_truth, baseline, max_soundness_drop, max_aict_increase } => {
+            let content = fs::read_to_string(&baseline).map_err(Error::io(&baseline))?;
+            let baseline: SoundnessReport = serde_json::from_str(&content).map_err(Error::json(&baseline))?;
+            let (report, cwe_checker_results, elf) = run_soundness(&run, binary_path, cwe_checker_result, ground_truth, false).await?;
+            print!("{}", render_text_report(&report, &cwe_checker_results, elf.as_ref()));
+            println!();
+
+            let regressions = check_against_baseline(&report, &baseline, &Thresholds { max_soundness_drop, max_aict_increase });
+            print!("{}", render_regressions(&regressions));
+            if let Some(regression) = regressions.first() {
+                std::process::exit(regression.exit_code());
+            }
+        },
+        Commands::Coordinator {
+            binaries,
+            listen,
+            token,
+            results,
+            max_attempts,
+            lease_minutes,
+        } => {
+            let mut paths = fs::read_dir(&binaries)
+                .map_err(Error::io(&binaries))?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .collect::<Vec<PathBuf>>();
+            paths.sort();
+            let jobs = paths.into_iter().enumerate().map(|(id, path)| Job::new(id as u64, path)).collect();
+            let summary = run_coordinator(&listen, &token, jobs, &results, max_attempts, std::time::Duration::from_secs(lease_minutes * 60)).await?;
+            for report_path in &summary.reports {
+                let content = fs::read_to_string(report_path).map_err(Error::io(report_path))?;
+                let report: SoundnessReport = serde_json::from_str(&content).map_err(Error::json(report_path))?;
+                let name = report_path.file_stem().unwrap_or_default().to_string_lossy();
+                run.store(&report, None, &name)?;
+            }
+            println!("Succeeded: {}, failed: {}, results in {}", summary.succeeded, summary.failed, results.display());
+        },
+        Commands::Worker { coordinator, name, token, work_dir, tracer, all_calls } => {
+            let name = name.unwrap_or_else(|| {
+                nix::unistd::gethostname()
+                    .map(|host| host.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| "worker".to_string())
+            });
+            let run = &run;
+            let processed = run_worker(&coordinator, &name, &token, &work_dir, |job, binary_path| async move {
+                let ground_truth = GroundTruthArgs {
+                    valgrind_output: None,
+                    callee_csv: None,
+                    callee_bin_name: None,
+                    qemu_log: None,
+                    load_bias: 0,
+                    tracer,
+                };
+                let (report, _, _) = run_soundness(&run.for_job(&job), Some(binary_path), None, ground_truth, all_calls).await?;
+                Ok(report)
+            })
+            .await?;
+            println!("Processed {} jobs", processed);
+        },
+        Commands::Query { view, limit } => {
+            let path = run.results_db.as_ref().ok_or_else(|| Error::Config("query needs --results-db".to_string()))?;
+            let (header, rows) = ResultsDb::open(path)?.query(view, limit)?;
+            // Function names contain commas, e.g. `std::map<int, int>::find`
+            let stdout_error = |err: csv::Error| Error::io("stdout")(err.into());
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for row in std::iter::once(header).chain(rows) {
+                writer.write_record(&row).map_err(stdout_error)?;
+            }
+            writer.flush().map_err(Error::io("stdout"))?;
+        },
+        Commands::BuildSubjects { manifest, output } => {
+            let build_manifest = BuildManifest::load(&manifest)?;
+            let base = manifest.parent().unwrap_or(Path::new(""));
+            let records = build_subjects(&build_manifest, base, &output)?;
+            let failed = records.iter().filter(|record| record.error.is_some()).count();
+            println!("Built {} of {} binaries into {}", records.len() - failed, records.len(), output.display());
+        },
+        Commands::GenerateBenchmarks { output, pattern, targets, depth } => {
+            let patterns = if pattern.is_empty() { Pattern::value_variants().to_vec() } else { pattern };
+            for path in write_benchmarks(&output, &patterns, targets, depth)? {
+                println!("{}", path.display());
+            }
+        },
+        Commands::VerifyBenchmarks { expected, binaries } => {
+            println!("binary,pattern,expected,found,missing,no_callsite,no_symbol,library_callback,extra_targets");
+            for binary in binaries {
+                let name = binary.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                let pattern = name.split('_').next().unwrap_or_default();
+                let expected_edges = load_expected_edges(&expected.join(format!("{}.expected.json", pattern)))?;
+                let cwe_checker = load_call_graph(&Some(binary.clone()), None, &run)?;
+                let report = verify_expected_edges(&expected_edges, &cwe_checker, &ElfInfo::load(&binary)?);
+                for edge in report.edges.iter().filter(|edge| edge.status != EdgeStatus::Found) {
+                    info!("{}: {} -> {}: {:?}", name, edge.caller.as_deref().unwrap_or("<library>"), edge.target, edge.status);
+                }
+                println!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    name,
+                    report.pattern.name(),
+                    report.edges.len(),
+                    report.count(EdgeStatus::Found),
+                    report.count(EdgeStatus::Missing),
+                    report.count(EdgeStatus::NoCallsite),
+                    report.count(EdgeStatus::NoSymbol),
+                    report.count(EdgeStatus::LibraryCallback),
+                    report.extra_targets
+                );
+            }
+        },
+        Commands::Plot { cwe_checker_outputs, output } => {
+            let outputs = match cwe_checker_outputs {
+                Some(folder) => output_files(&folder)?,
+                None => run
+                    .cache
+                    .artifacts("cwe_checker")
+                    .into_iter()
+                    .map(|(key, path)| (key.binary_name, path))
+                    .collect(),
             };
-            let valgrind_result = match callee_csv {
-                Some(path) => {load_from_callee_csv::load_callee_from_csv(&path, &callee_bin_name.expect("If using callee_csv, callee_bin_name needs to be set") )},
-                None => {
-                    match valgrind_output {
-                        Some(valgrind_output) => analyze_valgrind(&valgrind_output),
-                        None => {
-                            let output_folder = Path::new("output");
-                            let date = Local::now();
-                            let output_folder = output_folder.join(Path::new(&date.format("%Y-%m-%d-%H-%M-%S").to_string()));
-
-                            let valgrind_output_file = output_folder.join(Path::new("valgrind.out"));
-                            fs::create_dir_all(output_folder).unwrap();
-
-                            let valgrind_result = run_valgrind(&binary_path.expect("If callee_csv is not set, the bianry needs to be set"), &valgrind_output_file).await;
-                            valgrind_result
-                            }
-                        }
+            let benchmarks = load_benchmark_logs(&outputs);
+            let mut aicts = Vec::new();
+            for (_, path) in &outputs {
+                match get_analysis_results(path) {
+                    Ok(cwe_checker) => aicts.extend(cwe_checker.aict().map(f64::from)),
+                    Err(err) => info!("Skipping {}", err),
+                }
+            }
+            let soundness = match &run.results_db {
+                Some(path) => {
+                    let mut db = ResultsDb::open(path)?;
+                    db.store_benchmarks(&benchmarks)?;
+                    db.soundness_by_variant()?
                 }
+                None => Vec::new(),
             };
-            soundness(&cwe_checker_results, &valgrind_result);
+            for figure in render_figures(&output, &benchmarks, &aicts, &soundness)? {
+                println!("{}", figure.display());
+            }
+        },
+        Commands::Cache { command: CacheCommand::Gc { max_age_days } } => {
+            let removed = run.cache.gc(chrono::Duration::days(max_age_days))?;
+            println!("Removed {} cache entries", removed);
         },
         Commands::ProcessCalleeResults { cwe_checker_result_folder, callee_csv } => {
-            let paths = fs::read_dir(cwe_checker_result_folder).unwrap();
+            let paths = fs::read_dir(&cwe_checker_result_folder).map_err(Error::io(&cwe_checker_result_folder))?;
+            let calls_by_binary = load_callee_csv(&callee_csv)?;
             let mut csv = "".to_owned();
-            for path in paths {
-                let path = path.unwrap();
-                let file_name = path.file_name().to_str().clone().unwrap().split(".").collect::<Vec<&str>>()[0].to_owned();
-                println!("{}", file_name);
-                let file_splited = file_name.split("_").collect::<Vec<&str>>();
-                if file_splited.len() != 3 {
+            for path in paths.flatten() {
+                let file_name = path.file_name().to_string_lossy().split(".").collect::<Vec<&str>>()[0].to_owned();
+                info!("{}", file_name);
+                let Some((binary, compiler, opt)) = split_binary_name(&file_name) else {
                     continue;
-                }
-                let binary = file_splited[0];
-                let compiler = file_splited[1];
-                let opt = file_splited[2];
+                };
 
-                let Some(analysis_result) = get_analysis_results(&path.path()) else {
-                    println!("For {} the analysis was not successful", binary);
-                    continue
+                let analysis_result = match get_analysis_results(&path.path()) {
+                    Ok(analysis_result) => analysis_result,
+                    Err(err) => {
+                        warn!("For {} the analysis was not successful: {}", binary, err);
+                        continue;
+                    }
                 };
 
-                let real = load_callee_from_csv(&callee_csv, &format!("{}-{}-{}", compiler, opt, binary));
-                let soundness_report = soundness(&analysis_result, &real);
-                csv = format!("{}\n{},{},{},{}", csv, file_name, soundness_report.checked_calls, soundness_report.sound_calls, soundness_report.sound_calls as f32 / soundness_report.checked_calls as f32);
+                let callee_bin_name = format!("{}-{}-{}", compiler, opt, binary);
+                let Some(real) = calls_by_binary.get(&callee_bin_name) else {
+                    warn!("For {} there are no calls of {} in {}", binary, callee_bin_name, callee_csv.display());
+                    continue;
+                };
+                let soundness_report = soundness(&analysis_result, real, &SoundnessOptions::default());
+                run.store(&soundness_report, Some(&analysis_result), &file_name)?;
+                csv = format!("{}\n{},{}", csv, file_name, soundness_report.to_csvline());
             }
             println!("{}", csv);
         },
+        Commands::Diff { old, new, binary_path, ground_truth } => {
+            let old = get_analysis_results(&old)?;
+            let new = get_analysis_results(&new)?;
+            let with_soundness = binary_path.is_some() || ground_truth.has_recorded_trace();
+            let real = if with_soundness {
+                Some(load_real_calls(binary_path, ground_truth, &new, &run).await?)
+            } else {
+                None
+            };
+            print!("{}", render_diff(&diff(&old, &new, real.as_ref()), with_soundness));
+        },
+        Commands::Export { cwe_checker_result, binary_path, ground_truth, format, output, function, depth } => {
+            let cwe_checker_results = get_analysis_results(&cwe_checker_result)?;
+            let real = if binary_path.is_some() || ground_truth.has_recorded_trace() {
+                Some(load_real_calls(binary_path, ground_truth, &cwe_checker_results, &run).await?)
+            } else {
+                None
+            };
+            let mut graph = build_graph(&cwe_checker_results, real.as_ref());
+            if let Some(function) = function {
+                graph = graph.restrict_to_neighbourhood(&function, depth);
+            }
+            write_file(&output, graph.render(format))?;
+            println!("Wrote {} nodes and {} edges to {}", graph.nodes.len(), graph.edges.len(), output.display());
+        },
+        Commands::Reachability { binary_path, cwe_checker_result, ground_truth, root } => {
+            let cwe_checker_results = load_call_graph(&binary_path, cwe_checker_result, &run)?;
+            let mut roots = root;
+            if let Some(binary_path) = &binary_path {
+                roots.extend(ElfInfo::load(binary_path)?.exported_functions().map(|symbol| symbol.name.clone()));
+            }
+            let valgrind_result = load_real_calls(binary_path, ground_truth, &cwe_checker_results, &run).await?;
+            print!("{}", render_reachability(&reachability(&cwe_checker_results, &valgrind_result, &roots)));
+        },
+        Commands::Symbolize { binary_path, load_base, addresses } => {
+            let elf = ElfInfo::load(&binary_path)?;
+            for address in addresses {
+                let elf_address = match load_base {
+                    Some(load_base) => elf.from_runtime_address(address, load_base),
+                    None => Some(address),
+                };
+                match elf_address {
+                    Some(elf_address) => println!("{:#x}: {}", address, elf.format_address(elf_address)),
+                    None => println!("{:#x}: below --load-base", address),
+                }
+            }
+        },
+        Commands::RankAnalyses { binary_path, cwe_checker_result, graph, ground_truth } => {
+            let cwe_checker_results = load_call_graph(&binary_path, cwe_checker_result, &run)?;
+            let elf = binary_path.as_deref().map(ElfInfo::load).transpose()?;
+            let valgrind_result = load_real_calls(binary_path, ground_truth, &cwe_checker_results, &run).await?;
+
+            // The granularity is the last element, cwe_checker resolves single callsites
+            let mut analyses = vec![("cwe_checker".to_string(), cwe_checker_results.clone(), "callsite")];
+            if elf.is_none() && !graph.is_empty() {
+                warn!("Without --binary-path the addresses of the graphs are not rebased, which is only correct for non-PIE binaries");
+            }
+            for (format, path) in graph {
+                let name = format!("{:?} ({})", format, path.display());
+                let granularity = if format.is_function_granular() { "function" } else { "callsite" };
+                let image_base = elf.as_ref().and_then(|elf| format.image_base(elf));
+                analyses.push((name, load_external_call_graph(format, &path, &cwe_checker_results, image_base)?, granularity));
+            }
+
+            let mut ranking = vec![];
+            for (name, analysis, granularity) in analyses {
+                info!("Checking {}", name);
+                let report = soundness(&analysis, &valgrind_result, &SoundnessOptions::default());
+                let sound_percentage = report.sound_percentage();
+                ranking.push((name, report, sound_percentage, analysis.aict(), granularity));
+            }
+            // Analyses without checked calls or resolved callsites come last
+            ranking.sort_by(|a, b| {
+                b.2.unwrap_or(-1.0)
+                    .total_cmp(&a.2.unwrap_or(-1.0))
+                    .then(a.3.unwrap_or(f32::INFINITY).total_cmp(&b.3.unwrap_or(f32::INFINITY)))
+            });
+
+            // Empty columns if there is nothing to divide by, paths may contain commas
+            let format = |value: Option<f32>| value.map(|value| format!("{:.2}", value)).unwrap_or_default();
+            let stdout_error = |err: csv::Error| Error::io("stdout")(err.into());
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            writer
+                .write_record(["analysis", "granularity", "checked", "sound", "sound_percentage", "aict"])
+                .map_err(stdout_error)?;
+            for (name, report, sound_percentage, aict, granularity) in ranking {
+                let row = [
+                    name,
+                    granularity.to_string(),
+                    report.checked_calls.to_string(),
+                    report.sound_calls.to_string(),
+                    format(sound_percentage),
+                    format(aict),
+                ];
+                writer.write_record(&row).map_err(stdout_error)?;
+            }
+            writer.flush().map_err(Error::io("stdout"))?;
+        },
     };
+    Ok(())
 }
 
+#[tokio::main]
+async fn main() {
+    let args = Cli::parse();
+    // RUST_LOG still overrides the level of single modules
+    env_logger::Builder::new().filter_level(args.log_level()).parse_default_env().init();
+    let remote = match args.remote() {
+        Ok(remote) => remote,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let run = Run::new(args.cache(), remote, args.results_db.clone());
+    if let Err(err) = run_command(args.command, run).await {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
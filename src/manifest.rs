@@ -0,0 +1,126 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::sha256_of_file,
+    error::{Error, Result},
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputFile {
+    pub path: String,
+    pub sha256: String,
+}
+
+impl InputFile {
+    pub fn new(path: &Path) -> Result<InputFile> {
+        Ok(InputFile {
+            path: path.display().to_string(),
+            sha256: sha256_of_file(path)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    pub tool: String,
+    /// None if the tool is not installed
+    pub version: Option<String>,
+    pub args: Vec<String>,
+}
+
+impl ToolInvocation {
+    pub fn new(tool: &str, version: Option<String>, args: &[&str]) -> ToolInvocation {
+        ToolInvocation {
+            tool: tool.to_string(),
+            version,
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+}
+
+/// How a soundness result was produced, written next to the outputs and embedded in the reports
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// Version of this tool
+    pub version: String,
+    pub command_line: Vec<String>,
+    pub host: String,
+    pub os: String,
+    pub binary: Option<InputFile>,
+    /// The cwe_checker result given on the command line
+    pub call_graph: Option<InputFile>,
+    /// Set if cwe_checker was run (or its cached result used)
+    pub cwe_checker: Option<ToolInvocation>,
+    /// Where the observed calls come from
+    pub ground_truth: String,
+    /// Recorded trace given on the command line
+    pub trace: Option<InputFile>,
+    /// Set if the binary was traced (or a cached trace used)
+    pub tracer: Option<ToolInvocation>,
+    /// How runtime addresses are moved into cwe_checker coordinates
+    pub load_bias_method: String,
+    /// Observed calls that are not checked
+    pub exclusions: Vec<String>,
+    pub started_at: DateTime<Local>,
+    pub finished_at: Option<DateTime<Local>>,
+}
+
+impl RunManifest {
+    pub fn new(started_at: DateTime<Local>) -> RunManifest {
+        let host = nix::unistd::gethostname()
+            .map(|host| host.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "unknown".to_string());
+        RunManifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command_line: std::env::args().collect(),
+            host,
+            os: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            binary: None,
+            call_graph: None,
+            cwe_checker: None,
+            ground_truth: String::new(),
+            trace: None,
+            tracer: None,
+            load_bias_method: String::new(),
+            exclusions: vec![],
+            started_at,
+            finished_at: None,
+        }
+    }
+
+    pub fn write(&self, folder: &Path) -> Result<()> {
+        fs::create_dir_all(folder).map_err(Error::io(folder))?;
+        let path = folder.join("manifest.json");
+        fs::write(&path, serde_json::to_string_pretty(self).unwrap()).map_err(Error::io(path))
+    }
+
+    /// Key and value pairs for the header of the reports
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let input_file = |file: &Option<InputFile>| match file {
+            Some(file) => format!("{} (sha256 {})", file.path, file.sha256),
+            None => "-".to_string(),
+        };
+        let tool = |tool: &Option<ToolInvocation>| match tool {
+            Some(tool) => format!("{} {} [{}]", tool.tool, tool.args.join(" "), tool.version.as_deref().unwrap_or("version unknown")),
+            None => "-".to_string(),
+        };
+        vec![
+            ("Binary", input_file(&self.binary)),
+            ("Call graph", input_file(&self.call_graph)),
+            ("cwe_checker", tool(&self.cwe_checker)),
+            ("Ground truth", self.ground_truth.clone()),
+            ("Trace", input_file(&self.trace)),
+            ("Tracer", tool(&self.tracer)),
+            ("Load bias", self.load_bias_method.clone()),
+            ("Exclusions", self.exclusions.join("; ")),
+            ("Host", format!("{} ({})", self.host, self.os)),
+            ("Version", self.version.clone()),
+            ("Command line", self.command_line.join(" ")),
+            ("Started", self.started_at.to_rfc3339()),
+            ("Finished", self.finished_at.map(|time| time.to_rfc3339()).unwrap_or_else(|| "-".to_string())),
+        ]
+    }
+}
@@ -0,0 +1,415 @@
+//! Small C programs with known indirect call targets. Every pattern is a separate program, so a
+//! missing edge points at the way the function pointer travels. The indirect call is in the
+//! function `dispatch`, except for library callbacks, and the expected targets are stored next to
+//! the source as `<pattern>.expected.json`, matched by symbol name as the addresses are only known
+//! after compiling.
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cwe_checker::CweCheckerResult,
+    elf::ElfInfo,
+    error::{Error, Result},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pattern {
+    /// Global array of function pointers indexed at runtime
+    FnTable,
+    /// Objects pointing to structs of function pointers, like C++ vtables
+    Vtable,
+    /// Comparators called by qsort in libc. The callsite is in libc, so every expected edge is a
+    /// [`EdgeStatus::LibraryCallback`] and its expected edges verify nothing about the call graph
+    /// of the binary.
+    Qsort,
+    /// Pointer stored in a heap object that is passed through several functions
+    Heap,
+    /// Pointer stored in a global variable, read after several calls
+    Global,
+    /// Pointer in a local variable whose address is passed through several functions
+    Stack,
+    /// Pointer set before longjmp and called after setjmp returned the second time
+    Setjmp,
+}
+
+impl Pattern {
+    /// Also used as subject name, so it must not contain `_`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::FnTable => "fntable",
+            Pattern::Vtable => "vtable",
+            Pattern::Qsort => "qsort",
+            Pattern::Heap => "heap",
+            Pattern::Global => "global",
+            Pattern::Stack => "stack",
+            Pattern::Setjmp => "setjmp",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedCall {
+    /// Function containing the indirect call, None if it is in a library
+    pub caller: Option<String>,
+    pub targets: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedEdges {
+    pub pattern: Pattern,
+    pub targets: usize,
+    pub depth: usize,
+    pub calls: Vec<ExpectedCall>,
+}
+
+const PRELUDE: &str = "#include <setjmp.h>
+#include <stdlib.h>
+
+/* No inlining or cloning, so the indirect call stays in dispatch */
+#if defined(__clang__)
+#define NOINLINE __attribute__((noinline))
+#else
+#define NOINLINE __attribute__((noinline, noclone))
+#endif
+
+typedef int (*handler_t)(int);
+
+volatile int sink;
+";
+
+/// Every target has a different body, so identical code folding does not merge them
+fn targets(source: &mut String, count: usize) {
+    for index in 0..count {
+        writeln!(source, "NOINLINE int target_{0}(int x) {{ sink += x * {1} + {0}; return x + {0}; }}", index, index + 1).unwrap();
+    }
+}
+
+/// `<assignee> = target_<index>` for the runtime index `index`, without a table in memory
+fn select_target(source: &mut String, indent: &str, assignee: &str, count: usize) {
+    writeln!(source, "{}switch (index) {{", indent).unwrap();
+    for index in 0..count {
+        writeln!(source, "{}case {}: {} = target_{}; break;", indent, index, assignee, index).unwrap();
+    }
+    writeln!(source, "{0}default: {1} = target_0; break;\n{0}}}", indent, assignee).unwrap();
+}
+
+/// `pass_1` to `pass_<depth>` forwarding their arguments, `pass_<depth>` calls dispatch
+fn pass_chain(source: &mut String, depth: usize, parameters: &str, arguments: &str) {
+    for level in (1..=depth).rev() {
+        let callee = if level == depth { "dispatch".to_string() } else { format!("pass_{}", level + 1) };
+        writeln!(source, "NOINLINE int pass_{}({}) {{ return {}({}) + 1; }}", level, parameters, callee, arguments).unwrap();
+    }
+}
+
+/// main calls every target once, the index depends on argc so it is not known statically
+fn main_loop(source: &mut String, count: usize, body: &str) {
+    writeln!(
+        source,
+        "\nint main(int argc, char **argv) {{\n    (void)argv;\n    for (int i = 0; i < {}; i++) {{\n        int index = (i + argc - 1) % {};\n{}    }}\n    return 0;\n}}",
+        count, count, body
+    )
+    .unwrap();
+}
+
+/// C source and expected edges of a pattern with `count` targets and `depth` functions between
+/// storing and calling the pointer. The `+ 1` after the calls keeps the compiler from turning
+/// them into tail calls, which would be indirect jumps.
+pub fn generate(pattern: Pattern, count: usize, depth: usize) -> (String, ExpectedEdges) {
+    let count = count.max(1);
+    let depth = depth.max(1);
+    let mut source = format!("/* Generated micro-benchmark, pattern {}, {} targets, depth {} */\n", pattern.name(), count, depth);
+    source.push_str(PRELUDE);
+    if pattern != Pattern::Qsort {
+        targets(&mut source, count);
+    }
+    source.push('\n');
+    let target_names = |prefix: &str| (0..count).map(|index| format!("{}_{}", prefix, index)).collect::<Vec<String>>();
+    let mut caller = Some("dispatch".to_string());
+    let mut expected_targets = target_names("target");
+
+    match pattern {
+        Pattern::FnTable => {
+            writeln!(source, "handler_t table[{}] = {{ {} }};\n", count, expected_targets.join(", ")).unwrap();
+            source.push_str("NOINLINE int dispatch(int index, int x) { return table[index](x) + 1; }\n");
+            main_loop(&mut source, count, "        dispatch(index, i);\n");
+        }
+        Pattern::Vtable => {
+            source.push_str("struct ops { handler_t run; };\nstruct object { const struct ops *ops; int value; };\n\n");
+            for index in 0..count {
+                writeln!(source, "const struct ops ops_{0} = {{ target_{0} }};", index).unwrap();
+            }
+            let ops = (0..count).map(|index| format!("&ops_{}", index)).collect::<Vec<String>>();
+            writeln!(source, "const struct ops *const all_ops[{}] = {{ {} }};\n", count, ops.join(", ")).unwrap();
+            source.push_str("NOINLINE int dispatch(struct object *object) { return object->ops->run(object->value) + 1; }\n");
+            main_loop(
+                &mut source,
+                count,
+                "        struct object *object = malloc(sizeof(struct object));\n        object->ops = all_ops[index];\n        object->value = i;\n        dispatch(object);\n        free(object);\n",
+            );
+        }
+        Pattern::Qsort => {
+            for index in 0..count {
+                writeln!(
+                    source,
+                    "NOINLINE int compare_{0}(const void *a, const void *b) {{ sink += {0}; return *(const int *)a - *(const int *)b; }}",
+                    index
+                )
+                .unwrap();
+            }
+            let comparators = target_names("compare");
+            writeln!(
+                source,
+                "\nint (*comparators[{}])(const void *, const void *) = {{ {} }};",
+                count,
+                comparators.join(", ")
+            )
+            .unwrap();
+            main_loop(&mut source, count, "        int values[3] = { 3, i, 1 };\n        qsort(values, 3, sizeof(int), comparators[index]);\n");
+            caller = None;
+            expected_targets = comparators;
+        }
+        Pattern::Heap => {
+            source.push_str("struct holder { handler_t handler; int value; };\n\n");
+            source.push_str("NOINLINE int dispatch(struct holder *holder) { return holder->handler(holder->value) + 1; }\n");
+            pass_chain(&mut source, depth, "struct holder *holder", "holder");
+            let mut body = String::from("        struct holder *holder = malloc(sizeof(struct holder));\n        holder->value = i;\n");
+            select_target(&mut body, "        ", "holder->handler", count);
+            body.push_str("        pass_1(holder);\n        free(holder);\n");
+            main_loop(&mut source, count, &body);
+        }
+        Pattern::Global => {
+            source.push_str("handler_t current;\n\n");
+            source.push_str("NOINLINE int dispatch(int x) { return current(x) + 1; }\n");
+            pass_chain(&mut source, depth, "int x", "x");
+            let mut body = String::new();
+            select_target(&mut body, "        ", "current", count);
+            body.push_str("        pass_1(i);\n");
+            main_loop(&mut source, count, &body);
+        }
+        Pattern::Stack => {
+            source.push_str("NOINLINE int dispatch(handler_t *handler) { return (*handler)(sink) + 1; }\n");
+            pass_chain(&mut source, depth, "handler_t *handler", "handler");
+            let mut body = String::from("        handler_t handler;\n");
+            select_target(&mut body, "        ", "handler", count);
+            body.push_str("        pass_1(&handler);\n");
+            main_loop(&mut source, count, &body);
+        }
+        Pattern::Setjmp => {
+            source.push_str("jmp_buf env;\nhandler_t pending;\n\n");
+            source.push_str("NOINLINE void raise_handler(int index) {\n");
+            select_target(&mut source, "    ", "pending", count);
+            source.push_str("    longjmp(env, 1);\n}\n\n");
+            source.push_str("NOINLINE int dispatch(int index, int x) {\n    if (setjmp(env) == 0) {\n        raise_handler(index);\n    }\n    return pending(x) + 1;\n}\n");
+            main_loop(&mut source, count, "        dispatch(index, i);\n");
+        }
+    }
+
+    let expected = ExpectedEdges {
+        pattern,
+        targets: count,
+        depth,
+        calls: vec![ExpectedCall {
+            caller,
+            targets: expected_targets,
+        }],
+    };
+    (source, expected)
+}
+
+/// Writes `<pattern>.c`, `<pattern>.expected.json` and a build-subjects manifest `subjects.json`
+/// compiling all of them
+pub fn write_benchmarks(output: &Path, patterns: &[Pattern], count: usize, depth: usize) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(output).map_err(Error::io(output))?;
+    let mut written = Vec::new();
+    let mut subjects = Vec::new();
+    for pattern in patterns {
+        let (source, expected) = generate(*pattern, count, depth);
+        let source_path = output.join(format!("{}.c", pattern.name()));
+        fs::write(&source_path, source).map_err(Error::io(&source_path))?;
+        let expected_path = output.join(format!("{}.expected.json", pattern.name()));
+        fs::write(&expected_path, serde_json::to_string_pretty(&expected).unwrap()).map_err(Error::io(&expected_path))?;
+        subjects.push(serde_json::json!({ "name": pattern.name(), "sources": [format!("{}.c", pattern.name())] }));
+        written.push(source_path);
+        written.push(expected_path);
+    }
+    let manifest_path = output.join("subjects.json");
+    let manifest = serde_json::json!({ "subjects": subjects });
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest).unwrap()).map_err(Error::io(&manifest_path))?;
+    written.push(manifest_path);
+    Ok(written)
+}
+
+pub fn load_expected_edges(path: &Path) -> Result<ExpectedEdges> {
+    let content = fs::read_to_string(path).map_err(Error::io(path))?;
+    serde_json::from_str(&content).map_err(Error::json(path))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum EdgeStatus {
+    Found,
+    /// The call graph has a callsite in the caller, but not this target
+    Missing,
+    /// The call graph has no indirect callsite in the caller
+    NoCallsite,
+    /// Caller or target are not in the symbol table, e.g. because they were inlined
+    NoSymbol,
+    /// The callsite is in a library, e.g. qsort calling its comparator, so the call graph of the
+    /// binary can not contain the edge
+    LibraryCallback,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckedEdge {
+    pub caller: Option<String>,
+    pub target: String,
+    pub status: EdgeStatus,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ExpectedEdgeReport {
+    pub pattern: Pattern,
+    pub edges: Vec<CheckedEdge>,
+    /// Targets of the callers' callsites in the call graph that are not expected
+    pub extra_targets: usize,
+}
+
+impl ExpectedEdgeReport {
+    pub fn count(&self, status: EdgeStatus) -> usize {
+        self.edges.iter().filter(|edge| edge.status == status).count()
+    }
+}
+
+/// Checks that the call graph contains every expected edge
+pub fn verify_expected_edges(expected: &ExpectedEdges, cwe_checker: &CweCheckerResult, elf: &ElfInfo) -> ExpectedEdgeReport {
+    let symbol_address = |name: &str| elf.symbols.iter().find(|symbol| symbol.name == name).map(|symbol| symbol.address);
+    let mut edges = Vec::new();
+    let mut extra_targets = 0;
+    for call in &expected.calls {
+        let expected_targets = call.targets.iter().map(|target| (target, symbol_address(target))).collect::<Vec<_>>();
+        // Indirect callsites of the caller, in ELF addresses
+        let callsites = cwe_checker
+            .metadata
+            .indirect_call_sites
+            .iter()
+            .filter(|callsite| {
+                call.caller.as_ref().is_some_and(|caller| {
+                    elf.from_cwe_address(**callsite, &cwe_checker.metadata)
+                        .and_then(|callsite| elf.lookup(callsite))
+                        .is_some_and(|(symbol, _)| &symbol.name == caller)
+                })
+            })
+            .collect::<Vec<&u64>>();
+        let targets = callsites
+            .iter()
+            .filter_map(|callsite| cwe_checker.get_call_site(**callsite))
+            .flat_map(|callsite| callsite.targets)
+            .filter_map(|target| elf.from_cwe_address(target, &cwe_checker.metadata))
+            .collect::<BTreeSet<u64>>();
+        let caller_known = call.caller.as_deref().is_none_or(|caller| symbol_address(caller).is_some());
+
+        for (target, address) in &expected_targets {
+            let status = match address {
+                Some(_) if !caller_known => EdgeStatus::NoSymbol,
+                None => EdgeStatus::NoSymbol,
+                Some(_) if call.caller.is_none() => EdgeStatus::LibraryCallback,
+                Some(_) if callsites.is_empty() => EdgeStatus::NoCallsite,
+                Some(address) if targets.contains(address) => EdgeStatus::Found,
+                Some(_) => EdgeStatus::Missing,
+            };
+            edges.push(CheckedEdge {
+                caller: call.caller.clone(),
+                target: target.to_string(),
+                status,
+            });
+        }
+        if call.caller.is_some() {
+            let expected_addresses = expected_targets.iter().filter_map(|(_, address)| *address).collect::<BTreeSet<u64>>();
+            extra_targets += targets.difference(&expected_addresses).count();
+        }
+    }
+    ExpectedEdgeReport {
+        pattern: expected.pattern,
+        edges,
+        extra_targets,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::cwe_checker::{Call, ExportCallGraph, Metadata};
+
+    #[test]
+    fn test_generate() {
+        let (source, expected) = generate(Pattern::Heap, 3, 2);
+        assert!(source.contains("NOINLINE int target_2(int x) { sink += x * 3 + 2; return x + 2; }"));
+        assert!(source.contains("NOINLINE int pass_2(struct holder *holder) { return dispatch(holder) + 1; }"));
+        assert!(source.contains("        case 1: holder->handler = target_1; break;"));
+        assert_eq!(expected.calls[0].caller.as_deref(), Some("dispatch"));
+        assert_eq!(expected.calls[0].targets, ["target_0", "target_1", "target_2"]);
+
+        let (_, expected) = generate(Pattern::Qsort, 2, 1);
+        assert_eq!(expected.calls[0].caller, None);
+        assert_eq!(expected.calls[0].targets, ["compare_0", "compare_1"]);
+    }
+
+    /// Call graph with one indirect callsite in dispatch calling the given targets
+    fn dispatch_call_graph(elf: &ElfInfo, targets: &[&str]) -> CweCheckerResult {
+        let address = |name: &str| elf.symbols.iter().find(|symbol| symbol.name == name).map(|symbol| symbol.address).unwrap();
+        let callsite = address("dispatch") + 4;
+        CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata { address_base_offset: 0, indirect_call_sites: vec![callsite], functions: vec![] },
+            calls: targets
+                .iter()
+                .map(|target| Call { from_instr: callsite, to_instr: Some(address(target)), is_indirect: true })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_compiled_benchmarks() {
+        let dir = tempfile::tempdir().unwrap();
+        write_benchmarks(dir.path(), &[Pattern::Heap, Pattern::Qsort], 3, 2).unwrap();
+        let build = |pattern: Pattern| {
+            let binary = dir.path().join(pattern.name());
+            let status = Command::new("cc")
+                .args(["-O2", "-no-pie", "-o"])
+                .arg(&binary)
+                .arg(dir.path().join(format!("{}.c", pattern.name())))
+                .status()
+                .expect("cc has to be installed for this test");
+            assert!(status.success());
+            let expected = load_expected_edges(&dir.path().join(format!("{}.expected.json", pattern.name()))).unwrap();
+            (ElfInfo::load(&binary).unwrap(), expected)
+        };
+
+        // The generated code keeps dispatch and every target as functions of their own
+        let (elf, expected) = build(Pattern::Heap);
+        let report = verify_expected_edges(&expected, &dispatch_call_graph(&elf, &["target_0", "target_2", "main"]), &elf);
+        let statuses = report.edges.iter().map(|edge| edge.status).collect::<Vec<EdgeStatus>>();
+        assert_eq!(statuses, [EdgeStatus::Found, EdgeStatus::Missing, EdgeStatus::Found]);
+        assert_eq!(report.extra_targets, 1);
+
+        // A callsite of the binary targeting a comparator does not count, qsort calls them from libc
+        let (elf, expected) = build(Pattern::Qsort);
+        let callsite = elf.symbols.iter().find(|symbol| symbol.name == "main").unwrap().address + 4;
+        let compare_0 = elf.symbols.iter().find(|symbol| symbol.name == "compare_0").unwrap().address;
+        let call_graph = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata { address_base_offset: 0, indirect_call_sites: vec![callsite], functions: vec![] },
+            calls: vec![Call { from_instr: callsite, to_instr: Some(compare_0), is_indirect: true }],
+        });
+        let report = verify_expected_edges(&expected, &call_graph, &elf);
+        assert_eq!(report.count(EdgeStatus::LibraryCallback), 3);
+        assert_eq!(report.extra_targets, 0);
+    }
+}
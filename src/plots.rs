@@ -0,0 +1,277 @@
+//! The figures of the evaluation as SVG: cwe_checker runtime and memory usage against the program
+//! size, the soundness per compiler and optimisation level and the AICT distribution. For PDF,
+//! convert them with e.g. `rsvg-convert -f pdf`.
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use plotters::prelude::*;
+
+use crate::{
+    benchmark_log::BenchmarkRecord,
+    error::{Error, Result},
+    results_db::VariantSoundness,
+};
+
+const SIZE: (u32, u32) = (800, 600);
+
+/// Errors of plotters are generic over the backend, only their message is kept
+fn plot_error(path: &Path) -> impl Fn(String) -> Error + '_ {
+    move |message| Error::Plot {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+/// Least-squares fit as (slope, intercept), None for less than two distinct x values
+pub fn linear_regression(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+    let sxy = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+/// Upper end of an axis starting at 0, with some room above the largest value
+fn axis_end(values: impl Iterator<Item = f64>) -> f64 {
+    let max = values.fold(0.0, f64::max);
+    if max > 0.0 {
+        max * 1.05
+    } else {
+        1.0
+    }
+}
+
+pub fn scatter_with_regression(path: &Path, title: &str, x_label: &str, y_label: &str, points: &[(f64, f64)]) -> Result<()> {
+    draw_scatter(path, title, x_label, y_label, points).map_err(plot_error(path))
+}
+
+fn draw_scatter(path: &Path, title: &str, x_label: &str, y_label: &str, points: &[(f64, f64)]) -> std::result::Result<(), String> {
+    let root = SVGBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(|err| err.to_string())?;
+    let x_end = axis_end(points.iter().map(|(x, _)| *x));
+    let y_end = axis_end(points.iter().map(|(_, y)| *y));
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(45)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..x_end, 0.0..y_end)
+        .map_err(|err| err.to_string())?;
+    chart
+        .configure_mesh()
+        .x_desc(x_label)
+        .y_desc(y_label)
+        .draw()
+        .map_err(|err| err.to_string())?;
+    chart
+        .draw_series(points.iter().map(|point| Circle::new(*point, 4, BLUE.mix(0.6).filled())))
+        .map_err(|err| err.to_string())?;
+    if let Some((slope, intercept)) = linear_regression(points) {
+        // Clipped to the plotting area by plotters
+        let line = [(0.0, intercept), (x_end, intercept + slope * x_end)];
+        chart
+            .draw_series(LineSeries::new(line, RED.stroke_width(2)))
+            .map_err(|err| err.to_string())?
+            .label(format!("y = {:.3e} x {:+.3}", slope, intercept))
+            .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], RED.stroke_width(2)));
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(|err| err.to_string())?;
+    }
+    root.present().map_err(|err| err.to_string())
+}
+
+/// One group of bars per optimisation level, one bar per compiler
+pub fn soundness_chart(path: &Path, rows: &[VariantSoundness]) -> Result<()> {
+    draw_soundness_chart(path, rows).map_err(plot_error(path))
+}
+
+fn draw_soundness_chart(path: &Path, rows: &[VariantSoundness]) -> std::result::Result<(), String> {
+    let opts = rows.iter().map(|row| row.opt.as_str()).collect::<BTreeSet<&str>>().into_iter().collect::<Vec<&str>>();
+    let compilers = rows.iter().map(|row| row.compiler.as_str()).collect::<BTreeSet<&str>>().into_iter().collect::<Vec<&str>>();
+
+    let root = SVGBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(|err| err.to_string())?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Soundness per optimisation level", ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(45)
+        .y_label_area_size(60)
+        .build_cartesian_2d(-0.5..opts.len() as f64 - 0.5, 0.0..100.0)
+        .map_err(|err| err.to_string())?;
+    // Only the group centres are labelled
+    let opt_label = |x: &f64| {
+        let index = x.round();
+        if (x - index).abs() < 1e-6 && index >= 0.0 {
+            opts.get(index as usize).map(|opt| opt.to_string()).unwrap_or_default()
+        } else {
+            String::new()
+        }
+    };
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(opts.len())
+        .x_label_formatter(&opt_label)
+        .x_desc("Optimisation level")
+        .y_desc("Sound calls (%)")
+        .draw()
+        .map_err(|err| err.to_string())?;
+
+    let bar_width = 0.8 / compilers.len() as f64;
+    for (compiler_index, compiler) in compilers.iter().enumerate() {
+        let color = Palette99::pick(compiler_index).to_rgba();
+        let bars = rows.iter().filter(|row| row.compiler == *compiler).filter_map(|row| {
+            let group = opts.iter().position(|opt| *opt == row.opt)? as f64;
+            let left = group - 0.4 + bar_width * compiler_index as f64;
+            Some(Rectangle::new([(left, 0.0), (left + bar_width, row.sound_percentage)], color.filled()))
+        });
+        chart
+            .draw_series(bars)
+            .map_err(|err| err.to_string())?
+            .label(*compiler)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 15, y + 5)], color.filled()));
+    }
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::LowerRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()
+        .map_err(|err| err.to_string())?;
+    root.present().map_err(|err| err.to_string())
+}
+
+/// NaN and infinite values are left out, they have no bin
+pub fn histogram(path: &Path, title: &str, x_label: &str, values: &[f64]) -> Result<()> {
+    let values = values.iter().copied().filter(|value| value.is_finite()).collect::<Vec<f64>>();
+    if values.is_empty() {
+        return Err(plot_error(path)("no finite values".to_string()));
+    }
+    draw_histogram(path, title, x_label, &values).map_err(plot_error(path))
+}
+
+fn draw_histogram(path: &Path, title: &str, x_label: &str, values: &[f64]) -> std::result::Result<(), String> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let bins = ((values.len() as f64).sqrt().ceil() as usize).clamp(1, 30);
+    // A single value still gets a bin of width 1
+    let width = if max > min { (max - min) / bins as f64 } else { 1.0 };
+    let mut counts = vec![0u32; bins];
+    for value in values {
+        let bin = (((value - min) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+
+    let root = SVGBackend::new(path, SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(|err| err.to_string())?;
+    let y_end = counts.iter().copied().max().unwrap_or(0) + 1;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(45)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min..min + width * bins as f64, 0u32..y_end)
+        .map_err(|err| err.to_string())?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_desc(x_label)
+        .y_desc("Binaries")
+        .draw()
+        .map_err(|err| err.to_string())?;
+    chart
+        .draw_series(counts.iter().enumerate().map(|(bin, count)| {
+            let left = min + width * bin as f64;
+            Rectangle::new([(left, 0), (left + width, *count)], BLUE.mix(0.6).filled())
+        }))
+        .map_err(|err| err.to_string())?;
+    root.present().map_err(|err| err.to_string())
+}
+
+/// Draws every figure there is data for into the folder and returns their paths
+pub fn render_figures(output: &Path, benchmarks: &[BenchmarkRecord], aicts: &[f64], soundness: &[VariantSoundness]) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(output).map_err(Error::io(output))?;
+    let mut figures = Vec::new();
+
+    let runtime = benchmarks
+        .iter()
+        .filter_map(|record| Some((record.program_size()? as f64, record.runtime_minutes?)))
+        .collect::<Vec<(f64, f64)>>();
+    let memory = benchmarks
+        .iter()
+        .filter_map(|record| Some((record.program_size()? as f64, record.memory_gib?)))
+        .collect::<Vec<(f64, f64)>>();
+    if runtime.is_empty() {
+        warn!("No benchmark logs with runtime and program size, skipping runtime.svg");
+    } else {
+        let path = output.join("runtime.svg");
+        scatter_with_regression(&path, "Runtime", "#Blocks", "Runtime (minutes)", &runtime)?;
+        figures.push(path);
+    }
+    if memory.is_empty() {
+        warn!("No benchmark logs with memory usage and program size, skipping memusage.svg");
+    } else {
+        let path = output.join("memusage.svg");
+        scatter_with_regression(&path, "Memory usage", "#Blocks", "Memory (GiB)", &memory)?;
+        figures.push(path);
+    }
+
+    if !aicts.iter().any(|aict| aict.is_finite()) {
+        warn!("No call graphs with resolved callsites, skipping aict.svg");
+    } else {
+        let path = output.join("aict.svg");
+        histogram(&path, "AICT distribution", "Average indirect call targets", aicts)?;
+        figures.push(path);
+    }
+
+    if soundness.is_empty() {
+        warn!("No soundness results of `<binary>_<compiler>_<opt>` binaries, skipping soundness.svg");
+    } else {
+        let path = output.join("soundness.svg");
+        soundness_chart(&path, soundness)?;
+        figures.push(path);
+    }
+
+    for figure in &figures {
+        info!("Wrote {}", figure.display());
+    }
+    Ok(figures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_regression() {
+        assert_eq!(linear_regression(&[(1.0, 3.0), (2.0, 5.0), (3.0, 7.0)]), Some((2.0, 1.0)));
+        assert_eq!(linear_regression(&[(1.0, 3.0), (1.0, 5.0)]), None);
+        assert_eq!(linear_regression(&[]), None);
+    }
+
+    #[test]
+    fn test_non_finite_aicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let figures = render_figures(dir.path(), &[], &[1.0, f64::NAN, 2.5, f64::INFINITY], &[]).unwrap();
+        assert_eq!(figures, [dir.path().join("aict.svg")]);
+        assert!(render_figures(dir.path(), &[], &[f64::NAN], &[]).unwrap().is_empty());
+        assert!(histogram(&dir.path().join("nan.svg"), "AICT", "AICT", &[f64::NAN]).is_err());
+    }
+}
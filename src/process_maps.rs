@@ -0,0 +1,63 @@
+//! Where a traced binary is mapped, to convert runtime addresses to the addresses of cwe_checker.
+use std::{fs, path::Path};
+
+use crate::{
+    cwe_checker::Metadata,
+    error::{Error, Result},
+};
+
+const ET_DYN: u16 = 3;
+
+/// Address ranges of all mappings of the binary, as found in /proc/<pid>/maps
+pub fn binary_mappings(maps: &str, binary: &Path) -> Vec<(u64, u64)> {
+    let binary = fs::canonicalize(binary).unwrap_or(binary.to_path_buf());
+    maps.lines()
+        .filter(|line| line.split_whitespace().nth(5).is_some_and(|path| Path::new(path) == binary))
+        .filter_map(|line| {
+            let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+            Some((u64::from_str_radix(start, 16).ok()?, u64::from_str_radix(end, 16).ok()?))
+        })
+        .collect()
+}
+
+pub fn is_position_independent(binary: &Path) -> Result<bool> {
+    let header = fs::read(binary).map_err(Error::io(binary))?;
+    Ok(header.len() > 17 && u16::from_le_bytes([header[16], header[17]]) == ET_DYN)
+}
+
+/// Difference between runtime and cwe_checker addresses.
+///
+/// Non-PIE binaries are loaded at their static addresses. PIE binaries are analysed by
+/// cwe_checker relative to address_base_offset and loaded at the first mapping of the file.
+/// None if a PIE binary is not mapped.
+pub fn load_bias(mappings: &[(u64, u64)], is_pie: bool, metadata: &Metadata) -> Option<u64> {
+    if !is_pie {
+        return Some(0);
+    }
+    let load_base = mappings.iter().map(|(start, _)| *start).min()?;
+    load_base.checked_sub(metadata.address_base_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mappings_and_load_bias() {
+        let maps = "555555554000-555555555000 r--p 00000000 fd:01 42 /opt/subjects/fp_gcc_O2\n\
+            555555555000-555555556000 r-xp 00001000 fd:01 42 /opt/subjects/fp_gcc_O2\n\
+            7ffff7dc3000-7ffff7deb000 r--p 00000000 fd:01 7 /usr/lib/libc.so.6\n\
+            7ffffffde000-7ffffffff000 rw-p 00000000 00:00 0 [stack]\n";
+        let mappings = binary_mappings(maps, Path::new("/opt/subjects/fp_gcc_O2"));
+        assert_eq!(mappings, [(0x555555554000, 0x555555555000), (0x555555555000, 0x555555556000)]);
+
+        let metadata = Metadata {
+            address_base_offset: 0x100000,
+            indirect_call_sites: vec![],
+            functions: vec![],
+        };
+        assert_eq!(load_bias(&mappings, true, &metadata), Some(0x555555454000));
+        assert_eq!(load_bias(&mappings, false, &metadata), Some(0));
+        assert_eq!(load_bias(&[], true, &metadata), None);
+    }
+}
@@ -0,0 +1,258 @@
+//! Ground truth from int3 breakpoints on the indirect callsites. Only built on x86_64, as the
+//! breakpoints and the instruction pointer are architecture specific.
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    fs,
+    os::unix::process::CommandExt,
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use log::info;
+use nix::{
+    sys::{
+        ptrace::{self, Options},
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+
+use crate::{
+    cwe_checker::Metadata,
+    error::{Error, Result},
+    process_maps::{binary_mappings, is_position_independent, load_bias},
+    valgrind::{RealCall, ValgrindResult},
+};
+
+/// Writes a single byte. The surrounding bytes are read again, they may contain other breakpoints
+fn write_byte(pid: Pid, addr: u64, byte: u8) -> nix::Result<()> {
+    let word = ptrace::read(pid, addr as *mut c_void)?;
+    ptrace::write(pid, addr as *mut c_void, (word & !0xff) | byte as i64)
+}
+
+const INT3: u8 = 0xcc;
+
+/// Runs the binary under ptrace with a breakpoint on each indirect callsite and records
+/// the target of every call. The process is killed after the timeout.
+///
+/// The binary runs in its own process group, so only its threads are waited for and other
+/// children of the calling process are left alone.
+pub fn run_ptrace_tracer(binary: &Path, metadata: &Metadata, timeout: Duration) -> Result<ValgrindResult> {
+    let mut command = Command::new(binary);
+    // Reading from the terminal would stop a process outside the foreground process group
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).process_group(0);
+    unsafe {
+        command.pre_exec(|| ptrace::traceme().map_err(std::io::Error::from));
+    }
+    let mut child = command.spawn().map_err(|err| Error::tool("ptrace", format!("could not start {}: {}", binary.display(), err)))?;
+    let pid = Pid::from_raw(child.id() as i32);
+
+    // The child stops with SIGTRAP after the exec
+    waitpid(pid, None)?;
+    ptrace::setoptions(pid, Options::PTRACE_O_TRACECLONE | Options::PTRACE_O_EXITKILL)?;
+
+    let maps_path = format!("/proc/{}/maps", pid);
+    let maps = fs::read_to_string(&maps_path).map_err(Error::io(maps_path))?;
+    let mappings = binary_mappings(&maps, binary);
+    let bias = load_bias(&mappings, is_position_independent(binary)?, metadata)
+        .ok_or_else(|| Error::tool("ptrace", "the binary is not mapped"))?;
+    let in_binary = |addr: u64| mappings.iter().any(|(start, end)| *start <= addr && addr < *end);
+    info!("Using load bias: {:#x}", bias);
+
+    // Original byte of every breakpoint
+    let mut breakpoints = HashMap::new();
+    for callsite in &metadata.indirect_call_sites {
+        let addr = callsite + bias;
+        let original = ptrace::read(pid, addr as *mut c_void)? as u8;
+        write_byte(pid, addr, INT3)?;
+        breakpoints.insert(addr, original);
+    }
+
+    // Dropping cancel stops the watchdog, so it never kills a reused pid
+    let (cancel, cancelled) = mpsc::channel::<()>();
+    let watchdog = thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+            let _ = kill(pid, Signal::SIGKILL);
+        }
+    });
+
+    // The process group id is the pid of the binary
+    let process_group = Pid::from_raw(-pid.as_raw());
+    let mut edges: HashMap<(u64, u64), u64> = HashMap::new();
+    ptrace::cont(pid, None)?;
+    'trace: while let Ok(status) = waitpid(process_group, Some(WaitPidFlag::__WALL)) {
+        match status {
+            WaitStatus::Exited(tid, _) | WaitStatus::Signaled(tid, _, _) if tid == pid => break,
+            WaitStatus::Stopped(tid, Signal::SIGTRAP) => {
+                let mut regs = ptrace::getregs(tid)?;
+                let callsite = regs.rip - 1;
+                let Some(original) = breakpoints.get(&callsite) else {
+                    let _ = ptrace::cont(tid, None);
+                    continue;
+                };
+
+                // Execute the original call instruction and read where it went
+                write_byte(tid, callsite, *original)?;
+                regs.rip = callsite;
+                ptrace::setregs(tid, regs)?;
+                ptrace::step(tid, None)?;
+                match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                    Ok(WaitStatus::Stopped(_, Signal::SIGTRAP)) => {
+                        let target = ptrace::getregs(tid)?.rip;
+                        *edges.entry((callsite, target)).or_default() += 1;
+                        write_byte(tid, callsite, INT3)?;
+                        let _ = ptrace::cont(tid, None);
+                    }
+                    Ok(WaitStatus::Stopped(_, signal)) => {
+                        // A signal arrived before the call was executed. It is delivered and the
+                        // call traps again at the restored breakpoint once the handler returned
+                        write_byte(tid, callsite, INT3)?;
+                        let _ = ptrace::cont(tid, signal);
+                    }
+                    Ok(WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _)) if tid == pid => break 'trace,
+                    _ => (),
+                }
+            }
+            WaitStatus::Stopped(tid, Signal::SIGSTOP) | WaitStatus::PtraceEvent(tid, _, _) => {
+                // New threads start stopped, clone events stop the parent
+                let _ = ptrace::cont(tid, None);
+            }
+            WaitStatus::Stopped(tid, signal) => {
+                let _ = ptrace::cont(tid, signal);
+            }
+            _ => (),
+        }
+    }
+    drop(cancel);
+    let _ = watchdog.join();
+    // Already reaped by waitpid, this only releases the handle
+    let _ignore = child.wait();
+
+    let calls = edges
+        .into_iter()
+        .map(|((callsite, target), count)| RealCall {
+            from_instr: callsite - bias,
+            to_instr: if in_binary(target) { target - bias } else { target },
+            in_fn: 0,
+            target_fn: 0,
+            does_jump_object_file: !in_binary(target),
+            call_count: count,
+            inclusive_cost: 0,
+        })
+        .collect();
+
+    // Addresses are already in cwe_checker coordinates, so no main function for offset_based_on_main
+    Ok(ValgrindResult {
+        calls,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf, time::Instant};
+
+    use object::{Object, ObjectSymbol};
+
+    use super::*;
+
+    /// Calls target three times through a pointer, the call instruction is labelled `callsite`.
+    /// Without the red zone the call can not overwrite the locals of main.
+    const INDIRECT_CALL: &str = r#"
+int counter;
+void target(void) { counter++; }
+void (*volatile pointer)(void) = target;
+int main(void) {
+    for (int i = 0; i < 3; i++) {
+        __asm__ volatile(".globl callsite\ncallsite: call *%0" : : "r"(pointer)
+                         : "rax", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11", "memory", "cc");
+    }
+    return counter - 3;
+}
+"#;
+
+    fn compile(dir: &Path, pie: bool) -> PathBuf {
+        let source = dir.join("indirect_call.c");
+        fs::write(&source, INDIRECT_CALL).unwrap();
+        let binary = dir.join(if pie { "indirect_call_pie" } else { "indirect_call" });
+        let flags = if pie { ["-fPIE", "-pie"] } else { ["-fno-pie", "-no-pie"] };
+        let status = Command::new("cc")
+            .args(["-O0", "-mno-red-zone"])
+            .args(flags)
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .expect("cc has to be installed for this test");
+        assert!(status.success());
+        binary
+    }
+
+    fn symbol_address(binary: &Path, name: &str) -> u64 {
+        let data = fs::read(binary).unwrap();
+        let file = object::File::parse(&*data).unwrap();
+        let symbol = file.symbols().find(|symbol| symbol.name() == Ok(name)).unwrap();
+        symbol.address()
+    }
+
+    fn metadata() -> Metadata {
+        Metadata {
+            address_base_offset: 0,
+            indirect_call_sites: vec![],
+            functions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_leaves_other_children() {
+        // An exited child of the test process, which the tracer must not reap
+        let mut other = Command::new("/bin/true").spawn().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let result = run_ptrace_tracer(Path::new("/bin/true"), &metadata(), Duration::from_secs(10)).unwrap();
+        assert!(result.calls.is_empty());
+        assert!(other.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("sleep.sh");
+        fs::write(&script, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let start = Instant::now();
+        run_ptrace_tracer(&script, &metadata(), Duration::from_millis(200)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_indirect_call() {
+        let dir = tempfile::tempdir().unwrap();
+        for pie in [false, true] {
+            let binary = compile(dir.path(), pie);
+            // cwe_checker analyses PIE binaries at address_base_offset
+            let offset = if pie { 0x100000 } else { 0 };
+            let callsite = symbol_address(&binary, "callsite") + offset;
+            let target = symbol_address(&binary, "target") + offset;
+            let metadata = Metadata {
+                address_base_offset: 0x100000,
+                indirect_call_sites: vec![callsite],
+                functions: vec![],
+            };
+
+            let result = run_ptrace_tracer(&binary, &metadata, Duration::from_secs(10)).unwrap();
+            let edges = result
+                .calls
+                .iter()
+                .map(|call| (call.from_instr, call.to_instr, call.call_count, call.does_jump_object_file))
+                .collect::<Vec<_>>();
+            assert_eq!(edges, [(callsite, target, 3, false)], "pie: {}", pie);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    error::{Error, Result},
+    valgrind::{RealCall, ValgrindResult},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub pc: u64,
+    pub disasm: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TraceLine {
+    /// An instruction of a translated block, from `-d in_asm`
+    BlockInstruction(Instruction),
+    /// Start of a new translated block (`IN:`)
+    BlockStart,
+    /// Execution of the block starting at pc, from `-d exec`
+    BlockExecuted(u64),
+    /// Execution of a single instruction, from the execlog plugin
+    Executed(Instruction),
+}
+
+/// Call mnemonics of the architectures supported by cwe_checker
+const CALL_MNEMONICS: [&str; 13] = [
+    "call", "callq", "calll", "bl", "blx", "blr", "blraa", "blrab", "jal", "jalr", "bal", "bgezal", "bltzal",
+];
+/// MIPS calls execute the next instruction before jumping to the target
+const DELAY_SLOT_MNEMONICS: [&str; 5] = ["jal", "jalr", "bal", "bgezal", "bltzal"];
+
+fn mnemonic(disasm: &str) -> &str {
+    disasm.split_whitespace().next().unwrap_or("")
+}
+
+fn is_call(disasm: &str) -> bool {
+    CALL_MNEMONICS.contains(&mnemonic(disasm))
+}
+
+fn has_delay_slot(disasm: &str) -> bool {
+    DELAY_SLOT_MNEMONICS.contains(&mnemonic(disasm))
+}
+
+fn parse_hex(input: &str) -> Option<u64> {
+    u64::from_str_radix(input.trim().trim_start_matches("0x"), 16).ok()
+}
+
+/// `0x0000000000401195:  ff d2                    callq    *%rdx`, older versions omit the bytes
+fn parse_in_asm_instruction(line: &str) -> Option<Instruction> {
+    let (pc, rest) = line.split_once(':')?;
+    let pc = parse_hex(pc.strip_prefix("0x")?)?;
+    let rest = rest.trim();
+    // Skip the opcode bytes, they are hex digit groups before the mnemonic
+    let mut tokens = rest.split_whitespace().peekable();
+    let mut skipped = 0;
+    while let Some(token) = tokens.peek() {
+        if token.chars().all(|c| c.is_ascii_hexdigit()) && token.len() % 2 == 0 && !CALL_MNEMONICS.contains(token) {
+            tokens.next();
+            skipped += 1;
+        } else {
+            break;
+        }
+    }
+    let disasm = if skipped == 0 { rest.to_string() } else { tokens.collect::<Vec<&str>>().join(" ") };
+    Some(Instruction { pc, disasm })
+}
+
+/// `Trace 0: 0x7f0c3c000100 [00000000/0000000000401143/00000000/ff000000] main`
+/// or in older versions `Trace 0x7f0c3c000100 [0000000000401143] main`
+fn parse_exec_line(line: &str) -> Option<u64> {
+    let fields = line.split_once('[')?.1.split_once(']')?.0;
+    let fields = fields.split('/').collect::<Vec<&str>>();
+    parse_hex(if fields.len() == 1 { fields[0] } else { fields[1] })
+}
+
+/// `0, 0x401195, 0xd2ff, "call *%rdx"`, possibly followed by memory accesses
+fn parse_execlog_line(line: &str) -> Option<Instruction> {
+    let mut fields = line.splitn(4, ", ");
+    let _cpu = fields.next()?.trim().parse::<u64>().ok()?;
+    let pc = parse_hex(fields.next()?.strip_prefix("0x")?)?;
+    let _opcode = fields.next()?;
+    let disasm = fields.next()?.strip_prefix('"')?.split('"').next()?;
+    Some(Instruction { pc, disasm: disasm.to_string() })
+}
+
+fn parse_line(line: &str) -> Option<TraceLine> {
+    if line.starts_with("IN:") {
+        return Some(TraceLine::BlockStart);
+    }
+    if line.starts_with("Trace ") {
+        return parse_exec_line(line).map(TraceLine::BlockExecuted);
+    }
+    if line.starts_with("0x") {
+        return parse_in_asm_instruction(line).map(TraceLine::BlockInstruction);
+    }
+    parse_execlog_line(line).map(TraceLine::Executed)
+}
+
+/// Reconstructs all executed calls as (callsite, target) pairs with their counts.
+///
+/// For `-d in_asm,exec,nochain` logs the calls are the last instruction (or the one before a
+/// delay slot) of a block, the target is the next executed block. For the execlog plugin the
+/// target is the instruction executed after the call.
+pub fn parse_qemu_log(content: &str) -> HashMap<(u64, u64), u64> {
+    let mut blocks: HashMap<u64, Vec<Instruction>> = HashMap::new();
+    let mut current_block: Option<u64> = None;
+    let mut pending_call: Option<(u64, bool)> = None;
+    let mut edges: HashMap<(u64, u64), u64> = HashMap::new();
+
+    for line in content.lines() {
+        match parse_line(line.trim_end()) {
+            Some(TraceLine::BlockStart) => current_block = None,
+            Some(TraceLine::BlockInstruction(instruction)) => {
+                let start = *current_block.get_or_insert(instruction.pc);
+                let block = blocks.entry(start).or_default();
+                if start == instruction.pc {
+                    // Retranslated block
+                    block.clear();
+                }
+                block.push(instruction);
+            }
+            Some(TraceLine::BlockExecuted(pc)) => {
+                if let Some((callsite, _)) = pending_call.take() {
+                    *edges.entry((callsite, pc)).or_default() += 1;
+                }
+                let Some(block) = blocks.get(&pc) else {
+                    continue;
+                };
+                let call = block.iter().rev().take(2).enumerate().find(|(index, instruction)| {
+                    is_call(&instruction.disasm) && (*index == 0 || has_delay_slot(&instruction.disasm))
+                });
+                pending_call = call.map(|(_, instruction)| (instruction.pc, false));
+            }
+            Some(TraceLine::Executed(instruction)) => {
+                match pending_call {
+                    // Delay slot, the target comes next
+                    Some((callsite, true)) => pending_call = Some((callsite, false)),
+                    Some((callsite, false)) => {
+                        *edges.entry((callsite, instruction.pc)).or_default() += 1;
+                        pending_call = None;
+                    }
+                    None => (),
+                }
+                if is_call(&instruction.disasm) {
+                    pending_call = Some((instruction.pc, has_delay_slot(&instruction.disasm)));
+                }
+            }
+            None => (),
+        }
+    }
+    edges
+}
+
+/// Loads a QEMU user-mode log. Addresses are moved into cwe_checker coordinates by subtracting
+/// the load bias, targets outside program_range are marked as calls into other objects.
+pub fn load_qemu_log(path: &Path, load_bias: u64, program_range: Option<(u64, u64)>) -> Result<ValgrindResult> {
+    let content = fs::read_to_string(path).map_err(Error::io(path))?;
+    let calls = parse_qemu_log(&content)
+        .into_iter()
+        .map(|((callsite, target), count)| {
+            let to_instr = target.wrapping_sub(load_bias);
+            RealCall {
+                from_instr: callsite.wrapping_sub(load_bias),
+                to_instr,
+                in_fn: 0,
+                target_fn: 0,
+                does_jump_object_file: program_range.is_some_and(|(start, end)| to_instr < start || end < to_instr),
+                call_count: count,
+                inclusive_cost: 0,
+            }
+        })
+        .collect();
+
+    Ok(ValgrindResult {
+        calls,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_asm_exec() {
+        let log = "----------------
+IN: main
+0x00010450:  e92d4800  push     {fp, lr}
+0x00010468:  e12fff33  blx      r3
+
+Trace 0: 0x7f6a4c000100 [00000000/00010450/00000010/ff200000] main
+Trace 0: 0x7f6a4c000200 [00000000/00010500/00000010/ff200000] callback
+";
+        let edges = parse_qemu_log(log);
+        assert_eq!(edges.get(&(0x10468, 0x10500)), Some(&1));
+    }
+
+    #[test]
+    fn test_execlog() {
+        let log = "0, 0x401190, 0x8b48, \"movq 0x8(%rbx), %rdx\"
+0, 0x401195, 0xd2ff, \"callq *%rdx\"
+0, 0x401126, 0x55, \"pushq %rbp\", store, 0x7ffc00000000
+0, 0x400100, 0x0c, \"jalr $t9\"
+0, 0x400104, 0x0, \"nop\"
+0, 0x400200, 0x0, \"addiu $sp, $sp, -32\"
+";
+        let edges = parse_qemu_log(log);
+        assert_eq!(edges.get(&(0x401195, 0x401126)), Some(&1));
+        assert_eq!(edges.get(&(0x400100, 0x400200)), Some(&1));
+        assert_eq!(edges.len(), 2);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use crate::{
+    cwe_checker::{CweCheckerResult, Function},
+    dynamic_call_graph::DynamicCallGraph,
+    soundness_test::offset_based_on_main,
+    valgrind::ValgrindResult,
+};
+
+/// Names always used as roots, if they exist in the binary
+pub const DEFAULT_ROOTS: [&str; 3] = ["_start", "entry", "main"];
+
+pub struct UnreachableFunction {
+    pub function: Function,
+    /// Names of the functions on the shortest observed call chain reaching the function
+    pub runtime_chain: Vec<String>,
+}
+
+pub struct ReachabilityReport {
+    pub roots: Vec<Function>,
+    pub statically_reachable: usize,
+    pub executed: usize,
+    pub unreachable: Vec<UnreachableFunction>,
+}
+
+/// Function level successors in the static call graph, keyed by function start address
+fn static_successors(cwe_checker: &CweCheckerResult) -> HashMap<u64, HashSet<u64>> {
+    let mut successors: HashMap<u64, HashSet<u64>> = HashMap::new();
+    for callsite in cwe_checker.call_sites() {
+        let Some(caller) = cwe_checker.get_function_containing(callsite.callsite_loc) else {
+            continue;
+        };
+        for target in &callsite.targets {
+            if let Some(callee) = cwe_checker.get_function_containing(*target) {
+                successors.entry(caller.address).or_default().insert(callee.address);
+            }
+        }
+    }
+    successors
+}
+
+/// Start addresses of all functions transitively reachable from the roots
+pub fn statically_reachable(cwe_checker: &CweCheckerResult, roots: &[Function]) -> HashSet<u64> {
+    let successors = static_successors(cwe_checker);
+    let mut reachable = HashSet::new();
+    let mut queue = roots.iter().map(|root| root.address).collect::<VecDeque<u64>>();
+    while let Some(current) = queue.pop_front() {
+        if !reachable.insert(current) {
+            continue;
+        }
+        for next in successors.get(&current).into_iter().flatten() {
+            queue.push_back(*next);
+        }
+    }
+    reachable
+}
+
+/// Maps the callgrind fn= numbers to the functions known to cwe_checker
+fn executed_functions(cwe_checker: &CweCheckerResult, real: &ValgrindResult) -> Vec<(u64, Function)> {
+    let offset = offset_based_on_main(cwe_checker, real);
+    let mut executed = vec![];
+    for (fn_id, base_address) in &real.base_address_mapping {
+        let address = ((*base_address as i64) - offset) as u64;
+        let name = real.valgrind_name_cache.get(*fn_id);
+        let function = cwe_checker
+            .metadata
+            .functions
+            .iter()
+            .find(|func| func.address == address)
+            .or_else(|| cwe_checker.metadata.functions.iter().find(|func| func.name == name));
+        if let Some(function) = function {
+            executed.push((*fn_id, function.clone()));
+        }
+    }
+    executed.sort_by_key(|(_, function)| function.address);
+    executed
+}
+
+pub fn reachability(cwe_checker: &CweCheckerResult, real: &ValgrindResult, extra_roots: &[String]) -> ReachabilityReport {
+    let roots = cwe_checker
+        .metadata
+        .functions
+        .iter()
+        .filter(|func| DEFAULT_ROOTS.contains(&func.name.as_str()) || extra_roots.contains(&func.name))
+        .cloned()
+        .collect::<Vec<Function>>();
+    let reachable = statically_reachable(cwe_checker, &roots);
+
+    let dynamic_call_graph = DynamicCallGraph::from_valgrind(real);
+    let dynamic_roots = real
+        .valgrind_name_cache
+        .name_cache
+        .iter()
+        .filter(|(_, name)| roots.iter().any(|root| &root.name == *name))
+        .map(|(fn_id, _)| *fn_id)
+        .collect::<Vec<u64>>();
+    let entry_functions = dynamic_call_graph.entry_functions();
+
+    let executed = executed_functions(cwe_checker, real);
+    let mut unreachable = vec![];
+    for (fn_id, function) in &executed {
+        if reachable.contains(&function.address) {
+            continue;
+        }
+        let chain = dynamic_call_graph
+            .shortest_chain(&dynamic_roots, *fn_id)
+            .or_else(|| dynamic_call_graph.shortest_chain(&entry_functions, *fn_id))
+            .unwrap_or_default();
+        unreachable.push(UnreachableFunction {
+            function: function.clone(),
+            runtime_chain: chain.iter().map(|fn_id| real.valgrind_name_cache.get(*fn_id)).collect(),
+        });
+    }
+
+    ReachabilityReport {
+        roots,
+        statically_reachable: reachable.len(),
+        executed: executed.len(),
+        unreachable,
+    }
+}
+
+pub fn render_reachability(report: &ReachabilityReport) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "Roots: {}",
+        report
+            .roots
+            .iter()
+            .map(|root| format!("{} ({:#x})", root.name, root.address))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+    .unwrap();
+    for unreachable in &report.unreachable {
+        writeln!(
+            out,
+            "\t[!] UNREACHABLE: {} ({:#x})",
+            unreachable.function.name, unreachable.function.address
+        )
+        .unwrap();
+        if !unreachable.runtime_chain.is_empty() {
+            writeln!(out, "\t\tvia {}", unreachable.runtime_chain.join(" -> ")).unwrap();
+        }
+    }
+    let covered = report.executed - report.unreachable.len();
+    // Nothing is executed without the function starts of a callgrind trace, e.g. with --callee-csv
+    let percentage = if report.executed == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}%", covered as f32 / report.executed as f32 * 100.0)
+    };
+    writeln!(
+        out,
+        "Statically reachable functions: {}, executed: {}, executed and reachable: {} ({})",
+        report.statically_reachable, report.executed, covered, percentage
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Metadata},
+        valgrind::{RealCall, ValgrindNameCache},
+    };
+
+    #[test]
+    fn test_reachability() {
+        let functions = [("main", 0x1000), ("parse", 0x1100), ("unused", 0x1200), ("callback", 0x1300), ("init", 0x1400)];
+        let static_call = |from_instr: u64, to_instr: u64| Call {
+            from_instr,
+            to_instr: Some(to_instr),
+            is_indirect: false,
+        };
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1110],
+                functions: functions
+                    .iter()
+                    .map(|(name, address)| Function {
+                        name: name.to_string(),
+                        address: *address,
+                    })
+                    .collect(),
+            },
+            calls: vec![static_call(0x1010, 0x1100), static_call(0x1108, 0x1200)],
+        });
+        // callback is called through the unresolved callsite in parse, init by the startup code
+        let call = |in_fn: i64, target_fn: i64| RealCall {
+            from_instr: 0,
+            to_instr: 0,
+            in_fn,
+            target_fn,
+            does_jump_object_file: false,
+            call_count: 1,
+            inclusive_cost: 1,
+        };
+        let names = ["(below main)", "main", "parse", "callback", "init"];
+        let real = ValgrindResult {
+            calls: vec![call(0, 1), call(1, 2), call(2, 3), call(0, 4)],
+            valgrind_name_cache: ValgrindNameCache {
+                name_cache: names.iter().enumerate().map(|(fn_id, name)| (fn_id as u64, name.to_string())).collect(),
+                ..Default::default()
+            },
+            base_address_mapping: HashMap::from([(1, 0x1000), (2, 0x1100), (3, 0x1300), (4, 0x1400)]),
+            ..Default::default()
+        };
+
+        let report = reachability(&cwe_checker, &real, &[]);
+        assert_eq!(report.roots.iter().map(|root| root.name.as_str()).collect::<Vec<&str>>(), ["main"]);
+        assert_eq!((report.statically_reachable, report.executed), (3, 4));
+        let unreachable = report
+            .unreachable
+            .iter()
+            .map(|function| (function.function.name.as_str(), function.runtime_chain.join(" -> ")))
+            .collect::<Vec<_>>();
+        // init is not reached from main, so its chain starts at the entry function
+        assert_eq!(
+            unreachable,
+            [("callback", "main -> parse -> callback".to_string()), ("init", "(below main) -> init".to_string())]
+        );
+
+        let report = reachability(&cwe_checker, &real, &["init".to_string()]);
+        assert_eq!(report.statically_reachable, 4);
+        assert_eq!(report.unreachable.len(), 1);
+
+        let empty = ReachabilityReport {
+            roots: vec![],
+            statically_reachable: 0,
+            executed: 0,
+            unreachable: vec![],
+        };
+        assert!(render_reachability(&empty).ends_with("executed and reachable: 0 (-)\n"));
+    }
+}
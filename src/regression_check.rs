@@ -0,0 +1,131 @@
+use std::{collections::HashSet, fmt::Write};
+
+use crate::soundness_test::{SoundnessReport, UnsoundEdge};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Regression {
+    /// Unsound edges that are not in the baseline, as callsite and target
+    NewUnsoundEdges(Vec<(u64, String)>),
+    /// Sound percentage of the baseline and the current report
+    SoundnessDrop { baseline: f32, current: f32 },
+    /// AICT of the baseline and the current report
+    AictIncrease { baseline: f32, current: f32 },
+}
+
+impl Regression {
+    /// Distinct exit codes so scripts can tell the failures apart, 1 is used for other errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Regression::NewUnsoundEdges(_) => 2,
+            Regression::SoundnessDrop { .. } => 3,
+            Regression::AictIncrease { .. } => 4,
+        }
+    }
+}
+
+/// Allowed regressions before the check fails
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    /// In percentage points
+    pub max_soundness_drop: f32,
+    pub max_aict_increase: f32,
+}
+
+/// Extern targets are compared by name, their addresses change between runs
+fn edge_key(edge: &UnsoundEdge) -> (u64, String) {
+    let target = match &edge.target_symbol {
+        Some(symbol) => symbol.clone(),
+        None => format!("{:#x}", edge.target),
+    };
+    (edge.callsite, target)
+}
+
+pub fn check_against_baseline(current: &SoundnessReport, baseline: &SoundnessReport, thresholds: &Thresholds) -> Vec<Regression> {
+    let mut regressions = vec![];
+
+    let known_edges = baseline.unsound_edges.iter().map(edge_key).collect::<HashSet<(u64, String)>>();
+    let mut new_edges = current
+        .unsound_edges
+        .iter()
+        .map(edge_key)
+        .filter(|edge| !known_edges.contains(edge))
+        .collect::<Vec<(u64, String)>>();
+    new_edges.sort();
+    new_edges.dedup();
+    if !new_edges.is_empty() {
+        regressions.push(Regression::NewUnsoundEdges(new_edges));
+    }
+
+    if let (Some(baseline), Some(current)) = (baseline.sound_percentage(), current.sound_percentage()) {
+        if baseline - current > thresholds.max_soundness_drop {
+            regressions.push(Regression::SoundnessDrop { baseline, current });
+        }
+    }
+
+    if let (Some(baseline), Some(current)) = (baseline.aict, current.aict) {
+        if current - baseline > thresholds.max_aict_increase {
+            regressions.push(Regression::AictIncrease { baseline, current });
+        }
+    }
+    regressions
+}
+
+pub fn render_regressions(regressions: &[Regression]) -> String {
+    let mut out = String::new();
+    if regressions.is_empty() {
+        writeln!(out, "No regressions against the baseline").unwrap();
+        return out;
+    }
+    writeln!(out, "Regressions against the baseline:").unwrap();
+    for regression in regressions {
+        match regression {
+            Regression::NewUnsoundEdges(edges) => {
+                writeln!(out, "\t{} new unsound edges", edges.len()).unwrap();
+                for (callsite, target) in edges {
+                    writeln!(out, "\t\t{:#x} -> {}", callsite, target).unwrap();
+                }
+            }
+            Regression::SoundnessDrop { baseline, current } => {
+                writeln!(out, "\tSoundness dropped from {:.2}% to {:.2}%", baseline, current).unwrap();
+            }
+            Regression::AictIncrease { baseline, current } => {
+                writeln!(out, "\tAICT increased from {:.2} to {:.2}", baseline, current).unwrap();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(sound_calls: i64, aict: f32, unsound_edges: &[(u64, u64)]) -> SoundnessReport {
+        SoundnessReport {
+            checked_calls: 10,
+            sound_calls,
+            aict: Some(aict),
+            unsound_edges: unsound_edges
+                .iter()
+                .map(|(callsite, target)| UnsoundEdge {
+                    callsite: *callsite,
+                    target: *target,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check_against_baseline() {
+        let baseline = report(9, 2.0, &[(0x401195, 0x401135)]);
+        let thresholds = Thresholds { max_soundness_drop: 5.0, max_aict_increase: 0.5 };
+        assert!(check_against_baseline(&report(9, 2.4, &[(0x401195, 0x401135)]), &baseline, &thresholds).is_empty());
+
+        let regressions = check_against_baseline(&report(8, 3.0, &[(0x401195, 0x401135), (0x4011a0, 0x401126)]), &baseline, &thresholds);
+        let exit_codes = regressions.iter().map(Regression::exit_code).collect::<Vec<i32>>();
+        assert_eq!(exit_codes, vec![2, 3, 4]);
+        assert_eq!(regressions[0], Regression::NewUnsoundEdges(vec![(0x4011a0, "0x401126".to_string())]));
+    }
+}
@@ -0,0 +1,245 @@
+//! Runs cwe_checker on another machine over ssh, e.g. a large cloud server. The host only needs
+//! nix with flakes, e.g. installed with
+//! `curl -sSf -L https://install.lix.systems/lix | sh -s -- install --enable-flakes --no-confirm`.
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use log::{info, warn};
+
+use crate::{
+    cache::ArtifactCache,
+    cwe_checker::{cached_analysis, CweCheckerResult, CWE_CHECKER_ARGS},
+    error::{Error, Result},
+};
+
+/// cwe_checker with the call graph export, run through `nix run` on the remote host. The branch
+/// is locked to its current revision before each analysis
+pub const CWE_CHECKER_FLAKE: &str = "github:felix-ulonska/cwe_checker/feat/split_stack_mem";
+
+const REMOTE_ARTIFACT: &str = "call_graph.json";
+
+#[derive(Clone, Debug)]
+pub struct RemoteHost {
+    /// `[user@]host` as understood by ssh and scp
+    pub destination: String,
+    /// Program and options, e.g. `["ssh", "-p", "2222"]`. The destination and the command are appended
+    pub ssh: Vec<String>,
+    /// Program and options, e.g. `["scp", "-P", "2222"]`. Source and target are appended
+    pub scp: Vec<String>,
+    pub flake: String,
+}
+
+impl RemoteHost {
+    pub fn new(destination: &str) -> RemoteHost {
+        RemoteHost {
+            destination: destination.to_string(),
+            ssh: vec!["ssh".to_string()],
+            scp: vec!["scp".to_string()],
+            flake: CWE_CHECKER_FLAKE.to_string(),
+        }
+    }
+
+    fn command(program: &[String]) -> Command {
+        let mut command = Command::new(&program[0]);
+        command.args(&program[1..]);
+        command
+    }
+
+    fn ssh(&self, script: &str) -> Command {
+        let mut command = Self::command(&self.ssh);
+        command.arg(&self.destination).arg(script);
+        command
+    }
+
+    /// Runs the script on the host and returns its stdout
+    fn run(&self, script: &str) -> Result<String> {
+        let output = self.ssh(script).output().map_err(|err| Error::tool("ssh", err))?;
+        if !output.status.success() {
+            return Err(Error::tool("ssh", format!("`{}` failed: {}", script, String::from_utf8_lossy(&output.stderr).trim())));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let status = Self::command(&self.scp)
+            .arg(from)
+            .arg(to)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|err| Error::tool("scp", err))?;
+        if !status.success() {
+            return Err(Error::tool("scp", format!("copying {} to {} failed with {}", from, to, status)));
+        }
+        Ok(())
+    }
+
+    fn remote_path(&self, path: &str) -> String {
+        format!("{}:{}", self.destination, path)
+    }
+
+    /// Identifies the cwe_checker build in the cache key, only unique for a locked flake
+    pub fn cwe_checker_version(&self) -> String {
+        format!("nix run {}", self.flake)
+    }
+
+    /// The same host with the flake locked to the revision it currently resolves to, so a moving
+    /// branch gets a new cache key and the run uses the recorded revision
+    pub fn locked(&self) -> Result<RemoteHost> {
+        let metadata = self.run(&format!(
+            "nix flake metadata --json --refresh --extra-experimental-features 'nix-command flakes' {}",
+            shell_quote(&self.flake)
+        ))?;
+        let flake = serde_json::from_str::<serde_json::Value>(&metadata)
+            .ok()
+            .and_then(|metadata| metadata["url"].as_str().map(str::to_string))
+            .ok_or_else(|| Error::tool("nix", format!("no locked url for {} in `{}`", self.flake, metadata)))?;
+        info!("Using {} on {}", flake, self.destination);
+        Ok(RemoteHost { flake, ..self.clone() })
+    }
+
+    /// Copies the binary to a temporary folder on the host, runs cwe_checker there and copies the
+    /// call graph to output_file. The folder is removed afterwards, also if cwe_checker failed.
+    pub fn run_cwe_checker(&self, binary: &Path, output_file: &Path) -> Result<()> {
+        let work_dir = self.run("mktemp -d")?;
+        if work_dir.is_empty() {
+            return Err(Error::tool("ssh", "mktemp -d printed no folder"));
+        }
+        let result = self.run_in(&work_dir, binary, output_file);
+        if let Err(err) = self.run(&format!("rm -rf {}", shell_quote(&work_dir))) {
+            warn!("Could not remove {} on {}: {}", work_dir, self.destination, err);
+        }
+        result
+    }
+
+    fn run_in(&self, work_dir: &str, binary: &Path, output_file: &Path) -> Result<()> {
+        let binary_name = remote_file_name(binary);
+        info!("Copying {} to {}", binary.display(), self.destination);
+        self.copy(&binary.to_string_lossy(), &self.remote_path(&format!("{}/{}", work_dir, binary_name)))?;
+
+        let script = cwe_checker_script(&self.flake, work_dir, &binary_name);
+        info!("Running cwe_checker on {}", self.destination);
+        let mut child = self
+            .ssh(&script)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| Error::tool("ssh", err))?;
+        let mut last_line = String::new();
+        if let Some(stderr) = child.stderr.take() {
+            for line in BufReader::new(stderr).lines().map_while(std::io::Result::ok) {
+                info!("[{}] {}", self.destination, line);
+                last_line = line;
+            }
+        }
+        let status = child.wait().map_err(|err| Error::tool("ssh", err))?;
+        if !status.success() {
+            return Err(Error::tool("cwe_checker", format!("failed on {} with {}: {}", self.destination, status, last_line)));
+        }
+
+        self.copy(&self.remote_path(&format!("{}/{}", work_dir, REMOTE_ARTIFACT)), &output_file.to_string_lossy())
+    }
+}
+
+/// scp paths are interpreted by the remote shell or not, depending on the protocol, so the
+/// binary is copied under a name without special characters
+fn remote_file_name(binary: &Path) -> String {
+    let name = binary.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect::<String>();
+    if name.is_empty() || name.starts_with('.') {
+        format!("binary{}", name)
+    } else {
+        name
+    }
+}
+
+/// Single quotes for the remote shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+fn cwe_checker_script(flake: &str, work_dir: &str, binary_name: &str) -> String {
+    format!(
+        "cd {} && nix run {} --extra-experimental-features 'nix-command flakes' -- {} {} > {}",
+        shell_quote(work_dir),
+        shell_quote(flake),
+        CWE_CHECKER_ARGS.join(" "),
+        shell_quote(binary_name),
+        REMOTE_ARTIFACT
+    )
+}
+
+/// Same as complete_analysis, but cwe_checker runs on the remote host. Also returns the host
+/// with the locked flake that was run or found in the cache
+pub fn remote_analysis(binary: &Path, host: &RemoteHost, cache: &ArtifactCache) -> Result<(CweCheckerResult, RemoteHost)> {
+    let host = host.locked()?;
+    let result = cached_analysis(binary, Some(host.cwe_checker_version()), cache, |binary, output_file| {
+        host.run_cwe_checker(binary, output_file)
+    })?;
+    Ok((result, host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheKey, CacheMode};
+
+    #[test]
+    fn test_cwe_checker_script() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(remote_file_name(Path::new("/tmp/my bin")), "my_bin");
+        assert_eq!(
+            cwe_checker_script("github:a/b", "/tmp/tmp.x", "fp"),
+            "cd '/tmp/tmp.x' && nix run 'github:a/b' --extra-experimental-features 'nix-command flakes' -- --debug i-call-rec-vsa 'fp' > call_graph.json"
+        );
+    }
+
+    /// ssh runs the script in a local shell with stubs for mktemp and nix, scp copies locally
+    fn stub_host(dir: &Path, nix_run: &str) -> RemoteHost {
+        let stubs = format!(
+            "mktemp() {{ mkdir {work} && echo {work}; }}; \
+             nix() {{ case \"$1\" in flake) echo '{{\"url\": \"github:a/b/0123\"}}';; run) [ \"$2\" = github:a/b/0123 ] && {nix_run};; esac; }}; \
+             eval \"$2\"",
+            work = dir.join("work").display(),
+        );
+        let mut host = RemoteHost::new("stub");
+        host.ssh = vec!["sh".to_string(), "-c".to_string(), stubs, "ssh".to_string()];
+        host.scp = ["sh", "-c", "cp \"${1#*:}\" \"${2#*:}\"", "scp"].iter().map(|arg| arg.to_string()).collect();
+        host.flake = "github:a/b".to_string();
+        host
+    }
+
+    #[test]
+    fn test_remote_analysis() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("fp");
+        std::fs::write(&binary, "binary").unwrap();
+        let call_graph = dir.path().join("export.json");
+        std::fs::write(
+            &call_graph,
+            r#"{"metadata":{"address_base_offset":0,"indirect_call_sites":[4096],"functions":[{"name":"main","address":4096}]},"calls":[]}"#,
+        )
+        .unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache"), CacheMode::Use);
+
+        let host = stub_host(dir.path(), &format!("cat {}", call_graph.display()));
+        let (result, locked) = remote_analysis(&binary, &host, &cache).unwrap();
+        assert_eq!(result.metadata.indirect_call_sites, [4096]);
+        assert_eq!(locked.cwe_checker_version(), "nix run github:a/b/0123");
+        assert!(!dir.path().join("work").exists());
+        // The call graph is cached under the locked revision
+        let key = CacheKey::new(&binary, "cwe_checker", Some("nix run github:a/b/0123".to_string()), &CWE_CHECKER_ARGS, "call_graph.json").unwrap();
+        assert!(cache.get(&key).is_some());
+
+        // The work folder is also removed if cwe_checker fails
+        let host = stub_host(dir.path(), "echo panicked >&2; return 1");
+        let err = host.locked().unwrap().run_cwe_checker(&binary, &dir.path().join("out.json")).unwrap_err();
+        assert!(err.to_string().contains("panicked"));
+        assert!(!dir.path().join("work").exists());
+    }
+}
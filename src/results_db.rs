@@ -0,0 +1,426 @@
+//! SQLite database collecting the soundness results of many runs, for comparisons across
+//! compilers, binaries and cwe_checker versions.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use rusqlite::{params, types::ValueRef, Connection, OptionalExtension};
+
+use crate::{
+    benchmark_log::BenchmarkRecord,
+    build_subjects::split_binary_name,
+    cwe_checker::CweCheckerResult,
+    error::{Error, Result},
+    soundness_test::SoundnessReport,
+};
+
+/// Stored in `PRAGMA user_version`, bumped on incompatible schema changes
+pub const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS binaries (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- Empty if the binary was not available, e.g. for results of a recorded trace
+    sha256 TEXT NOT NULL DEFAULT '',
+    compiler TEXT,
+    opt TEXT,
+    UNIQUE (name, sha256)
+);
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    binary_id INTEGER NOT NULL REFERENCES binaries(id),
+    started_at TEXT,
+    finished_at TEXT,
+    tool_version TEXT,
+    cwe_checker_version TEXT,
+    ground_truth TEXT,
+    tracer TEXT,
+    host TEXT,
+    checked_calls INTEGER NOT NULL,
+    sound_calls INTEGER NOT NULL,
+    aict REAL,
+    checked_direct_calls INTEGER NOT NULL,
+    sound_direct_calls INTEGER NOT NULL,
+    -- The whole run manifest as JSON
+    manifest TEXT
+);
+CREATE TABLE IF NOT EXISTS callsites (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    address INTEGER NOT NULL,
+    function TEXT,
+    -- NULL if the call graph was not available
+    static_targets INTEGER,
+    observed_targets INTEGER NOT NULL,
+    unsound_targets INTEGER NOT NULL,
+    PRIMARY KEY (run_id, address)
+);
+CREATE TABLE IF NOT EXISTS edges (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    callsite INTEGER NOT NULL,
+    target INTEGER NOT NULL,
+    target_symbol TEXT,
+    target_object TEXT,
+    sound INTEGER NOT NULL
+);
+-- cwe_checker runtime, memory usage and program size from the benchmark build, one row per binary
+CREATE TABLE IF NOT EXISTS benchmarks (
+    binary_id INTEGER PRIMARY KEY REFERENCES binaries(id),
+    program_size INTEGER,
+    complexity INTEGER,
+    runtime_minutes REAL,
+    memory_gib REAL,
+    -- All sizes of the log as JSON
+    sizes TEXT NOT NULL
+);
+";
+
+fn db_error(path: &Path) -> impl Fn(rusqlite::Error) -> Error + '_ {
+    move |source| Error::Database {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+pub struct ResultsDb {
+    connection: Connection,
+    path: PathBuf,
+}
+
+impl ResultsDb {
+    /// Opens the database and creates the tables if needed
+    pub fn open(path: &Path) -> Result<ResultsDb> {
+        let connection = Connection::open(path).map_err(db_error(path))?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(db_error(path))?;
+        if version > SCHEMA_VERSION {
+            return Err(Error::Config(format!(
+                "{} has schema version {}, this version supports up to {}",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            )));
+        }
+        connection.execute_batch(SCHEMA).map_err(db_error(path))?;
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(db_error(path))?;
+        Ok(ResultsDb {
+            connection,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Takes the transaction of the insert it is used for. Without a hash, the latest binary of
+    /// that name is taken if there is one.
+    fn binary_id(connection: &Connection, name: &str, sha256: &str) -> rusqlite::Result<i64> {
+        if sha256.is_empty() {
+            let known = connection
+                .query_row("SELECT id FROM binaries WHERE name = ?1 ORDER BY id DESC LIMIT 1", params![name], |row| row.get(0))
+                .optional()?;
+            if let Some(id) = known {
+                return Ok(id);
+            }
+        }
+        let (compiler, opt) = split_binary_name(name).map(|(_, compiler, opt)| (compiler, opt)).unzip();
+        connection.execute(
+            "INSERT OR IGNORE INTO binaries (name, sha256, compiler, opt) VALUES (?1, ?2, ?3, ?4)",
+            params![name, sha256, compiler, opt],
+        )?;
+        connection.query_row(
+            "SELECT id FROM binaries WHERE name = ?1 AND sha256 = ?2",
+            params![name, sha256],
+            |row| row.get(0),
+        )
+    }
+
+    /// Stores the report as a new run and returns its id. The binary name is taken from the
+    /// manifest if there is one. Without the call graph, the static targets are unknown.
+    pub fn store(&mut self, report: &SoundnessReport, cwe_checker: Option<&CweCheckerResult>, binary_name: &str) -> Result<i64> {
+        self.store_run(report, cwe_checker, binary_name).map_err(db_error(&self.path))
+    }
+
+    fn store_run(&mut self, report: &SoundnessReport, cwe_checker: Option<&CweCheckerResult>, binary_name: &str) -> rusqlite::Result<i64> {
+        let manifest = report.manifest.as_ref();
+        let binary = manifest.and_then(|manifest| manifest.binary.as_ref());
+        let name = binary
+            .and_then(|binary| Path::new(&binary.path).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| binary_name.to_string());
+
+        let transaction = self.connection.transaction()?;
+        let binary_id = Self::binary_id(&transaction, &name, binary.map(|binary| binary.sha256.as_str()).unwrap_or(""))?;
+        transaction.execute(
+            "INSERT INTO runs (binary_id, started_at, finished_at, tool_version, cwe_checker_version, ground_truth, tracer, host,
+                checked_calls, sound_calls, aict, checked_direct_calls, sound_direct_calls, manifest)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                binary_id,
+                manifest.map(|manifest| manifest.started_at.to_rfc3339()),
+                manifest.and_then(|manifest| manifest.finished_at).map(|time| time.to_rfc3339()),
+                manifest.map(|manifest| manifest.version.clone()),
+                manifest
+                    .and_then(|manifest| manifest.cwe_checker.as_ref())
+                    .and_then(|tool| tool.version.clone()),
+                manifest.map(|manifest| manifest.ground_truth.clone()),
+                manifest.and_then(|manifest| manifest.tracer.as_ref()).map(|tool| tool.tool.clone()),
+                manifest.map(|manifest| manifest.host.clone()),
+                report.checked_calls,
+                report.sound_calls,
+                report.aict,
+                report.checked_direct_calls,
+                report.sound_direct_calls,
+                manifest.map(|manifest| serde_json::to_string(manifest).unwrap()),
+            ],
+        )?;
+        let run_id = transaction.last_insert_rowid();
+
+        // Observed and unsound targets per callsite
+        let mut callsites: BTreeMap<u64, (i64, i64)> = BTreeMap::new();
+        if let Some(cwe_checker) = cwe_checker {
+            for callsite in &cwe_checker.metadata.indirect_call_sites {
+                callsites.entry(*callsite).or_default();
+            }
+        }
+        {
+            let mut insert_edge = transaction.prepare(
+                "INSERT INTO edges (run_id, callsite, target, target_symbol, target_object, sound) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for call in &report.checked {
+                insert_edge.execute(params![
+                    run_id,
+                    call.callsite as i64,
+                    call.target as i64,
+                    call.target_symbol,
+                    call.target_object,
+                    call.sound
+                ])?;
+                let counts = callsites.entry(call.callsite).or_default();
+                counts.0 += 1;
+                if !call.sound {
+                    counts.1 += 1;
+                }
+            }
+
+            let mut insert_callsite = transaction.prepare(
+                "INSERT INTO callsites (run_id, address, function, static_targets, observed_targets, unsound_targets)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for (address, (observed, unsound)) in callsites {
+                let function = cwe_checker
+                    .and_then(|cwe_checker| cwe_checker.get_function_containing(address))
+                    .map(|function| function.name.clone());
+                let static_targets = cwe_checker.map(|cwe_checker| {
+                    cwe_checker
+                        .get_call_site(address)
+                        .map(|callsite| callsite.targets.len() as i64)
+                        .unwrap_or(0)
+                });
+                insert_callsite.execute(params![run_id, address as i64, function, static_targets, observed, unsound])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    /// Stores the benchmarks of binaries named like the records, replacing earlier ones
+    pub fn store_benchmarks(&mut self, records: &[BenchmarkRecord]) -> Result<()> {
+        self.store_benchmark_records(records).map_err(db_error(&self.path))
+    }
+
+    fn store_benchmark_records(&mut self, records: &[BenchmarkRecord]) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        for record in records {
+            let binary_id = Self::binary_id(&transaction, &record.name, "")?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO benchmarks (binary_id, program_size, complexity, runtime_minutes, memory_gib, sizes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    binary_id,
+                    record.program_size().map(|size| size as i64),
+                    record.complexity.map(|complexity| complexity as i64),
+                    record.runtime_minutes,
+                    record.memory_gib,
+                    serde_json::to_string(&record.sizes).unwrap(),
+                ],
+            )?;
+        }
+        transaction.commit()
+    }
+
+    /// Header and rows of a view, values formatted as text
+    pub fn query(&self, view: QueryView, limit: usize) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+        let mut statement = self.connection.prepare(view.sql()).map_err(db_error(&self.path))?;
+        let header = statement.column_names().into_iter().map(str::to_string).collect::<Vec<String>>();
+        let columns = header.len();
+        let rows = statement
+            .query_map(params![limit as i64], |row| {
+                (0..columns)
+                    .map(|index| {
+                        Ok(match row.get_ref(index)? {
+                            ValueRef::Null => String::new(),
+                            ValueRef::Integer(value) => value.to_string(),
+                            ValueRef::Real(value) => format!("{:.2}", value),
+                            ValueRef::Text(text) | ValueRef::Blob(text) => String::from_utf8_lossy(text).into_owned(),
+                        })
+                    })
+                    .collect::<rusqlite::Result<Vec<String>>>()
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<Vec<String>>>>())
+            .map_err(db_error(&self.path))?;
+        Ok((header, rows))
+    }
+
+    /// Sound percentage over all runs of binaries named `<binary>_<compiler>_<opt>`
+    pub fn soundness_by_variant(&self) -> Result<Vec<VariantSoundness>> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT b.compiler, b.opt, 100.0 * SUM(r.sound_calls) / SUM(r.checked_calls)
+                 FROM runs r JOIN binaries b ON b.id = r.binary_id
+                 WHERE b.compiler IS NOT NULL AND b.opt IS NOT NULL
+                 GROUP BY b.compiler, b.opt HAVING SUM(r.checked_calls) > 0 ORDER BY b.compiler, b.opt",
+            )
+            .map_err(db_error(&self.path))?;
+        statement
+            .query_map([], |row| {
+                Ok(VariantSoundness {
+                    compiler: row.get(0)?,
+                    opt: row.get(1)?,
+                    sound_percentage: row.get(2)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(db_error(&self.path))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariantSoundness {
+    pub compiler: String,
+    pub opt: String,
+    pub sound_percentage: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum QueryView {
+    /// Sound percentage and AICT per compiler and optimisation level
+    SoundnessByCompiler,
+    /// Sound percentage and AICT per cwe_checker version, oldest first
+    CweCheckerVersions,
+    /// Callsites with the most unsound targets over all runs
+    WorstCallsites,
+    /// The latest runs
+    Runs,
+    /// cwe_checker runtime and memory usage of the benchmark build, largest programs first
+    Benchmarks,
+}
+
+impl QueryView {
+    /// Every view takes the row limit as ?1
+    fn sql(&self) -> &'static str {
+        match self {
+            QueryView::SoundnessByCompiler => {
+                "SELECT b.compiler, b.opt, COUNT(*) AS runs, SUM(r.checked_calls) AS checked, SUM(r.sound_calls) AS sound,
+                    100.0 * SUM(r.sound_calls) / SUM(r.checked_calls) AS sound_percentage, AVG(r.aict) AS aict
+                 FROM runs r JOIN binaries b ON b.id = r.binary_id
+                 GROUP BY b.compiler, b.opt ORDER BY b.compiler, b.opt LIMIT ?1"
+            }
+            QueryView::CweCheckerVersions => {
+                "SELECT COALESCE(r.cwe_checker_version, 'unknown') AS cwe_checker_version, COUNT(*) AS runs,
+                    COUNT(DISTINCT r.binary_id) AS binaries, MIN(r.started_at) AS first_run,
+                    100.0 * SUM(r.sound_calls) / SUM(r.checked_calls) AS sound_percentage, AVG(r.aict) AS aict
+                 FROM runs r
+                 GROUP BY r.cwe_checker_version ORDER BY MIN(r.started_at) LIMIT ?1"
+            }
+            QueryView::WorstCallsites => {
+                "SELECT b.name AS binary, printf('0x%x', c.address) AS callsite, c.function, COUNT(*) AS runs,
+                    SUM(c.unsound_targets) AS unsound_targets, MAX(c.static_targets) AS static_targets
+                 FROM callsites c JOIN runs r ON r.id = c.run_id JOIN binaries b ON b.id = r.binary_id
+                 WHERE c.unsound_targets > 0
+                 GROUP BY b.name, c.address ORDER BY unsound_targets DESC, runs DESC LIMIT ?1"
+            }
+            QueryView::Runs => {
+                "SELECT r.id, b.name AS binary, r.started_at, r.cwe_checker_version, r.tracer, r.checked_calls, r.sound_calls,
+                    100.0 * r.sound_calls / r.checked_calls AS sound_percentage, r.aict
+                 FROM runs r JOIN binaries b ON b.id = r.binary_id
+                 ORDER BY r.id DESC LIMIT ?1"
+            }
+            QueryView::Benchmarks => {
+                "SELECT b.name AS binary, b.compiler, b.opt, m.program_size, m.complexity, m.runtime_minutes, m.memory_gib
+                 FROM benchmarks m JOIN binaries b ON b.id = m.binary_id
+                 ORDER BY m.program_size DESC, b.name LIMIT ?1"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soundness_test::CheckedCall;
+
+    #[test]
+    fn test_store_and_query() {
+        let report = SoundnessReport {
+            checked_calls: 2,
+            sound_calls: 1,
+            aict: Some(1.5),
+            checked: [(0x401135, true), (0x401126, false)]
+                .into_iter()
+                .map(|(target, sound)| CheckedCall {
+                    callsite: 0x401195,
+                    target,
+                    target_symbol: None,
+                    target_object: None,
+                    sound,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut db = ResultsDb::open(Path::new(":memory:")).unwrap();
+        db.store(&report, None, "fp_gcc_O2").unwrap();
+        db.store(&report, None, "fp_gcc_O2").unwrap();
+
+        let (header, rows) = db.query(QueryView::SoundnessByCompiler, 10).unwrap();
+        assert_eq!(header[..3], ["compiler", "opt", "runs"]);
+        assert_eq!(rows, vec![vec!["gcc", "O2", "2", "4", "2", "50.00", "1.50"]]);
+        let (_, rows) = db.query(QueryView::WorstCallsites, 10).unwrap();
+        assert_eq!(rows, vec![vec!["fp_gcc_O2", "0x401195", "", "2", "2", ""]]);
+
+        let record = BenchmarkRecord {
+            name: "fp_gcc_O2".to_string(),
+            sizes: BTreeMap::from([("block_to_func".to_string(), 120)]),
+            complexity: None,
+            runtime_minutes: Some(1.5),
+            memory_gib: Some(0.25),
+        };
+        // Stored twice for the same binary, e.g. by two plots
+        let records = [record.clone(), record];
+        db.store_benchmarks(&records).unwrap();
+        let (_, rows) = db.query(QueryView::Benchmarks, 10).unwrap();
+        assert_eq!(rows, vec![vec!["fp_gcc_O2", "gcc", "O2", "120", "", "1.50", "0.25"]]);
+
+        // Benchmarks have no hash and belong to the binary stored with one
+        db.connection
+            .execute("INSERT INTO binaries (name, sha256, compiler, opt) VALUES ('ls_gcc_O0', 'abc', 'gcc', 'O0')", [])
+            .unwrap();
+        let mut record = records[0].clone();
+        record.name = "ls_gcc_O0".to_string();
+        db.store_benchmarks(&[record]).unwrap();
+        let binaries: i64 = db
+            .connection
+            .query_row("SELECT COUNT(*) FROM binaries WHERE name = 'ls_gcc_O0'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(binaries, 1);
+        assert_eq!(
+            db.soundness_by_variant().unwrap(),
+            vec![VariantSoundness {
+                compiler: "gcc".to_string(),
+                opt: "O2".to_string(),
+                sound_percentage: 50.0
+            }]
+        );
+    }
+}
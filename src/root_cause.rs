@@ -0,0 +1,192 @@
+//! Heuristics for the probable cause of an unsound edge, to group the edges of a report by what
+//! the pointer analysis has to learn. The first matching cause is taken, in the order of
+//! [`RootCause`].
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    call_classification::public_symbol_name,
+    cwe_checker::CweCheckerResult,
+    elf::ElfInfo,
+    soundness_test::{SoundnessReport, UnsoundEdge},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RootCause {
+    /// cwe_checker has no callsite at the address of the call
+    MissingCallsite,
+    /// The target is not a function start known to cwe_checker
+    UnknownTarget,
+    /// The callsite was reached through a library function that calls or passes on pointers it
+    /// was given, e.g. qsort or the start routine of pthread_create
+    LibraryPassThrough,
+    /// The function of the callsite is called back from another object, or the target is in
+    /// another object
+    CrossObjectCallback,
+    /// The target is stored in .data, .rodata or .data.rel.ro, e.g. in a function pointer table
+    GlobalTable,
+    Unclassified,
+}
+
+impl fmt::Display for RootCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RootCause::MissingCallsite => "missing callsite",
+            RootCause::UnknownTarget => "unknown target",
+            RootCause::LibraryPassThrough => "library pass-through",
+            RootCause::CrossObjectCallback => "cross-object callback",
+            RootCause::GlobalTable => "global table",
+            RootCause::Unclassified => "unclassified",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Library functions that call a pointer they were given. The start routine of pthread_create is
+/// called on the stack of the new thread, which starts at start_thread, so start_thread stands for
+/// pthread_create. Exit handlers are only called by exit, so it is not listed.
+const PASS_THROUGH_FUNCTIONS: [&str; 15] = [
+    "qsort", "qsort_r", "bsearch", "lfind", "lsearch", "tsearch", "tfind", "twalk", "twalk_r", "start_thread",
+    "pthread_once", "dl_iterate_phdr", "ftw", "nftw", "scandir",
+];
+
+/// Callgrind marks recursion levels as `name'2`
+fn function_name(name: &str) -> &str {
+    name.split('\'').next().unwrap_or(name)
+}
+
+pub fn root_cause(edge: &UnsoundEdge, cwe_checker: &CweCheckerResult, elf: Option<&ElfInfo>) -> RootCause {
+    if edge.callsite_missing {
+        return RootCause::MissingCallsite;
+    }
+    if edge.target_symbol.is_none() && !cwe_checker.metadata.functions.iter().any(|function| function.address == edge.target) {
+        return RootCause::UnknownTarget;
+    }
+
+    // The call stack ends with the function of the callsite
+    let callers = &edge.call_stack[..edge.call_stack.len().saturating_sub(1)];
+    let in_binary = |frame: &String| elf.is_some_and(|elf| elf.symbols.iter().any(|symbol| symbol.name == function_name(frame)));
+    // Only the library frames directly before the chain of binary functions ending at the
+    // callsite, the frame before it without the ELF. Earlier pass-throughs did not pass this pointer
+    let chain_start = callers.iter().rposition(|frame| !in_binary(frame)).map_or(0, |index| index + 1);
+    let library_start = match elf {
+        Some(_) => callers[..chain_start].iter().rposition(in_binary).map_or(0, |index| index + 1),
+        None => chain_start.saturating_sub(1),
+    };
+    if callers[library_start..chain_start]
+        .iter()
+        .any(|caller| PASS_THROUGH_FUNCTIONS.contains(&public_symbol_name(function_name(caller)).as_str()))
+    {
+        return RootCause::LibraryPassThrough;
+    }
+    let called_from_other_object = elf.is_some_and(|elf| {
+        callers
+            .last()
+            .is_some_and(|caller| !elf.symbols.iter().any(|symbol| symbol.name == function_name(caller)))
+    });
+    if edge.target_symbol.is_some() || called_from_other_object {
+        return RootCause::CrossObjectCallback;
+    }
+
+    if elf.is_some_and(|elf| {
+        elf.from_cwe_address(edge.target, &cwe_checker.metadata)
+            .is_some_and(|target| elf.data_pointers.contains_key(&target))
+    }) {
+        return RootCause::GlobalTable;
+    }
+    RootCause::Unclassified
+}
+
+/// Number of unsound edges per cause, edges of older reports without a cause are not counted
+pub fn root_cause_counts(report: &SoundnessReport) -> BTreeMap<RootCause, usize> {
+    let mut counts = BTreeMap::new();
+    for cause in report.unsound_edges.iter().filter_map(|edge| edge.root_cause) {
+        *counts.entry(cause).or_default() += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        cwe_checker::{ExportCallGraph, Function, Metadata},
+        elf::{ElfSymbol, SymbolSource},
+    };
+
+    #[test]
+    fn test_root_cause() {
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1010],
+                functions: vec![Function {
+                    name: "compare".to_string(),
+                    address: 0x1100,
+                }],
+            },
+            calls: vec![],
+        });
+        let edge = |target: u64, call_stack: &[&str]| UnsoundEdge {
+            callsite: 0x1010,
+            target,
+            call_stack: call_stack.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(root_cause(&edge(0x1234, &[]), &cwe_checker, None), RootCause::UnknownTarget);
+        assert_eq!(root_cause(&edge(0x1100, &["main", "qsort", "sort_helper"]), &cwe_checker, None), RootCause::LibraryPassThrough);
+        // Without the ELF only the frame before the callsite is checked
+        assert_eq!(
+            root_cause(&edge(0x1100, &["start_thread", "worker", "sort_helper"]), &cwe_checker, None),
+            RootCause::Unclassified
+        );
+        assert_eq!(root_cause(&edge(0x1100, &["start_thread", "worker"]), &cwe_checker, None), RootCause::LibraryPassThrough);
+        assert_eq!(root_cause(&edge(0x1100, &["main", "sort_helper"]), &cwe_checker, None), RootCause::Unclassified);
+        let missing = UnsoundEdge {
+            callsite_missing: true,
+            ..edge(0x1100, &[])
+        };
+        assert_eq!(root_cause(&missing, &cwe_checker, None), RootCause::MissingCallsite);
+
+        let symbol = |name: &str, address: u64| ElfSymbol {
+            name: name.to_string(),
+            address,
+            size: 0x10,
+            source: SymbolSource::Symtab,
+            is_exported: false,
+            is_ifunc: false,
+        };
+        let elf = ElfInfo {
+            is_pie: false,
+            symbols: vec![symbol("main", 0x1000), symbol("sort_helper", 0x1010), symbol("compare", 0x1100), symbol("worker", 0x1200)],
+            sections: vec![],
+            plt_entries: vec![],
+            got_imports: HashMap::new(),
+            data_pointers: HashMap::from([(0x1100, ".data.rel.ro".to_string())]),
+        };
+        let elf = Some(&elf);
+        assert_eq!(
+            root_cause(&edge(0x1100, &["main", "qsort", "msort_with_tmp'2", "sort_helper"]), &cwe_checker, elf),
+            RootCause::LibraryPassThrough
+        );
+        // qsort did not pass the pointer called from printf
+        assert_eq!(
+            root_cause(&edge(0x1100, &["main", "qsort", "compare", "printf", "vfprintf", "sort_helper"]), &cwe_checker, elf),
+            RootCause::CrossObjectCallback
+        );
+        let library_target = UnsoundEdge {
+            target_symbol: Some("free".to_string()),
+            ..edge(0x7000, &["main", "sort_helper"])
+        };
+        assert_eq!(root_cause(&library_target, &cwe_checker, elf), RootCause::CrossObjectCallback);
+        // The start routine of pthread_create is called from start_thread in the new thread
+        assert_eq!(
+            root_cause(&edge(0x1100, &["start_thread", "worker", "sort_helper"]), &cwe_checker, elf),
+            RootCause::LibraryPassThrough
+        );
+        assert_eq!(root_cause(&edge(0x1100, &["main", "sort_helper"]), &cwe_checker, elf), RootCause::GlobalTable);
+    }
+}
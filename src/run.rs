@@ -0,0 +1,292 @@
+//! One soundness test from the inputs to the stored report: loading the call graph, tracing the
+//! binary or reading a recorded trace, and recording the run in a manifest.
+use std::{fs, path::{Path, PathBuf}, time::Duration};
+
+use chrono::{DateTime, Local};
+use clap::{Args, ValueEnum};
+use log::info;
+
+#[cfg(target_arch = "x86_64")]
+use crate::ptrace_tracer::run_ptrace_tracer;
+use crate::{
+    cache::{ArtifactCache, CacheKey},
+    cwe_checker::{complete_analysis, cwe_checker_version, get_analysis_results, CweCheckerResult, CWE_CHECKER_ARGS},
+    elf::ElfInfo,
+    error::{Error, Result},
+    gdb_tracer::run_gdb_tracer,
+    job_queue::Job,
+    load_from_callee_csv::load_callee_from_csv,
+    manifest::{InputFile, RunManifest, ToolInvocation},
+    qemu_trace::load_qemu_log,
+    remote::{remote_analysis, RemoteHost},
+    results_db::ResultsDb,
+    soundness_test::{exclusion_policy, soundness, SoundnessOptions, SoundnessReport},
+    source_lines::SourceLines,
+    valgrind::{analyze_valgrind, run_valgrind, valgrind_version, ValgrindResult, VALGRIND_ARGS},
+};
+
+/// Hex address argument, with or without `0x`
+pub fn parse_hex_arg(arg: &str) -> std::result::Result<u64, String> {
+    u64::from_str_radix(arg.trim_start_matches("0x"), 16).map_err(|err| err.to_string())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Tracer {
+    Valgrind,
+    /// Breakpoints on the indirect callsites, much faster than valgrind. Only on x86_64
+    #[cfg(target_arch = "x86_64")]
+    Ptrace,
+    /// Same as ptrace, but driven through gdb
+    Gdb,
+}
+
+/// Where the observed calls come from. Without a recorded trace, the binary is traced
+#[derive(Args)]
+pub struct GroundTruthArgs {
+    #[arg(long)]
+    pub valgrind_output: Option<PathBuf>,
+
+    #[arg(long)]
+    pub callee_csv: Option<PathBuf>,
+    #[arg(long)]
+    pub callee_bin_name: Option<String>,
+
+    /// Log of qemu-user with -d in_asm,exec,nochain or of the execlog plugin
+    #[arg(long)]
+    pub qemu_log: Option<PathBuf>,
+
+    /// Subtracted from all addresses in the QEMU log, e.g. the guest base of a PIE binary
+    #[arg(long, value_parser = parse_hex_arg, default_value = "0")]
+    pub load_bias: u64,
+
+    #[arg(long, value_enum, default_value_t = Tracer::Valgrind)]
+    pub tracer: Tracer,
+}
+
+impl GroundTruthArgs {
+    pub fn has_recorded_trace(&self) -> bool {
+        self.valgrind_output.is_some() || self.callee_csv.is_some() || self.qemu_log.is_some()
+    }
+
+    /// Same precedence as load_real_calls
+    pub fn describe(&self) -> String {
+        if let Some(qemu_log) = &self.qemu_log {
+            return format!("QEMU log {} (load bias {:#x})", qemu_log.display(), self.load_bias);
+        }
+        if let Some(callee_csv) = &self.callee_csv {
+            return format!("callee CSV {} ({})", callee_csv.display(), self.callee_bin_name.clone().unwrap_or_default());
+        }
+        if let Some(valgrind_output) = &self.valgrind_output {
+            return format!("callgrind output {}", valgrind_output.display());
+        }
+        match self.tracer {
+            Tracer::Valgrind => format!("valgrind {}", VALGRIND_ARGS.join(" ")),
+            #[cfg(target_arch = "x86_64")]
+            Tracer::Ptrace => "ptrace tracer".to_string(),
+            Tracer::Gdb => "gdb tracer".to_string(),
+        }
+    }
+
+    /// The trace file that is used, same precedence as load_real_calls
+    pub fn recorded_trace(&self) -> Option<&PathBuf> {
+        self.qemu_log.as_ref().or(self.callee_csv.as_ref()).or(self.valgrind_output.as_ref())
+    }
+
+    /// The tool that traces the binary, None if a recorded trace is used
+    pub fn tracer_invocation(&self) -> Option<ToolInvocation> {
+        if self.has_recorded_trace() {
+            return None;
+        }
+        Some(match self.tracer {
+            Tracer::Valgrind => ToolInvocation::new("valgrind", valgrind_version(), &VALGRIND_ARGS),
+            #[cfg(target_arch = "x86_64")]
+            Tracer::Ptrace => ToolInvocation::new("ptrace", None, &[]),
+            Tracer::Gdb => ToolInvocation::new("gdb", None, &["--interpreter=mi", "--nx", "--quiet"]),
+        })
+    }
+
+    pub fn load_bias_method(&self) -> String {
+        if self.qemu_log.is_some() {
+            return format!("QEMU addresses minus --load-bias {:#x}", self.load_bias);
+        }
+        if self.callee_csv.is_some() || self.valgrind_output.is_some() || self.tracer == Tracer::Valgrind {
+            return "offset between main in the trace and in the call graph".to_string();
+        }
+        "mapping of the binary in /proc/<pid>/maps minus address_base_offset for PIE binaries".to_string()
+    }
+}
+
+/// Names the output folders
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d-%H-%M-%S";
+
+/// State shared by the steps of one invocation
+pub struct Run {
+    pub cache: ArtifactCache,
+    /// Where cwe_checker runs, locally if None
+    pub remote: Option<RemoteHost>,
+    pub results_db: Option<PathBuf>,
+    started_at: DateTime<Local>,
+    /// `output/<start time>` for the manifest and uncached traces
+    output_folder: PathBuf,
+}
+
+impl Run {
+    /// Adds the report to the results database, if one is configured
+    pub fn store(&self, report: &SoundnessReport, cwe_checker: Option<&CweCheckerResult>, binary_name: &str) -> Result<()> {
+        let Some(path) = &self.results_db else {
+            return Ok(());
+        };
+        let run_id = ResultsDb::open(path)?.store(report, cwe_checker, binary_name)?;
+        info!("Stored {} as run {} in {}", binary_name, run_id, path.display());
+        Ok(())
+    }
+
+    pub fn new(cache: ArtifactCache, remote: Option<RemoteHost>, results_db: Option<PathBuf>) -> Run {
+        let started_at = Local::now();
+        Run {
+            cache,
+            remote,
+            results_db,
+            started_at,
+            output_folder: Path::new("output").join(started_at.format(TIMESTAMP_FORMAT).to_string()),
+        }
+    }
+
+    /// A run of its own for each job of a worker, in `<output folder>/<binary>-<attempt>`
+    pub fn for_job(&self, job: &Job) -> Run {
+        Run {
+            cache: self.cache.clone(),
+            remote: self.remote.clone(),
+            results_db: self.results_db.clone(),
+            started_at: Local::now(),
+            output_folder: self.output_folder.join(format!("{}-{}", job.name, job.attempt)),
+        }
+    }
+
+    /// Created on first use
+    pub fn output_folder(&self) -> Result<PathBuf> {
+        fs::create_dir_all(&self.output_folder).map_err(Error::io(&self.output_folder))?;
+        Ok(self.output_folder.clone())
+    }
+}
+
+pub fn required_binary(binary_path: Option<PathBuf>, reason: &str) -> Result<PathBuf> {
+    binary_path.ok_or_else(|| Error::Config(format!("--binary-path is required {}", reason)))
+}
+
+/// Runs cwe_checker, on the remote host if there is one. Also returns the cwe_checker build that
+/// was used, for a remote host the locked revision of the flake
+fn analyze(binary_path: &Path, run: &Run) -> Result<(CweCheckerResult, ToolInvocation)> {
+    match &run.remote {
+        Some(host) => {
+            let (result, locked) = remote_analysis(binary_path, host, &run.cache)?;
+            let tool = format!("cwe_checker on {}", locked.destination);
+            Ok((result, ToolInvocation::new(&tool, Some(locked.cwe_checker_version()), &CWE_CHECKER_ARGS)))
+        }
+        None => {
+            let result = complete_analysis(binary_path, &run.cache)?;
+            Ok((result, ToolInvocation::new("cwe_checker", cwe_checker_version(), &CWE_CHECKER_ARGS)))
+        }
+    }
+}
+
+pub fn load_call_graph(binary_path: &Option<PathBuf>, cwe_checker_result: Option<PathBuf>, run: &Run) -> Result<CweCheckerResult> {
+    match cwe_checker_result {
+        Some(path) => get_analysis_results(&path),
+        None => {
+            let binary_path = required_binary(binary_path.clone(), "without --cwe-checker-result")?;
+            Ok(analyze(&binary_path, run)?.0)
+        }
+    }
+}
+
+pub async fn load_real_calls(binary_path: Option<PathBuf>, ground_truth: GroundTruthArgs, cwe_checker: &CweCheckerResult, run: &Run) -> Result<ValgrindResult> {
+    if let Some(qemu_log) = ground_truth.qemu_log {
+        return load_qemu_log(&qemu_log, ground_truth.load_bias, cwe_checker.address_range());
+    }
+    match ground_truth.callee_csv {
+        Some(path) => {
+            let callee_bin_name = ground_truth
+                .callee_bin_name
+                .ok_or_else(|| Error::Config("--callee-bin-name is required with --callee-csv".to_string()))?;
+            load_callee_from_csv(&path, &callee_bin_name)
+        },
+        None => {
+            match ground_truth.valgrind_output {
+                Some(valgrind_output) => analyze_valgrind(&valgrind_output),
+                None => {
+                    let binary_path = required_binary(binary_path, "without a recorded trace")?;
+                    match ground_truth.tracer {
+                        #[cfg(target_arch = "x86_64")]
+                        Tracer::Ptrace => run_ptrace_tracer(&binary_path, &cwe_checker.metadata, Duration::from_secs(10)),
+                        Tracer::Gdb => run_gdb_tracer(&binary_path, &cwe_checker.metadata, Duration::from_secs(10)).await,
+                        Tracer::Valgrind => {
+                            let key = CacheKey::new(&binary_path, "valgrind", valgrind_version(), &VALGRIND_ARGS, "callgrind.out")?;
+                            if let Some(cached) = run.cache.get(&key) {
+                                return analyze_valgrind(&cached);
+                            }
+                            let valgrind_output_file = match run.cache.put_path(&key)? {
+                                Some(path) => path,
+                                None => run.output_folder()?.join(Path::new("valgrind.out")),
+                            };
+
+                            let (valgrind_result, reached_timeout) = run_valgrind(&binary_path, &valgrind_output_file).await?;
+                            // An incomplete trace would be reused by later runs
+                            if !reached_timeout {
+                                run.cache.commit(&key)?;
+                            }
+                            Ok(valgrind_result)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs the soundness test and records the run in a manifest, which is embedded in the report
+/// and written to the output folder
+pub async fn run_soundness(
+    run: &Run,
+    binary_path: Option<PathBuf>,
+    cwe_checker_result: Option<PathBuf>,
+    ground_truth: GroundTruthArgs,
+    all_calls: bool,
+) -> Result<(SoundnessReport, CweCheckerResult, Option<ElfInfo>)> {
+    let mut manifest = RunManifest::new(run.started_at);
+    manifest.binary = binary_path.as_deref().map(InputFile::new).transpose()?;
+    manifest.call_graph = cwe_checker_result.as_deref().map(InputFile::new).transpose()?;
+    manifest.ground_truth = ground_truth.describe();
+    manifest.trace = ground_truth.recorded_trace().map(|trace| InputFile::new(trace)).transpose()?;
+    manifest.tracer = ground_truth.tracer_invocation();
+    manifest.load_bias_method = ground_truth.load_bias_method();
+
+    let binary_name = binary_path
+        .as_ref()
+        .or(cwe_checker_result.as_ref())
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cwe_checker_results = match cwe_checker_result {
+        Some(path) => get_analysis_results(&path)?,
+        None => {
+            let binary_path = required_binary(binary_path.clone(), "without --cwe-checker-result")?;
+            let (result, invocation) = analyze(&binary_path, run)?;
+            manifest.cwe_checker = Some(invocation);
+            result
+        }
+    };
+    let elf = binary_path.as_deref().map(ElfInfo::load).transpose()?;
+    let source_lines = binary_path.as_deref().map(SourceLines::load).transpose()?;
+    let valgrind_result = load_real_calls(binary_path, ground_truth, &cwe_checker_results, run).await?;
+    let options = SoundnessOptions { all_calls, elf: elf.as_ref(), source_lines: source_lines.as_ref() };
+    let mut report = soundness(&cwe_checker_results, &valgrind_result, &options);
+
+    manifest.load_bias_method = format!("{}, offset {:#x}", manifest.load_bias_method, report.offset);
+    manifest.exclusions = exclusion_policy(&options);
+    manifest.finished_at = Some(Local::now());
+    manifest.write(&run.output_folder()?)?;
+    report.manifest = Some(manifest);
+    run.store(&report, Some(&cwe_checker_results), &binary_name)?;
+    Ok((report, cwe_checker_results, elf))
+}
@@ -0,0 +1,480 @@
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    call_classification::{classify, has_extern_target, public_symbol_name, ObservedCallKind},
+    cwe_checker::CweCheckerResult,
+    dynamic_call_graph::DynamicCallGraph,
+    elf::{ElfInfo, SymbolSource},
+    manifest::RunManifest,
+    root_cause::{root_cause, RootCause},
+    source_lines::{SourceLines, SourceLocation},
+    valgrind::{RealCall, ValgrindResult},
+};
+
+pub(crate) fn offset_based_on_main(cwe_checker: &CweCheckerResult, real: &ValgrindResult) -> i64 {
+    let Some(main_function) = cwe_checker.metadata.functions.iter().find(|function| function.name == "main") else { return 0; };
+    let cwe_function_offset = main_function.address;
+
+    // find main
+    let Some(fn_id) = real.valgrind_name_cache.name_cache.iter().find_map(|(fn_id, name)| {if name == "main" { Some(fn_id) } else {None}}) else {return 0;};
+
+    let Some(main_address_real) = real.base_address_mapping.get(fn_id) else { return 0; };
+    debug!("main at {:#x} in the trace, at {:#x} in the call graph", *main_address_real, cwe_function_offset);
+    // Below cwe_checker's main e.g. for a trace of a non-PIE binary against a rebased result
+    (*main_address_real as i64).wrapping_sub(cwe_function_offset as i64)
+}
+
+/// An observed indirect call missing in the static call graph
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UnsoundEdge {
+    /// Addresses in the coordinates of the cwe_checker result
+    pub callsite: u64,
+    /// 0 for targets in another object, which have no address in these coordinates
+    pub target: u64,
+    /// Public name of the target, if the call goes into another object
+    pub target_symbol: Option<String>,
+    /// false if only the target is missing
+    pub callsite_missing: bool,
+    /// Reconstructed caller chain leading to the function of the callsite, outermost first
+    pub call_stack: Vec<String>,
+    pub callsite_source: Option<SourceLocation>,
+    /// Source of the target function
+    pub target_source: Option<SourceLocation>,
+    /// None in reports written before the classification
+    #[serde(default)]
+    pub root_cause: Option<RootCause>,
+}
+
+/// An observed indirect call that was checked against the static call graph
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckedCall {
+    /// Addresses in the coordinates of the cwe_checker result
+    pub callsite: u64,
+    /// 0 for targets in another object, see target_symbol
+    pub target: u64,
+    pub target_symbol: Option<String>,
+    /// Object file of a target in another object, if the trace names it
+    pub target_object: Option<String>,
+    pub sound: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SoundnessReport {
+    pub checked_calls: i64,
+    pub sound_calls: i64,
+    /// AICT of the call graph, None if no indirect callsite has a target
+    #[serde(default)]
+    pub aict: Option<f32>,
+    #[serde(default)]
+    pub checked: Vec<CheckedCall>,
+    pub unsound_edges: Vec<UnsoundEdge>,
+    /// Only counted with SoundnessOptions::all_calls
+    pub checked_direct_calls: i64,
+    pub sound_direct_calls: i64,
+    /// Observed direct calls and tail-call jumps missing in the static call graph
+    pub missing_direct_edges: Vec<(u64, u64)>,
+    /// Subtracted from the observed addresses
+    #[serde(default)]
+    pub offset: i64,
+    /// Set by the caller, soundness does not know how the inputs were produced
+    #[serde(default)]
+    pub manifest: Option<RunManifest>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct SoundnessOptions<'a> {
+    /// Also check direct calls and tail-call jumps, not only indirect callsites
+    pub all_calls: bool,
+    /// With the ELF file, calls into other objects are checked against the extern symbols
+    /// instead of being ignored
+    pub elf: Option<&'a ElfInfo>,
+    /// DWARF line table of the binary, callgrind's source lines are used as fallback
+    pub source_lines: Option<&'a SourceLines>,
+}
+
+impl SoundnessReport {
+    /// None if no call was checked
+    pub fn sound_percentage(&self) -> Option<f32> {
+        if self.checked_calls == 0 {
+            return None;
+        }
+        Some(self.sound_calls as f32 / self.checked_calls as f32 * 100.0)
+    }
+
+    /// `checked,sound,sound_percentage`, the percentage is empty if no call was checked
+    pub fn to_csvline(&self) -> String {
+        let sound_percentage = self.sound_percentage().map(|percentage| percentage.to_string()).unwrap_or_default();
+        format!("{},{},{}", self.checked_calls, self.sound_calls, sound_percentage)
+    }
+}
+
+pub fn soundness(cwe_checker: &CweCheckerResult, real: &ValgrindResult, options: &SoundnessOptions) -> SoundnessReport {
+    let mut soundness_report = SoundnessReport {
+        aict: cwe_checker.aict(),
+        ..Default::default()
+    };
+    // Only Indirect calls from the program
+    let mut real_calls = real.calls.clone();
+    // We sort to have a function, by function analysis. It just nicer to read
+    real_calls.sort_by_key(|call| call.from_instr);
+
+    let offset = offset_based_on_main(cwe_checker, real);
+    info!("Using offset {:#x} between trace and call graph", offset);
+    soundness_report.offset = offset;
+
+    let mut sorted_funcs = cwe_checker.metadata.functions.clone();
+    sorted_funcs.sort_by_key(|func| func.address);
+    sorted_funcs.reverse();
+    debug!(
+        "Indirect callsites: {}",
+        cwe_checker
+            .metadata
+            .indirect_call_sites
+            .iter()
+            .map(|target| format!("{:#x}", target))
+            .collect::<Vec<String>>()
+            .join(",")
+    );
+    let real_calls_from_prog_region = real
+        .calls
+        .iter()
+        .filter(|call| {
+            cwe_checker
+                .metadata
+                .indirect_call_sites
+                .contains(&(((call.from_instr as i64) - offset) as u64))
+        })
+        .collect::<Vec<&RealCall>>();
+
+    let dynamic_call_graph = DynamicCallGraph::from_valgrind(real);
+    let call_stack_of = |call: &RealCall| {
+        // Without callgrind function names there is no context to reconstruct
+        if real.valgrind_name_cache.name_cache.is_empty() {
+            return vec![];
+        }
+        dynamic_call_graph
+            .caller_chain(call.in_fn as u64)
+            .into_iter()
+            .map(|fn_id| real.valgrind_name_cache.get(fn_id))
+            .collect::<Vec<String>>()
+    };
+
+    // Addresses in the coordinates of the cwe_checker result
+    let source_of = |addr: u64| source_location(addr, offset, cwe_checker, real, options);
+
+    let mut current_function_cwe = "".to_string();
+    for call in real_calls_from_prog_region {
+        for func in &sorted_funcs {
+            if (func.address) < ((call.from_instr as i64) - offset) as u64 {
+                current_function_cwe = func.name.clone();
+                break
+            }
+
+        }
+
+        if current_function_cwe == "__libc_csu_init" {
+            continue;
+        }
+
+        let callsite_addr = ((call.from_instr as i64) - offset) as u64;
+        let target_addr = ((call.to_instr as i64) - offset) as u64;
+        if let Some(elf) = options.elf {
+            match classify(call, callsite_addr, cwe_checker, real, elf) {
+                ObservedCallKind::PltStub { import } => {
+                    debug!("Ignoring call {} in PLT stub of {}", call, import);
+                    continue;
+                }
+                ObservedCallKind::CrossObject { symbol } => {
+                    soundness_report.checked_calls += 1;
+                    let sound = has_extern_target(callsite_addr, &symbol, cwe_checker, elf);
+                    soundness_report.checked.push(CheckedCall {
+                        callsite: callsite_addr,
+                        target: 0,
+                        target_symbol: Some(symbol.clone()),
+                        target_object: real.target_objects.get(&call.to_instr).cloned(),
+                        sound,
+                    });
+                    if sound {
+                        soundness_report.sound_calls += 1;
+                        debug!("Sound: {:#x} -> {} (extern)", callsite_addr, symbol);
+                    } else {
+                        debug!("Unsound: {:#x} -> {} (extern)", callsite_addr, symbol);
+                        soundness_report.unsound_edges.push(UnsoundEdge {
+                            callsite: callsite_addr,
+                            target: 0,
+                            target_symbol: Some(symbol),
+                            callsite_missing: cwe_checker.get_call_site(callsite_addr).is_none(),
+                            call_stack: call_stack_of(call),
+                            callsite_source: source_of(callsite_addr),
+                            target_source: real.source_lines.get(&call.to_instr).cloned(),
+                            root_cause: None,
+                        });
+                    }
+                    continue;
+                }
+                ObservedCallKind::Internal | ObservedCallKind::Unknown => (),
+            }
+        }
+
+        if call.does_jump_object_file {
+            debug!("Ignoring call {} between object files", call);
+            continue;
+        }
+        soundness_report.checked_calls += 1;
+
+        let Some(callsite) = cwe_checker.get_call_site(callsite_addr) else {
+            debug!("Unsound: callsite {:#x} is missing", callsite_addr);
+            soundness_report.checked.push(CheckedCall {
+                callsite: callsite_addr,
+                target: target_addr,
+                target_symbol: None,
+                target_object: None,
+                sound: false,
+            });
+            soundness_report.unsound_edges.push(UnsoundEdge {
+                callsite: ((call.from_instr as i64) - offset) as u64,
+                target: ((call.to_instr as i64) - offset) as u64,
+                target_symbol: None,
+                callsite_missing: true,
+                call_stack: call_stack_of(call),
+                callsite_source: source_of(((call.from_instr as i64) - offset) as u64),
+                target_source: source_of(((call.to_instr as i64) - offset) as u64),
+                root_cause: None,
+            });
+            continue;
+        };
+        let sound = callsite.has_target(&target_addr);
+        soundness_report.checked.push(CheckedCall {
+            callsite: callsite_addr,
+            target: target_addr,
+            target_symbol: None,
+            target_object: None,
+            sound,
+        });
+        if sound {
+            soundness_report.sound_calls += 1;
+            debug!("Sound: {:#x} -> {:#x}", callsite_addr, target_addr);
+        } else {
+            debug!("Unsound: {:#x} -> {:#x}", callsite_addr, target_addr);
+            soundness_report.unsound_edges.push(UnsoundEdge {
+                callsite: callsite.callsite_loc,
+                target: ((call.to_instr as i64) - offset) as u64,
+                target_symbol: None,
+                callsite_missing: false,
+                call_stack: call_stack_of(call),
+                callsite_source: source_of(callsite.callsite_loc),
+                target_source: source_of(((call.to_instr as i64) - offset) as u64),
+                root_cause: None,
+            });
+        }
+    }
+
+    for edge in &mut soundness_report.unsound_edges {
+        edge.root_cause = Some(root_cause(edge, cwe_checker, options.elf));
+    }
+
+    if options.all_calls {
+        check_direct_calls(cwe_checker, real, offset, options.elf, &mut soundness_report);
+    }
+
+    soundness_report
+}
+
+/// The observed calls soundness does not check, recorded in the run manifest
+pub fn exclusion_policy(options: &SoundnessOptions) -> Vec<String> {
+    let mut exclusions = vec!["calls from __libc_csu_init".to_string()];
+    if options.elf.is_some() {
+        exclusions.push("jumps of PLT stubs into the imported function".to_string());
+        exclusions.push("calls into other objects without a target name".to_string());
+    } else {
+        exclusions.push("calls into other objects".to_string());
+    }
+    if !options.all_calls {
+        exclusions.push("direct calls and tail-call jumps".to_string());
+    }
+    exclusions
+}
+
+/// The DWARF line table of the binary if available, otherwise the line callgrind recorded
+fn source_location(addr: u64, offset: i64, cwe_checker: &CweCheckerResult, real: &ValgrindResult, options: &SoundnessOptions) -> Option<SourceLocation> {
+    if let (Some(source_lines), Some(elf)) = (options.source_lines, options.elf) {
+        if let Some(location) = elf.from_cwe_address(addr, &cwe_checker.metadata).and_then(|addr| source_lines.lookup(addr)) {
+            return Some(location.clone());
+        }
+    }
+    real.source_lines.get(&((addr as i64) + offset).try_into().ok()?).cloned()
+}
+
+/// Checks direct calls and jumps into other functions. Misses here point to disassembly
+/// or function boundary bugs rather than to the pointer analysis.
+fn check_direct_calls(cwe_checker: &CweCheckerResult, real: &ValgrindResult, offset: i64, elf: Option<&ElfInfo>, soundness_report: &mut SoundnessReport) {
+    let Some((program_start, program_end)) = cwe_checker.address_range() else {
+        return;
+    };
+    let in_program = |addr: u64| program_start <= addr && addr <= program_end;
+
+    let calls = real.calls.iter().map(|call| (call, false));
+    let jumps = real.jumps.iter().map(|jump| (jump, true));
+    for (call, is_jump) in calls.chain(jumps) {
+        if call.does_jump_object_file {
+            continue;
+        }
+        let from_instr = ((call.from_instr as i64) - offset) as u64;
+        let to_instr = ((call.to_instr as i64) - offset) as u64;
+        if !in_program(from_instr) || !in_program(to_instr) {
+            continue;
+        }
+        if cwe_checker.metadata.indirect_call_sites.contains(&from_instr) {
+            continue;
+        }
+        let Some(caller) = cwe_checker.get_function_containing(from_instr) else {
+            continue;
+        };
+        if caller.name == "__libc_csu_init" {
+            continue;
+        }
+        if is_jump {
+            // Only jumps to the start of another function are tail calls
+            if !cwe_checker.metadata.functions.iter().any(|func| func.address == to_instr && func.address != caller.address) {
+                continue;
+            }
+        }
+
+        soundness_report.checked_direct_calls += 1;
+        let is_sound = cwe_checker
+            .get_call_site(from_instr)
+            .is_some_and(|callsite| callsite.has_target(&to_instr))
+            || elf.is_some_and(|elf| calls_plt_stub_of_extern_target(from_instr, to_instr, cwe_checker, elf));
+        if is_sound {
+            soundness_report.sound_direct_calls += 1;
+        } else {
+            debug!(
+                "Missing direct {}: {:#x} -> {:#x}",
+                if is_jump { "jump" } else { "call" },
+                from_instr,
+                to_instr
+            );
+            soundness_report.missing_direct_edges.push((from_instr, to_instr));
+        }
+    }
+}
+
+/// cwe_checker may use the extern symbol as target of a call to a PLT stub
+fn calls_plt_stub_of_extern_target(from_instr: u64, to_instr: u64, cwe_checker: &CweCheckerResult, elf: &ElfInfo) -> bool {
+    match elf.from_cwe_address(to_instr, &cwe_checker.metadata).and_then(|to_instr| elf.lookup(to_instr)) {
+        Some((symbol, 0)) if symbol.source == SymbolSource::Plt => {
+            has_extern_target(from_instr, &public_symbol_name(&symbol.name), cwe_checker, elf)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Function, Metadata},
+        valgrind::ValgrindNameCache,
+    };
+
+    fn report(checked_calls: i64, sound_calls: i64) -> SoundnessReport {
+        SoundnessReport {
+            checked_calls,
+            sound_calls,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_to_csvline() {
+        assert_eq!(report(4, 3).to_csvline(), "4,3,75");
+        assert_eq!(report(0, 0).to_csvline(), "0,0,");
+    }
+
+    #[test]
+    fn test_soundness() {
+        let functions = [("main", 0x1000), ("foo", 0x1100), ("bar", 0x1200), ("__libc_csu_init", 0x1300)];
+        let static_call = |from_instr: u64, to_instr: u64, is_indirect: bool| Call {
+            from_instr,
+            to_instr: Some(to_instr),
+            is_indirect,
+        };
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1010, 0x1310],
+                functions: functions
+                    .iter()
+                    .map(|(name, address)| Function {
+                        name: name.to_string(),
+                        address: *address,
+                    })
+                    .collect(),
+            },
+            calls: vec![
+                static_call(0x1010, 0x1100, true),
+                static_call(0x1020, 0x1100, false),
+                static_call(0x1110, 0x1200, false),
+                static_call(0x1310, 0x1100, true),
+                static_call(0x1320, 0x1100, false),
+            ],
+        });
+        let real_call = |from_instr: u64, to_instr: u64| RealCall {
+            from_instr,
+            to_instr,
+            in_fn: 0,
+            target_fn: 1,
+            does_jump_object_file: false,
+            call_count: 1,
+            inclusive_cost: 0,
+        };
+        let real = ValgrindResult {
+            // The calls from __libc_csu_init would be unsound
+            calls: vec![real_call(0x1010, 0x1100), real_call(0x1020, 0x1100), real_call(0x1030, 0x1200), real_call(0x1310, 0x1200), real_call(0x1320, 0x1000)],
+            // A tail call to bar, a jump into the middle of bar and a missing tail call to main
+            jumps: vec![real_call(0x1110, 0x1200), real_call(0x1120, 0x1204), real_call(0x1130, 0x1000)],
+            ..Default::default()
+        };
+
+        let report = soundness(&cwe_checker, &real, &SoundnessOptions::default());
+        assert_eq!((report.checked_calls, report.sound_calls), (1, 1));
+        assert_eq!(report.checked_direct_calls, 0);
+        assert!(report.unsound_edges.is_empty());
+
+        let options = SoundnessOptions {
+            all_calls: true,
+            ..Default::default()
+        };
+        let report = soundness(&cwe_checker, &real, &options);
+        // The indirect callsite is only counted as indirect call
+        assert_eq!((report.checked_calls, report.sound_calls), (1, 1));
+        assert_eq!((report.checked_direct_calls, report.sound_direct_calls), (4, 2));
+        assert_eq!(report.missing_direct_edges, [(0x1030, 0x1200), (0x1130, 0x1000)]);
+    }
+
+    #[test]
+    fn test_offset_below_main() {
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0x100000,
+                indirect_call_sites: vec![],
+                functions: vec![Function {
+                    name: "main".to_string(),
+                    address: 0x101000,
+                }],
+            },
+            calls: vec![],
+        });
+        let mut valgrind_name_cache = ValgrindNameCache::new();
+        valgrind_name_cache.name_cache.insert(7, "main".to_string());
+        let real = ValgrindResult {
+            valgrind_name_cache,
+            base_address_mapping: HashMap::from([(7, 0x1000)]),
+            ..Default::default()
+        };
+        assert_eq!(offset_based_on_main(&cwe_checker, &real), -0x100000);
+    }
+}
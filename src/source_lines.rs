@@ -0,0 +1,125 @@
+use std::{borrow::Cow, fmt::Display, fs, path::Path};
+
+use gimli::{DwarfSections, EndianSlice, RunTimeEndian, SectionId};
+use object::{Object, ObjectSection};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u64,
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Line table from .debug_line, addresses are virtual addresses of the file
+#[derive(Debug)]
+pub struct SourceLines {
+    /// Sorted by address, None marks the end of a sequence
+    rows: Vec<(u64, Option<SourceLocation>)>,
+}
+
+fn join_path(directory: &str, file: &str) -> String {
+    if directory.is_empty() || file.starts_with('/') {
+        file.to_string()
+    } else {
+        format!("{}/{}", directory.trim_end_matches('/'), file)
+    }
+}
+
+impl SourceLines {
+    /// Empty if the binary has no debug information
+    pub fn load(path: &Path) -> Result<SourceLines> {
+        let data = fs::read(path).map_err(Error::io(path))?;
+        let file = object::File::parse(&*data).map_err(|source| Error::Elf { path: path.to_path_buf(), source })?;
+        let load_section = |id: SectionId| -> std::result::Result<Cow<[u8]>, gimli::Error> {
+            Ok(file
+                .section_by_name(id.name())
+                .and_then(|section| section.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[])))
+        };
+        let dwarf_sections = DwarfSections::load(load_section).map_err(|source| Error::Dwarf { path: path.to_path_buf(), source })?;
+        let endian = if file.is_little_endian() { RunTimeEndian::Little } else { RunTimeEndian::Big };
+        let dwarf = dwarf_sections.borrow(|section| EndianSlice::new(section, endian));
+
+        let mut rows = vec![];
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let Ok(unit) = dwarf.unit(header) else {
+                continue;
+            };
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let comp_dir = unit.comp_dir.map(|dir| dir.to_string_lossy().into_owned()).unwrap_or_default();
+            let mut program_rows = program.rows();
+            while let Ok(Some((header, row))) = program_rows.next_row() {
+                if row.end_sequence() {
+                    rows.push((row.address(), None));
+                    continue;
+                }
+                let Some(file) = row.file(header) else {
+                    continue;
+                };
+                let Ok(name) = dwarf.attr_string(&unit, file.path_name()) else {
+                    continue;
+                };
+                let directory = file
+                    .directory(header)
+                    .and_then(|directory| dwarf.attr_string(&unit, directory).ok())
+                    .map(|directory| directory.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                rows.push((
+                    row.address(),
+                    Some(SourceLocation {
+                        file: join_path(&comp_dir, &join_path(&directory, &name.to_string_lossy())),
+                        line: row.line().map(|line| line.get()).unwrap_or(0),
+                    }),
+                ));
+            }
+        }
+        // The end of a sequence may share the address with the start of the next one
+        rows.sort_by_key(|(address, location)| (*address, location.is_some()));
+        Ok(SourceLines { rows })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn lookup(&self, addr: u64) -> Option<&SourceLocation> {
+        let index = self.rows.partition_point(|(address, _)| *address <= addr).checked_sub(1)?;
+        self.rows[index].1.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object::ObjectSymbol;
+
+    use super::*;
+
+    #[test]
+    fn test_own_binary() {
+        // Test builds have debug information
+        let path = std::env::current_exe().unwrap();
+        let source_lines = SourceLines::load(&path).unwrap();
+        assert!(!source_lines.is_empty());
+
+        let data = fs::read(&path).unwrap();
+        let file = object::File::parse(&*data).unwrap();
+        let join_path = file
+            .symbols()
+            .find(|symbol| symbol.name().is_ok_and(|name| name.contains("source_lines9join_path")))
+            .expect("join_path is not inlined without optimisations");
+        let location = source_lines.lookup(join_path.address()).unwrap();
+        assert!(location.file.ends_with("src/source_lines.rs"), "{}", location);
+        assert!(location.line > 0);
+    }
+}
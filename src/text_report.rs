@@ -0,0 +1,201 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{cwe_checker::CweCheckerResult, elf::ElfInfo, root_cause::root_cause_counts, soundness_test::SoundnessReport};
+
+#[derive(Default)]
+struct FunctionRow {
+    name: String,
+    callsites: usize,
+    checked: usize,
+    sound: usize,
+}
+
+fn percentage(part: usize, total: usize) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", part as f64 / total as f64 * 100.0)
+    }
+}
+
+/// `symbol+offset`, from the ELF symbols if available, otherwise from the functions of cwe_checker
+pub fn symbolize(addr: u64, cwe_checker: &CweCheckerResult, elf: Option<&ElfInfo>) -> String {
+    if let Some(elf) = elf {
+        let elf_address = elf.from_cwe_address(addr, &cwe_checker.metadata);
+        if let Some(elf_address) = elf_address.filter(|elf_address| elf.lookup(*elf_address).is_some()) {
+            return format!("{} ({:#x})", elf.format_address(elf_address), addr);
+        }
+    }
+    match cwe_checker.get_function_containing(addr) {
+        Some(function) if function.address == addr => format!("{} ({:#x})", function.name, addr),
+        Some(function) => format!("{}+{:#x} ({:#x})", function.name, addr - function.address, addr),
+        None => format!("{:#x}", addr),
+    }
+}
+
+fn row_of<'a>(rows: &'a mut BTreeMap<u64, FunctionRow>, addr: u64, cwe_checker: &CweCheckerResult) -> &'a mut FunctionRow {
+    let (address, name) = match cwe_checker.get_function_containing(addr) {
+        Some(function) => (function.address, function.name.clone()),
+        None => (u64::MAX, "<unknown>".to_string()),
+    };
+    rows.entry(address).or_insert_with(|| FunctionRow { name, ..Default::default() })
+}
+
+/// Indirect callsites and checked calls per function, by address of the function
+fn function_rows(report: &SoundnessReport, cwe_checker: &CweCheckerResult) -> BTreeMap<u64, FunctionRow> {
+    let mut rows = BTreeMap::new();
+    for callsite in &cwe_checker.metadata.indirect_call_sites {
+        row_of(&mut rows, *callsite, cwe_checker).callsites += 1;
+    }
+    for call in &report.checked {
+        let row = row_of(&mut rows, call.callsite, cwe_checker);
+        row.checked += 1;
+        row.sound += call.sound as usize;
+    }
+    rows
+}
+
+pub fn render_text_report(report: &SoundnessReport, cwe_checker: &CweCheckerResult, elf: Option<&ElfInfo>) -> String {
+    let mut out = String::new();
+    let checked = report.checked_calls as usize;
+    let sound = report.sound_calls as usize;
+
+    writeln!(out, "Soundness report").unwrap();
+    writeln!(out, "================").unwrap();
+    if let Some(manifest) = &report.manifest {
+        for (key, value) in manifest.summary() {
+            writeln!(out, "{:<21}{}", format!("{}:", key), value).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    writeln!(out, "Functions:           {}", cwe_checker.metadata.functions.len()).unwrap();
+    writeln!(out, "Indirect callsites:  {}", cwe_checker.metadata.indirect_call_sites.len()).unwrap();
+    let aict = cwe_checker.aict().map(|aict| format!("{:.2}", aict)).unwrap_or_else(|| "-".to_string());
+    writeln!(out, "AICT:                {}", aict).unwrap();
+    writeln!(out, "Checked calls:       {}", checked).unwrap();
+    writeln!(out, "Sound calls:         {} ({})", sound, percentage(sound, checked)).unwrap();
+    writeln!(out, "Unsound edges:       {}", report.unsound_edges.len()).unwrap();
+    if report.checked_direct_calls > 0 {
+        writeln!(
+            out,
+            "Direct calls:        {} checked, {} sound ({}), {} missing",
+            report.checked_direct_calls,
+            report.sound_direct_calls,
+            percentage(report.sound_direct_calls as usize, report.checked_direct_calls as usize),
+            report.missing_direct_edges.len()
+        )
+        .unwrap();
+    }
+    writeln!(out, "Result:              {}", if report.unsound_edges.is_empty() { "IS_SOUND" } else { "IS_UNSOUND" }).unwrap();
+
+    let rows = function_rows(report, cwe_checker);
+    if !rows.is_empty() {
+        let width = rows.values().map(|row| row.name.len()).max().unwrap_or(0).max("function".len());
+        writeln!(out).unwrap();
+        writeln!(out, "{:<width$}  {:>9}  {:>7}  {:>5}  {:>6}", "function", "callsites", "checked", "sound", "%").unwrap();
+        for row in rows.values() {
+            writeln!(
+                out,
+                "{:<width$}  {:>9}  {:>7}  {:>5}  {:>6}",
+                row.name,
+                row.callsites,
+                row.checked,
+                row.sound,
+                percentage(row.sound, row.checked)
+            )
+            .unwrap();
+        }
+    }
+
+    let causes = root_cause_counts(report);
+    if !causes.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "Root causes").unwrap();
+        for (cause, count) in &causes {
+            writeln!(out, "  {:<23}{}", cause.to_string(), count).unwrap();
+        }
+    }
+
+    if !report.unsound_edges.is_empty() {
+        let mut edges = report.unsound_edges.iter().collect::<Vec<_>>();
+        edges.sort_by_key(|edge| (edge.callsite, edge.target));
+        writeln!(out).unwrap();
+        writeln!(out, "Unsound edges").unwrap();
+        for edge in edges {
+            let target = match &edge.target_symbol {
+                Some(symbol) => format!("{} (extern)", symbol),
+                None => symbolize(edge.target, cwe_checker, elf),
+            };
+            let tag = match edge.root_cause {
+                Some(cause) => format!(" [{}]", cause),
+                None if edge.callsite_missing => " [callsite missing]".to_string(),
+                None => String::new(),
+            };
+            writeln!(out, "  {} -> {}{}", symbolize(edge.callsite, cwe_checker, elf), target, tag).unwrap();
+            if let Some(source) = &edge.callsite_source {
+                writeln!(out, "      callsite at {}", source).unwrap();
+            }
+            if let Some(source) = &edge.target_source {
+                writeln!(out, "      target at {}", source).unwrap();
+            }
+            if !edge.call_stack.is_empty() {
+                writeln!(out, "      reached via {}", edge.call_stack.join(" -> ")).unwrap();
+            }
+        }
+    }
+
+    if !report.missing_direct_edges.is_empty() {
+        let mut edges = report.missing_direct_edges.clone();
+        edges.sort();
+        writeln!(out).unwrap();
+        writeln!(out, "Missing direct calls").unwrap();
+        for (callsite, target) in edges {
+            writeln!(out, "  {} -> {}", symbolize(callsite, cwe_checker, elf), symbolize(target, cwe_checker, elf)).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cwe_checker::{Call, ExportCallGraph, Function, Metadata},
+        soundness_test::{CheckedCall, UnsoundEdge},
+    };
+
+    #[test]
+    fn test_render_text_report() {
+        let cwe_checker = CweCheckerResult::from_export_call_graph(ExportCallGraph {
+            metadata: Metadata {
+                address_base_offset: 0,
+                indirect_call_sites: vec![0x1010],
+                functions: vec![
+                    Function { name: "main".to_string(), address: 0x1000 },
+                    Function { name: "handler".to_string(), address: 0x1100 },
+                ],
+            },
+            calls: vec![Call { from_instr: 0x1010, to_instr: Some(0x1100), is_indirect: true }],
+        });
+        let mut report = SoundnessReport {
+            checked_calls: 1,
+            sound_calls: 1,
+            aict: cwe_checker.aict(),
+            checked: vec![CheckedCall { callsite: 0x1010, target: 0x1100, target_symbol: None, target_object: None, sound: true }],
+            ..Default::default()
+        };
+        let text = render_text_report(&report, &cwe_checker, None);
+        assert!(text.contains("Result:              IS_SOUND\n"));
+        assert!(text.contains("AICT:                1.00\n"));
+        assert!(text.contains("main              1        1      1  100.0%\n"));
+
+        report.unsound_edges.push(UnsoundEdge {
+            callsite: 0x1010,
+            target: 0x1104,
+            ..Default::default()
+        });
+        let text = render_text_report(&report, &cwe_checker, None);
+        assert!(text.contains("Result:              IS_UNSOUND\n"));
+        assert!(text.contains("  main+0x10 (0x1010) -> handler+0x4 (0x1104)\n"));
+    }
+}
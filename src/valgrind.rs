@@ -1,15 +1,19 @@
-use std::collections::HashSet;
 use std::process::Stdio;
-use std::{collections::HashMap, fmt::Display, fs, path::PathBuf};
+use std::{collections::HashMap, fmt::Display, fs, path::{Path, PathBuf}};
 use std::time::Duration;
 use tokio::time::timeout;
 use tokio::process::Command;
+use log::warn;
 use nix::{
     sys::signal::{kill, Signal::SIGTERM},
     unistd::Pid,
 };
 
-use crate::valgrind_parser::{parse_valgrind_file, InstrCounter, PositionName, ValgrindLine};
+use crate::{
+    error::{Error, Result},
+    source_lines::SourceLocation,
+    valgrind_parser::{parse_valgrind_file, InstrCounter, PositionName, ValgrindLine},
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RealCall {
@@ -18,6 +22,9 @@ pub struct RealCall {
     pub in_fn: i64,
     pub target_fn: i64,
     pub does_jump_object_file: bool,
+    pub call_count: u64,
+    /// Inclusive cost as reported by callgrind, 0 if unknown
+    pub inclusive_cost: u64,
 }
 
 impl Display for RealCall {
@@ -26,12 +33,18 @@ impl Display for RealCall {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct ValgrindResult {
     pub calls: Vec<RealCall>,
+    /// Jumps leaving their function, e.g. tail calls
+    pub jumps: Vec<RealCall>,
     pub valgrind_name_cache: ValgrindNameCache,
     /// From: fn number to base_address
-    pub base_address_mapping: HashMap<u64, u64>
+    pub base_address_mapping: HashMap<u64, u64>,
+    /// Source lines of callsites and function starts, only with callgrind's --dump-line=yes
+    pub source_lines: HashMap<u64, SourceLocation>,
+    /// Object file of targets in other objects, e.g. `/usr/lib/libc.so.6`
+    pub target_objects: HashMap<u64, String>,
 }
 
 impl ValgrindResult {
@@ -43,115 +56,280 @@ impl ValgrindResult {
     }
 }
 
-pub fn analyze_valgrind(output_file: &PathBuf) -> ValgrindResult {
-    let mut base_address_mapping = HashMap::new();
+fn next_line(line: &Option<InstrCounter>, curr_line: u64) -> u64 {
+    match line {
+        Some(InstrCounter::Absolute(abs)) => *abs,
+        Some(InstrCounter::Relative(relative)) => (curr_line as i64 + relative) as u64,
+        Some(InstrCounter::Same()) | None => curr_line,
+    }
+}
+
+pub fn analyze_valgrind(output_file: &PathBuf) -> Result<ValgrindResult> {
+    let content = fs::read_to_string(output_file).map_err(Error::io(output_file))?;
+    analyze_callgrind(&content, output_file)
+}
 
+/// Analyzes the content of a callgrind file, `output_file` is only used for errors
+pub(crate) fn analyze_callgrind(content: &str, output_file: &Path) -> Result<ValgrindResult> {
+    let mut base_address_mapping = HashMap::new();
+    let mut source_lines = HashMap::new();
+    let mut target_objects = HashMap::new();
 
-    let content = fs::read_to_string(output_file).unwrap();
-    let valgrind_file = parse_valgrind_file(&content);
+    let (_, valgrind_lines) = parse_valgrind_file(content).map_err(|err| Error::Parse {
+        path: output_file.to_path_buf(),
+        message: format!("not a callgrind file: {}", err),
+    })?;
     let mut valgrind_name_cache = ValgrindNameCache::new();
     let mut curr_index: u64 = 0;
     let mut curr_fn_index = 0;
     let mut curr_fn_base_address_set = false;
+    // Source files share their own name compression
+    let mut file_name_cache = ValgrindNameCache::new();
+    let mut object_name_cache = ValgrindNameCache::new();
+    let mut has_line_positions = false;
+    // The file of the function, and the file of the current lines which differs in inlined code
+    let mut function_file: Option<String> = None;
+    let mut curr_file: Option<String> = None;
+    let mut curr_line: u64 = 0;
 
     let mut calls = vec![];
-    for line in valgrind_file.unwrap().1 {
+    let mut jumps = vec![];
+    for line in valgrind_lines {
         match line {
             ValgrindLine::FnLine(fn_) => {
-                valgrind_name_cache.add(&fn_);
-                curr_fn_index = fn_.number.unwrap();
+                curr_fn_index = valgrind_name_cache.resolve(&fn_).ok_or_else(|| unnamed(output_file, "fn="))?;
                 curr_fn_base_address_set = false;
+                curr_file = function_file.clone();
+            }
+            ValgrindLine::Positions(positions) => {
+                has_line_positions = positions.iter().any(|position| position == "line");
+            }
+            ValgrindLine::FileLine(file) => {
+                file_name_cache.add(&file);
+                function_file = file.number.and_then(|number| file_name_cache.name_cache.get(&number).cloned());
+                curr_file = function_file.clone();
+            }
+            ValgrindLine::InlineFileLine(file) => {
+                file_name_cache.add(&file);
+                curr_file = file.number.and_then(|number| file_name_cache.name_cache.get(&number).cloned());
+            }
+            ValgrindLine::ObjectLine(object) => {
+                object_name_cache.add(&object);
             }
             ValgrindLine::CfnLine(cfn) => {
-                valgrind_name_cache.add(&cfn.position_name);
+                let target_fn = valgrind_name_cache.resolve(&cfn.position_name).ok_or_else(|| unnamed(output_file, "cfn="))?;
+                if let Some(object) = &cfn.next_object_file {
+                    object_name_cache.add(object);
+                }
+                if let Some(target_file) = &cfn.target_file {
+                    file_name_cache.add(target_file);
+                }
+                if has_line_positions {
+                    curr_line = next_line(&cfn.from_line, curr_line);
+                }
                 calls.push(RealCall {
                     from_instr: match cfn.from_instr {
-                        InstrCounter::Absolute(abs) => {curr_index = abs; abs as u64},
+                        InstrCounter::Absolute(abs) => {curr_index = abs; abs},
                         InstrCounter::Relative(relative) => {curr_index = (curr_index as i64  + relative) as u64; curr_index},
-                        InstrCounter::Same() => curr_index as u64
+                        InstrCounter::Same() => curr_index
                     },
                     to_instr: match cfn.target_instr {
-                        InstrCounter::Absolute(abs) => {abs as u64},
+                        InstrCounter::Absolute(abs) => {abs},
                         InstrCounter::Relative(relative) => (curr_index as i64 + relative) as u64,
-                        InstrCounter::Same() => curr_index as u64
+                        InstrCounter::Same() => curr_index
                     },
                     in_fn: curr_fn_index as i64,
-                    target_fn: cfn.position_name.number.unwrap().try_into().unwrap(),
-                    does_jump_object_file: cfn.next_object_file.is_some()
+                    target_fn: target_fn as i64,
+                    does_jump_object_file: cfn.next_object_file.is_some(),
+                    call_count: cfn.call_count,
+                    inclusive_cost: cfn.inclusive_cost,
                 });
+                if let (true, Some(file)) = (has_line_positions, &curr_file) {
+                    source_lines.insert(curr_index, SourceLocation { file: file.clone(), line: curr_line });
+                }
+                if let Some(object) = cfn.next_object_file.and_then(|object| object.number) {
+                    let call = calls.last().unwrap();
+                    target_objects.insert(call.to_instr, object_name_cache.get(object));
+                }
             },
-            ValgrindLine::InstrCounter(InstrCounter::Absolute(new_index)) => {
-                curr_index = new_index;
-                if !curr_fn_base_address_set {
-                    curr_fn_base_address_set = true;
-                    base_address_mapping.insert(curr_fn_index, new_index);
+            ValgrindLine::JumpLine(jump) => {
+                let from_instr = match jump.from_instr {
+                    InstrCounter::Absolute(abs) => {curr_index = abs; abs},
+                    InstrCounter::Relative(relative) => {curr_index = (curr_index as i64  + relative) as u64; curr_index},
+                    InstrCounter::Same() => curr_index
+                };
+                if has_line_positions {
+                    curr_line = next_line(&jump.from_line, curr_line);
+                }
+                let Some(target_fn) = jump.target_fn else {
+                    continue;
+                };
+                let target_fn = valgrind_name_cache.resolve(&target_fn).ok_or_else(|| unnamed(output_file, "jfn="))?;
+                if target_fn == curr_fn_index {
+                    continue;
                 }
+                jumps.push(RealCall {
+                    from_instr,
+                    to_instr: match jump.target_instr {
+                        InstrCounter::Absolute(abs) => {abs},
+                        InstrCounter::Relative(relative) => (curr_index as i64 + relative) as u64,
+                        InstrCounter::Same() => curr_index
+                    },
+                    in_fn: curr_fn_index as i64,
+                    target_fn: target_fn as i64,
+                    does_jump_object_file: false,
+                    call_count: 0,
+                    inclusive_cost: 0,
+                });
             },
-            ValgrindLine::InstrCounter(InstrCounter::Relative(relative)) => {
-                curr_index = (curr_index as i64  + relative) as u64;
+            ValgrindLine::InstrCounter(instr, line) => {
+                if has_line_positions {
+                    curr_line = next_line(&line, curr_line);
+                }
+                match instr {
+                    InstrCounter::Absolute(new_index) => curr_index = new_index,
+                    InstrCounter::Relative(relative) => curr_index = (curr_index as i64  + relative) as u64,
+                    InstrCounter::Same() => continue,
+                }
                 if !curr_fn_base_address_set {
                     curr_fn_base_address_set = true;
                     base_address_mapping.insert(curr_fn_index, curr_index);
+                    if let (true, Some(file)) = (has_line_positions, &curr_file) {
+                        source_lines.insert(curr_index, SourceLocation { file: file.clone(), line: curr_line });
+                    }
                 }
             },
-            _ => ()
         }
 
     }
 
-    //for call in &calls {
-    //    println!("{} -> {} @ {}", call.from_instr, call.to_instr, valgrind_name_cache.get(call.in_fn.try_into().unwrap()));
-    //}
-    ValgrindResult {
-        calls, valgrind_name_cache, base_address_mapping
+    Ok(ValgrindResult {
+        calls, jumps, valgrind_name_cache, base_address_mapping, source_lines, target_objects
+    })
+
+}
+
+fn unnamed(output_file: &Path, line: &str) -> Error {
+    Error::Parse {
+        path: output_file.to_path_buf(),
+        message: format!("{} without number or name", line),
     }
+}
+
+pub const VALGRIND_ARGS: [&str; 4] = ["--tool=callgrind", "--dump-instr=yes", "--dump-line=yes", "--collect-jumps=yes"];
 
+/// Version of the installed valgrind, None if it is not installed
+pub fn valgrind_version() -> Option<String> {
+    let output = std::process::Command::new("valgrind").arg("--version").output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|version| !version.is_empty())
 }
 
-pub async fn run_valgrind(binary: &PathBuf, output_file: &PathBuf) -> ValgrindResult {
-    let output_file_arg = format!("--callgrind-out-file={}", output_file.to_str().unwrap());
+/// The calls observed until valgrind exited or was stopped, and whether it reached the timeout
+pub async fn run_valgrind(binary: &Path, output_file: &PathBuf) -> Result<(ValgrindResult, bool)> {
+    let output_file_arg = format!("--callgrind-out-file={}", output_file.display());
     let mut child = Command::new("valgrind")
-        .args(["--tool=callgrind", "--dump-instr=yes", &output_file_arg, "--collect-jumps=yes", binary.to_str().unwrap()])
+        .args(VALGRIND_ARGS)
+        .arg(&output_file_arg)
+        .arg(binary)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .unwrap();
+        .map_err(|err| Error::tool("valgrind", err))?;
 
-    // Timeout after 20 seconds
+    // Timeout after 10 seconds
     let reached_timeout = timeout(Duration::from_secs(10),child.wait()).await.is_err();
     if reached_timeout {
-        if let Some(pid) = child.id() {
+        // The pid of a running child fits into pid_t
+        if let Some(pid) = child.id().and_then(|pid| i32::try_from(pid).ok()) {
             // If the child hasn't already completed, send a SIGTERM.
-            if let Err(e) = kill(Pid::from_raw(pid.try_into().expect("Invalid PID")), SIGTERM) {
-                eprintln!("Failed to forward SIGTERM to child process: {}", e);
+            if let Err(e) = kill(Pid::from_raw(pid), SIGTERM) {
+                warn!("Failed to forward SIGTERM to child process: {}", e);
             }
         }
         // Wait to get the child's exit code.
         let _ignore = child.wait().await;
+        warn!("valgrind reached the timeout, the trace of {} is incomplete", binary.display());
     }
 
-    analyze_valgrind(output_file)
+    Ok((analyze_valgrind(output_file)?, reached_timeout))
 }
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
 pub struct ValgrindNameCache {
-    pub name_cache: HashMap::<u64, String>
+    pub name_cache: HashMap::<u64, String>,
+    /// Numbers given to names without compression, i.e. `--compress-strings=no`
+    pub uncompressed: HashMap<String, u64>,
 }
 
+/// Above the numbers callgrind uses for compressed names
+const FIRST_UNCOMPRESSED_NUMBER: u64 = 1 << 32;
+
 impl ValgrindNameCache {
     pub fn new() -> ValgrindNameCache {
         ValgrindNameCache {
-            name_cache: HashMap::new()
+            name_cache: HashMap::new(),
+            uncompressed: HashMap::new(),
+        }
+    }
+
+    /// Adds the name and returns its number, names without a number get their own numbers
+    fn resolve(&mut self, pos: &PositionName) -> Option<u64> {
+        match pos {
+            PositionName { number: Some(number), .. } => {
+                self.add(pos);
+                Some(*number)
+            }
+            PositionName { number: None, trailing: Some(name) } => {
+                if let Some(number) = self.uncompressed.get(name) {
+                    return Some(*number);
+                }
+                let number = FIRST_UNCOMPRESSED_NUMBER + self.uncompressed.len() as u64;
+                self.uncompressed.insert(name.clone(), number);
+                self.name_cache.insert(number, name.clone());
+                Some(number)
+            }
+            PositionName { number: None, trailing: None } => None,
         }
     }
 
     fn add(&mut self, pos: &PositionName) {
         if let PositionName {number: Some(number), trailing: Some(name)} = pos {
-            self.name_cache.insert(number.clone(), name.to_string());
+            self.name_cache.insert(*number, name.to_string());
         }
     }
 
-    fn get(&self, index: u64) -> String {
+    pub fn get(&self, index: u64) -> String {
         self.name_cache.get(&index).unwrap_or(&index.to_string()).to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jumps() {
+        // A jump within main, a tail call to foo and a jump back into the caller from foo
+        let input = "fn=(1) main\n0x401100 1\njump=1 0x401110\n+4\njfn=(2) foo\njcnd=1/3 0x401200\n+8\nfn=(2)\n0x401200 1\njfn=(1)\njump=1 0x401108\n+4\n";
+        let result = analyze_callgrind(input, Path::new("callgrind.out")).unwrap();
+        let jumps = result.jumps.iter().map(|jump| (jump.from_instr, jump.to_instr, jump.in_fn, jump.target_fn)).collect::<Vec<_>>();
+        assert_eq!(jumps, [(0x40110c, 0x401200, 1, 2), (0x401204, 0x401108, 2, 1)]);
+        assert_eq!(result.get_target_function_of_call(&result.jumps[0]), "foo");
+        assert_eq!(result.base_address_mapping, HashMap::from([(1, 0x401100), (2, 0x401200)]));
+    }
+
+    #[test]
+    fn test_uncompressed_names() {
+        // --compress-strings=no repeats the names instead of numbering them
+        let input = "fn=main\n0x401100 1\ncfn=foo\ncalls=1 0x401200\n+4 10\njfn=foo\njump=1 0x401200\n+8\nfn=foo\n0x401200 1\n";
+        let result = analyze_callgrind(input, Path::new("callgrind.out")).unwrap();
+        assert_eq!(result.calls.len(), 1);
+        assert_eq!(result.get_function_of_call(&result.calls[0]), "main");
+        assert_eq!(result.get_target_function_of_call(&result.calls[0]), "foo");
+        assert_eq!(result.jumps[0].target_fn, result.calls[0].target_fn);
+        assert_eq!(result.base_address_mapping.len(), 2);
+
+        let err = analyze_callgrind("fn=\n0x401100 1\n", Path::new("callgrind.out")).unwrap_err();
+        assert!(matches!(err, Error::Parse { .. }));
+    }
+}
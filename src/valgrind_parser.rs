@@ -1,4 +1,4 @@
-use nom::{bytes::complete::{tag, take_until}, character::complete::{u64 as parseu64, line_ending, space0, space1}, combinator::{map, opt}, multi::many0, number::complete::hex_u32, sequence::{preceded, terminated}, IResult, Parser};
+use nom::{bytes::complete::{tag, take_until}, character::complete::{u64 as parseu64, line_ending, space0, space1}, combinator::{map, opt}, number::complete::hex_u32, sequence::{preceded, terminated}, IResult, Parser};
 use nom::sequence::delimited;
 
 
@@ -26,31 +26,99 @@ fn parse_fn_line(input: &str) -> IResult<&str, PositionName> {
     delimited(tag("fn="), parse_position_name, line_ending).parse(input)
 }
 
+/// `fl=` sets the source file of the function
+fn parse_file_line(input: &str) -> IResult<&str, PositionName> {
+    delimited(tag("fl="), parse_position_name, line_ending).parse(input)
+}
+
+/// `fi=`/`fe=` set the file of inlined code, until the next `fi=`/`fe=` or `fn=`
+fn parse_inline_file_line(input: &str) -> IResult<&str, PositionName> {
+    let file_tag = nom::branch::alt((tag("fi="), tag("fe=")));
+    delimited(file_tag, parse_position_name, line_ending).parse(input)
+}
+
+/// `ob=` sets the object file of the following functions
+fn parse_object_line(input: &str) -> IResult<&str, PositionName> {
+    delimited(tag("ob="), parse_position_name, line_ending).parse(input)
+}
+
+/// `positions: instr line` with --dump-instr=yes --dump-line=yes
+fn parse_positions(input: &str) -> IResult<&str, Vec<String>> {
+    let (input, positions) = preceded(tag("positions:"), parse_till_eol).parse(input)?;
+    Ok((input, positions.split_whitespace().map(str::to_string).collect()))
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CfnLine {
     pub position_name: PositionName,
     pub target_instr: InstrCounter,
     pub from_instr: InstrCounter,
+    /// Source line of the call, only meaningful if the positions contain lines
+    pub from_line: Option<InstrCounter>,
     /// If some, the call jumps between object files
     pub next_object_file: Option<PositionName>,
+    /// Source file of the callee, names are shared with fl=
+    pub target_file: Option<PositionName>,
+    pub call_count: u64,
+    /// Cost of the call including all costs of the callee
+    pub inclusive_cost: u64,
 }
 
 /// looks like this
 /// cfn=(122) _dl_map_object
 /// calls=1 0x7b20 0 
 /// * 0 2935
-fn parse_cfn(input: &str) -> IResult<&str, CfnLine> {
+fn parse_cfn(input: &str, with_line: bool) -> IResult<&str, CfnLine> {
     let (input, next_object_file) = opt(delimited(tag("cob="), parse_position_name, line_ending)).parse(input)?;
-    let (input, _next_object_file_file) = opt(delimited(tag("cfi="), parse_position_name, line_ending)).parse(input)?;
+    let (input, target_file) = opt(delimited(tag("cfi="), parse_position_name, line_ending)).parse(input)?;
     let (input, position_name) = delimited(tag("cfn="), parse_position_name, line_ending).parse(input)?;
-    let (input, target_instr) = parse_calls_line(input)?;
-    let (input, from_instr) = parse_costline(input)?;
+    let (input, (call_count, target_instr)) = parse_calls_line(input)?;
+    let (input, (from_instr, from_line, inclusive_cost)) = parse_costline_with_cost(input, with_line)?;
 
     Ok((input, CfnLine {
         position_name,
         target_instr,
         from_instr,
-        next_object_file
+        from_line,
+        next_object_file,
+        target_file,
+        call_count,
+        inclusive_cost,
+    }))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JumpLine {
+    /// Only set if the jump leaves the current function
+    pub target_fn: Option<PositionName>,
+    pub target_instr: InstrCounter,
+    pub from_instr: InstrCounter,
+    pub from_line: Option<InstrCounter>,
+}
+
+/// Only emitted with --collect-jumps=yes, looks like this
+/// jfn=(12) foo
+/// jump=1 0x4011a0
+/// +5
+/// or for conditional jumps
+/// jcnd=1/3 0x4011a0
+/// +5
+fn parse_jump(input: &str, with_line: bool) -> IResult<&str, JumpLine> {
+    let (input, _target_file) = opt(delimited(tag("jfi="), parse_position_name, line_ending)).parse(input)?;
+    let (input, target_fn) = opt(delimited(tag("jfn="), parse_position_name, line_ending)).parse(input)?;
+    let (input, _) = nom::branch::alt((
+        map((tag("jump="), parseu64), |_| ()),
+        map((tag("jcnd="), parseu64, tag("/"), parseu64), |_| ()),
+    )).parse(input)?;
+    let (input, _) = space1(input)?;
+    let (input, target_instr) = parse_costline(input)?;
+    let (input, (from_instr, from_line)) = parse_costline_with_line(input, with_line)?;
+
+    Ok((input, JumpLine {
+        target_fn,
+        target_instr,
+        from_instr,
+        from_line,
     }))
 }
 
@@ -58,30 +126,63 @@ fn parse_cfn(input: &str) -> IResult<&str, CfnLine> {
 pub enum ValgrindLine {
     FnLine(PositionName),
     CfnLine(CfnLine),
-    InstrCounter(InstrCounter)
+    JumpLine(JumpLine),
+    FileLine(PositionName),
+    InlineFileLine(PositionName),
+    ObjectLine(PositionName),
+    Positions(Vec<String>),
+    /// Instruction and, if the positions contain lines, the source line
+    InstrCounter(InstrCounter, Option<InstrCounter>)
 } 
 
-fn parse_line(input: &str) -> IResult<&str, Option<ValgrindLine>> {
+fn parse_line(input: &str, with_line: bool) -> IResult<&str, Option<ValgrindLine>> {
     nom::branch::alt((
-        map(parse_costline, |val| Some(ValgrindLine::InstrCounter(val))),
+        map(|input| parse_costline_with_line(input, with_line), |(instr, line)| Some(ValgrindLine::InstrCounter(instr, line))),
         map(parse_fn_line, |val| Some(ValgrindLine::FnLine(val))),
-        map(parse_cfn, |val| Some(ValgrindLine::CfnLine(val))),
+        map(|input| parse_cfn(input, with_line), |val| Some(ValgrindLine::CfnLine(val))),
+        map(|input| parse_jump(input, with_line), |val| Some(ValgrindLine::JumpLine(val))),
+        map(parse_file_line, |val| Some(ValgrindLine::FileLine(val))),
+        map(parse_inline_file_line, |val| Some(ValgrindLine::InlineFileLine(val))),
+        map(parse_object_line, |val| Some(ValgrindLine::ObjectLine(val))),
+        map(parse_positions, |val| Some(ValgrindLine::Positions(val))),
         map(parse_till_eol, |_| None)
     )).parse(input)
 }
 
-pub fn parse_valgrind_file(input: &str) -> IResult<&str, Vec<ValgrindLine>> {
-    let (input, output) = many0(parse_line).parse(input)?;
-    Ok((input, output.iter().filter_map(|line| line.clone()).collect::<Vec<ValgrindLine>>()))
+/// Stops at the first line that can not be parsed, like many0
+pub fn parse_valgrind_file(mut input: &str) -> IResult<&str, Vec<ValgrindLine>> {
+    // Whether the positions contain a line number, it comes before the costs
+    let mut with_line = false;
+    let mut lines = vec![];
+    loop {
+        let (rest, line) = match parse_line(input, with_line) {
+            Ok(result) => result,
+            Err(nom::Err::Error(_)) => break,
+            Err(err) => return Err(err),
+        };
+        if rest.len() == input.len() {
+            break;
+        }
+        input = rest;
+        if let Some(ValgrindLine::Positions(positions)) = &line {
+            with_line = positions.iter().any(|position| position == "line");
+        }
+        lines.extend(line);
+    }
+    Ok((input, lines))
 }
 
+/// `(1) name`, `(1)` or, with `--compress-strings=no`, only `name`
 pub fn parse_position_name(input: &str) -> IResult<&str, PositionName> {
     let (input, number) = opt(parse_paren_number).parse(input)?;
-    let (input, trailing) = opt(
-        preceded(space1, parse_no_newline_chars)
-    ).parse(input)?;
+    let (input, trailing) = if number.is_some() {
+        opt(preceded(space1, parse_no_newline_chars)).parse(input)?
+    } else {
+        opt(parse_no_newline_chars).parse(input)?
+    };
+    let trailing = trailing.filter(|name| !name.is_empty());
 
-    Ok((input, PositionName { number, trailing: trailing.and_then(|val| Some(val.to_string())) }))
+    Ok((input, PositionName { number, trailing: trailing.map(|val| val.to_string()) }))
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -105,8 +206,20 @@ fn parse_subposition(input: &str) -> IResult<&str, InstrCounter> {
     )).parse(input)
 }
 
-fn parse_calls_line(input: &str) -> IResult<&str, InstrCounter> {
-    preceded((tag("calls="), parseu64, space1), parse_costline).parse(input)
+/// Line numbers are decimal instead of hex
+fn parse_line_subposition(input: &str) -> IResult<&str, InstrCounter> {
+    nom::branch::alt((
+        map(preceded(tag("+"), parseu64), |val| InstrCounter::Relative(val as i64)),
+        map(preceded(tag("-"), parseu64), |val| InstrCounter::Relative(-(val as i64))),
+        map(parseu64, InstrCounter::Absolute),
+        map(tag("*"), |_| InstrCounter::Same()),
+    )).parse(input)
+}
+
+fn parse_calls_line(input: &str) -> IResult<&str, (u64, InstrCounter)> {
+    let (input, (_, call_count, _)) = (tag("calls="), parseu64, space1).parse(input)?;
+    let (input, target_instr) = parse_costline(input)?;
+    Ok((input, (call_count, target_instr)))
 }
 
 fn parse_costline(input: &str) -> IResult<&str, InstrCounter> {
@@ -116,13 +229,100 @@ fn parse_costline(input: &str) -> IResult<&str, InstrCounter> {
     Ok((input, instr))
 }
 
+/// The token after the instruction is the line with --dump-line=yes, otherwise it is a cost
+fn parse_costline_with_line(input: &str, with_line: bool) -> IResult<&str, (InstrCounter, Option<InstrCounter>)> {
+    let (input, instr) = parse_subposition(input)?;
+    let (input, _) = space0(input)?;
+    let (input, line) = if with_line { opt(parse_line_subposition).parse(input)? } else { (input, None) };
+    let (input, _) = parse_till_eol(input)?;
+    Ok((input, (instr, line)))
+}
+
+/// The first cost follows the position (and the line number with --dump-line=yes), it is Ir as
+/// long as the events start with it
+fn parse_costline_with_cost(input: &str, with_line: bool) -> IResult<&str, (InstrCounter, Option<InstrCounter>, u64)> {
+    let (input, instr) = parse_subposition(input)?;
+    let (input, _) = space0(input)?;
+    let (input, costs) = parse_till_eol(input)?;
+    let mut tokens = costs.split_whitespace();
+    let line = if with_line {
+        tokens.next().and_then(|line| parse_line_subposition(line).ok()).map(|(_, line)| line)
+    } else {
+        None
+    };
+    let cost = tokens.next().and_then(|cost| cost.parse().ok()).unwrap_or(0);
+    Ok((input, (instr, line, cost)))
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use std::path::Path;
+
+    use crate::valgrind::analyze_callgrind;
+
+    #[test]
+    fn test_cfn_without_tag() {
+        assert!(parse_cfn("(23) fooo", false).is_err());
+    }
+
+    #[test]
+    fn test_cfn_inclusive_cost() {
+        let (_, cfn) = parse_cfn("cfn=(122) _dl_map_object\ncalls=2 0x7b20 0\n* 0 2935\n", true).unwrap();
+        assert_eq!(cfn.call_count, 2);
+        assert_eq!(cfn.target_instr, InstrCounter::Absolute(0x7b20));
+        assert_eq!(cfn.from_instr, InstrCounter::Same());
+        assert_eq!(cfn.inclusive_cost, 2935);
+    }
+
+    #[test]
+    fn test_line_positions() {
+        let input = "positions: instr line\nfl=(1) /src/fp.c\nfn=(2) main\n0x401143 12 3\n+4 +1 2\ncfn=(3) a\ncalls=1 0x401126 3\n+82 * 10\n";
+        let (rest, lines) = parse_valgrind_file(input).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(lines[0], ValgrindLine::Positions(vec!["instr".to_string(), "line".to_string()]));
+        assert_eq!(lines[1], ValgrindLine::FileLine(PositionName { number: Some(1), trailing: Some("/src/fp.c".to_string()) }));
+        assert_eq!(lines[3], ValgrindLine::InstrCounter(InstrCounter::Absolute(0x401143), Some(InstrCounter::Absolute(12))));
+        assert_eq!(lines[4], ValgrindLine::InstrCounter(InstrCounter::Relative(4), Some(InstrCounter::Relative(1))));
+        let ValgrindLine::CfnLine(cfn) = &lines[5] else { panic!("Expected a call") };
+        assert_eq!(cfn.from_line, Some(InstrCounter::Same()));
+        assert_eq!(cfn.inclusive_cost, 10);
+
+        // Inlined code only changes the file until the next function
+        let input = "positions: instr line\nfl=(1) /src/fp.c\nfn=(2) main\n0x401143 12 3\nfi=(2) /src/inline.h\n0x401150 5 1\ncfn=(3) a\ncalls=1 0x401126 3\n* * 10\nfn=(4) b\n0x401160 30 1\ncfn=(3)\ncalls=1 0x401126 3\n+4 +1 10\n";
+        let result = analyze_callgrind(input, Path::new("callgrind.out")).unwrap();
+        let file_of = |addr: u64| result.source_lines[&addr].file.clone();
+        assert_eq!(file_of(0x401143), "/src/fp.c");
+        assert_eq!(file_of(0x401150), "/src/inline.h");
+        assert_eq!(file_of(0x401160), "/src/fp.c");
+        assert_eq!(file_of(0x401164), "/src/fp.c");
+        assert_eq!(result.source_lines[&0x401164].line, 31);
+    }
+
+    #[test]
+    fn test_multiple_events() {
+        let input = "positions: instr line\nevents: Ir sysCount sysTime\nfn=(2) main\n0x401143 12 3 1 20\ncfn=(3) a\ncalls=1 0x401126 3\n+82 * 10 2 500\n";
+        let (_, lines) = parse_valgrind_file(input).unwrap();
+        let ValgrindLine::CfnLine(cfn) = &lines[3] else { panic!("Expected a call") };
+        assert_eq!(cfn.from_line, Some(InstrCounter::Same()));
+        assert_eq!(cfn.inclusive_cost, 10);
+
+        // Without lines the first cost directly follows the instruction
+        let input = "positions: instr\nfn=(2) main\n0x401143 3 1\ncfn=(3) a\ncalls=1 0x401126\n+82 10 2\n";
+        let (_, lines) = parse_valgrind_file(input).unwrap();
+        assert_eq!(lines[2], ValgrindLine::InstrCounter(InstrCounter::Absolute(0x401143), None));
+        let ValgrindLine::CfnLine(cfn) = &lines[3] else { panic!("Expected a call") };
+        assert_eq!(cfn.from_line, None);
+        assert_eq!(cfn.inclusive_cost, 10);
+    }
 
     #[test]
-    fn test_add() {
-        println!("{:#?}", parse_cfn("(23) fooo"));
+    fn test_jump_to_other_function() {
+        let (rest, jump) = parse_jump("jfn=(4) foo\njcnd=1/3 0x4011a0\n+5\n", false).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(jump.target_fn, Some(PositionName { number: Some(4), trailing: Some("foo".to_string()) }));
+        assert_eq!(jump.target_instr, InstrCounter::Absolute(0x4011a0));
+        assert_eq!(jump.from_instr, InstrCounter::Relative(5));
     }
 }